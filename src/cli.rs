@@ -0,0 +1,286 @@
+// retronym (C) copyright Kroc Camen 2017, 2018
+// BSD 2-clause licence; see LICENSE.TXT
+
+//! The commands, other than the REPL:
+//!
+//! ```
+//! retronym asm <file> [-o <output>] [-t <target>] [-m <map>] [-I <dir>]... [-g] [-u] [-r] [-e <option>]...
+//! retronym doc <file> [-I <dir>]...
+//! ```
+//!
+//! The target given with `-t` is a memory map bundled with Retronym, e.g.
+//! "sms"; a memory map given with `-m` adds to it. Once linked, `-g` lists
+//! the space left unused in the output, and `-u` how full each area of
+//! memory, and each bank, is. `-r` enables relaxation, e.g. of `jp` to
+//! `jr` where near enough, and lists the sites relaxed. `-e` enables an
+//! option from the start of assembly, as `\enable` would, e.g.
+//! `-e z80-undocumented`.
+//!
+//! The modules a file imports are found in the file's own directory, then
+//! in the directories given with `-I`, in order, and then amongst the
+//! modules bundled with Retronym; so a directory can override a bundled
+//! module with its own of the same name.
+
+use retronym::assembler::Assembler;
+use retronym::error::ParseError;
+use retronym::linker::{self, Image};
+use retronym::map::PRESETS;
+use retronym::module::SearchPath;
+use retronym::source::FileId;
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+const USAGE: &str = "usage:
+    retronym asm <file> [-o <output>] [-t <target>] [-m <map>] [-I <dir>]... [-g] [-u] [-r] [-e <option>]...
+    retronym doc <file> [-I <dir>]...";
+
+/// The arguments given to a command.
+#[derive(Default)]
+struct Args {
+    /// The source file.
+    file: PathBuf,
+    /// Where to write the output, given with `-o`.
+    output: Option<PathBuf>,
+    /// The memory map bundled with Retronym to link by, given with `-t`.
+    target: Option<String>,
+    /// The memory map to link by, given with `-m`.
+    map: Option<PathBuf>,
+    /// The directories to search for modules, given with `-I`.
+    dirs: Vec<PathBuf>,
+    /// List the space left unused in the output, given with `-g`.
+    gaps: bool,
+    /// List how full each area of memory is, given with `-u`.
+    fills: bool,
+    /// Enable relaxation and list the sites relaxed, given with `-r`.
+    relax: bool,
+    /// The options to enable, given with `-e`.
+    options: Vec<String>,
+}
+
+/// Assemble and link a source file, writing the packed binary data,
+/// e.g. `retronym asm game.rym -o game.sms -t sms`. Without `-o`,
+/// the output is written beside the source file, e.g. "game.bin".
+///
+pub fn asm(args: &[String]) {
+    //--------------------------------------------------------------------------
+    let args = parse(args);
+    let mut asm = Assembler::default();
+    let file = load(&mut asm, &args);
+    if args.relax {
+        asm.enable(linker::OPTION);
+    }
+    for option in args.options.iter() {
+        asm.enable(option);
+    }
+
+    if let Some(e) = asm.assemble_file(file) {
+        fail(describe(&asm, &e));
+    }
+    for w in asm.warnings().iter() {
+        eprintln!("! WARNING: {}", describe(&asm, w));
+    }
+
+    if let Some(ref target) = args.target {
+        let file = asm.add_preset(target).unwrap_or_else(|| {
+            let names: Vec<&str> = PRESETS.iter().map(|(n, _)| *n).collect();
+            fail(format!(
+                "unknown target `{}`; one of: {}",
+                target,
+                names.join(", ")
+            ))
+        });
+        if let Some(e) = asm.add_map(file) {
+            fail(describe(&asm, &e));
+        }
+    }
+    if let Some(ref path) = args.map {
+        let file = asm.load(path).unwrap_or_else(|e| {
+            fail(format!("cannot read `{}`: {}", path.display(), e))
+        });
+        if let Some(e) = asm.add_map(file) {
+            fail(describe(&asm, &e));
+        }
+    }
+    let image = asm.link().unwrap_or_else(|e| fail(describe(&asm, &e)));
+    let output = match args.output {
+        Some(ref output) => output.clone(),
+        None => args.file.with_extension("bin"),
+    };
+    if let Err(e) = fs::write(&output, image.data()) {
+        fail(format!("cannot write `{}`: {}", output.display(), e));
+    }
+    if args.gaps {
+        gaps(&image);
+    }
+    if args.fills {
+        fills(&image, args.target.is_some() || args.map.is_some());
+    }
+    if args.relax {
+        relaxations(&asm, &image);
+    }
+}
+
+/// Print the space left unused in the output of a link, e.g.
+/// "ROM: $7000-$7FEF at $7000, 4080 byte(s)", with the total.
+///
+fn gaps(image: &Image) {
+    //--------------------------------------------------------------------------
+    for gap in image.gaps() {
+        let area = match gap.bank {
+            Some(bank) => format!("{} bank {}", gap.area, bank),
+            None => gap.area.clone(),
+        };
+        println!(
+            "{}: ${:04X}-${:04X} at ${:X}, {} byte(s)",
+            area,
+            gap.address,
+            gap.address + gap.size - 1,
+            gap.load,
+            gap.size
+        );
+    }
+    let total: usize = image.gaps().iter().map(|g| g.size).sum();
+    println!("{} byte(s) unused", total);
+}
+
+/// Print how full each area of memory, and each bank, is once linked,
+/// e.g. "SLOT2 bank 5: 12288 of 16384 byte(s) used (75%)". Without a
+/// memory map, memory has no end, so only the bytes used are given.
+///
+fn fills(image: &Image, is_mapped: bool) {
+    //--------------------------------------------------------------------------
+    for fill in image.fills() {
+        let area = match fill.bank {
+            Some(bank) => format!("{} bank {}", fill.area, bank),
+            None => fill.area.clone(),
+        };
+        match fill.size {
+            size if is_mapped && size > 0 => println!(
+                "{}: {} of {} byte(s) used ({}%)",
+                area,
+                fill.used,
+                size,
+                fill.used * 100 / size
+            ),
+            _ => println!("{}: {} byte(s) used", area, fill.used),
+        }
+    }
+}
+
+/// Print the sites relaxed to another form once linked, e.g.
+/// "game.rym at 12:9: $0150, 3 byte(s) to 2", with the count.
+///
+fn relaxations(asm: &Assembler, image: &Image) {
+    //--------------------------------------------------------------------------
+    for relaxation in image.relaxations() {
+        let site = match relaxation.location {
+            Some(l) => format!("{} at {}", asm.sources().name(l.span.file), l),
+            None => "?".to_string(),
+        };
+        println!(
+            "{}: ${:04X}, {} byte(s) to {}",
+            site, relaxation.address, relaxation.from, relaxation.to
+        );
+    }
+    println!("{} site(s) relaxed", image.relaxations().len());
+}
+
+/// Assemble a module and print a Markdown reference of its exports,
+/// e.g. `retronym doc modules/z80.rym > z80.md`.
+///
+pub fn doc(args: &[String]) {
+    //--------------------------------------------------------------------------
+    let args = parse(args);
+    let mut asm = Assembler::default();
+    let file = load(&mut asm, &args);
+
+    if let Some(e) = asm.assemble_file(file) {
+        fail(describe(&asm, &e));
+    }
+
+    // the module is titled by its file name, e.g. "z80"
+    let title = args
+        .file
+        .file_stem()
+        .map_or("".into(), |s| s.to_string_lossy());
+    let (object, modules) = asm.objects().split_last().unwrap();
+    print!("{}", retronym::doc::markdown(&title, object, modules));
+}
+
+/// Read the arguments of a command; the source file, followed by
+/// any options. Exits, with the usage, if they are not understood.
+///
+fn parse(args: &[String]) -> Args {
+    //--------------------------------------------------------------------------
+    let mut parsed = Args::default();
+    let mut iter = args.iter();
+    match iter.next() {
+        Some(file) if !file.starts_with('-') => parsed.file = file.into(),
+        _ => fail(USAGE),
+    }
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            // options without a value
+            "-g" => parsed.gaps = true,
+            "-u" => parsed.fills = true,
+            "-r" => parsed.relax = true,
+            _ => match (arg.as_str(), iter.next()) {
+                ("-o", Some(output)) => parsed.output = Some(output.into()),
+                ("-t", Some(target)) => parsed.target = Some(target.clone()),
+                ("-m", Some(map)) => parsed.map = Some(map.into()),
+                ("-I", Some(dir)) => parsed.dirs.push(dir.into()),
+                ("-e", Some(option)) => parsed.options.push(option.clone()),
+                _ => fail(USAGE),
+            },
+        }
+    }
+    parsed
+}
+
+/// Read the source file, finding modules in the file's own directory
+/// and then those given. Exits, with the error, if they cannot be read.
+///
+fn load(asm: &mut Assembler, args: &Args) -> FileId {
+    //--------------------------------------------------------------------------
+    let dir = args
+        .file
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    let dirs: Vec<&Path> = Some(dir)
+        .into_iter()
+        .chain(args.dirs.iter().map(PathBuf::as_path))
+        .collect();
+
+    let mut path = SearchPath::default();
+    for dir in dirs.iter() {
+        path.add_dir(dir).unwrap_or_else(|e| fail(e));
+    }
+    path.add_bundled();
+    asm.add_search_path(&path);
+
+    asm.load(&args.file).unwrap_or_else(|e| {
+        fail(format!("cannot read `{}`: {}", args.file.display(), e))
+    })
+}
+
+/// Describe an error, or warning, prefixed with the name of the file
+/// it occurred in, e.g. "game.rym: Undefined macro `ld` at 3:9".
+///
+fn describe(asm: &Assembler, e: &ParseError) -> String {
+    //--------------------------------------------------------------------------
+    match e.location() {
+        Some(l) => format!("{}: {}", asm.sources().name(l.span.file), e),
+        None => e.to_string(),
+    }
+}
+
+/// Report an error and exit.
+///
+fn fail<T: Display>(message: T) -> ! {
+    //--------------------------------------------------------------------------
+    eprintln!("! ERROR: {}", message);
+    process::exit(1);
+}
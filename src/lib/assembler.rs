@@ -4,34 +4,70 @@
 //! **Assembler** orchestrates the assembly process.
 
 #[derive(Default)]
-pub struct Assembler<'token> {
-    _objects: Vec<Object<'token>>,
+pub struct Assembler {
+    /// The Objects assembled; each module imported comes before
+    /// the Objects that import it.
+    objects: Vec<Object>,
+    /// The source code of every file assembled, including modules imported.
+    sources: SourceMap,
+    /// The source code of the modules added to be imported, by name.
+    modules: HashMap<String, String>,
+    /// Where to find the modules that are not added, read when imported.
+    path: SearchPath,
+    /// The modules being imported, by name, innermost last;
+    /// for finding modules that import each other.
+    importing: Vec<String>,
+    /// Problems found that do not stop assembly, such as those reported by
+    /// `\warn` within a macro, in the order they occurred.
+    warnings: Vec<ParseError>,
+    /// Options enabled for assembly. Macros can change these
+    /// as they are expanded, with `\enable` & `\disable`.
+    options: Options,
+    /// Which of the Z80's registers are swapped for their shadows,
+    /// if the `z80-shadow-check` option is enabled.
+    shadows: Shadows,
+    /// Where in memory the segments are placed when linked.
+    map: MemoryMap,
 }
 
+/// Options enabled for assembly, by name, e.g. `z80-undocumented`.
+/// A macro form can require an option with `\requires`.
+pub type Options = HashSet<String>;
+
+/// How deep macro invocations can nest within the bodies of other macros
+/// before we assume that a macro is invoking itself without end.
+const MAX_EXPANSION_DEPTH: usize = 64;
+
 use crate::ast::AST;
 use crate::error::*;
+use crate::linker::{self, Image, Linker};
+use crate::list::List;
+use crate::map::{self, MemoryMap};
+use crate::module::{self, Found, SearchPath};
 use crate::node::{Node, NodeIter, NodeKind};
 use crate::object::Object;
+use crate::r#macro::Macro;
 use crate::r#struct::Struct;
+use crate::segment::Segment;
+use crate::shadow::{self, Shadows};
+use crate::source::{FileId, SourceMap};
 use crate::table::TableBuilder;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::mem;
+use std::path::Path;
 
-impl<'token> Assembler<'token> {
+impl Assembler {
     //==========================================================================
-    /// Assembles from a string source.
+    /// Assembles from a string source, with the modules bundled with
+    /// Retronym available to import. Returns the first error that occurred;
+    /// otherwise any warnings are kept by the Assembler, see `warnings`.
     ///
-    pub fn assemble_str(source: &'token str) -> Self {
+    pub fn assemble_str(source: &str) -> ParseResult<Self> {
         //----------------------------------------------------------------------
         //TODO:
-        // - evaluate keywords by returning definitions+exports for the object
-        //   i.e. macros and atoms need to be defined and exported for use in
-        //   other objects
-        //
-        // - modules! a file can import other modules, requiring these to be
-        //   turned into Objects too. Handled by linker only?
-        //
-        // - macros *must* be expanded before packing -- we can't know how many
-        //   list items a macro generates; therefore object files are bound to
-        //   the specific choice of macros they import (modules?)
+        // - object files are bound to the specific choice of macros they
+        //   import (modules?) as macros are expanded before packing
         //
         // - establish a default segment for relocating once the AST has been
         //   parsed into data tables
@@ -51,25 +87,473 @@ impl<'token> Assembler<'token> {
         // all referenced files have also been parsed and we have a complete
         // pool of symbol names to draw from
 
-        let asm = Assembler::default();
-        let ast = AST::new_from_str(source);
-        asm.assemble_ast(&ast);
-        asm
+        let mut asm = Assembler::default();
+        asm.add_bundled();
+        let file = asm.add_source("<input>", source);
+        match asm.assemble_file(file) {
+            Some(e) => Err(e),
+            None => Ok(asm),
+        }
+    }
+
+    /// The Objects that have been assembled.
+    ///
+    pub fn objects(&self) -> &[Object] {
+        //----------------------------------------------------------------------
+        &self.objects
+    }
+
+    /// The source code of every file assembled, including modules imported;
+    /// for finding the files that errors occurred in.
+    ///
+    pub fn sources(&self) -> &SourceMap {
+        //----------------------------------------------------------------------
+        &self.sources
+    }
+
+    /// Enable an option for the Objects assembled hereafter,
+    /// e.g. `z80-undocumented` for the Z80's undocumented instructions.
+    ///
+    pub fn enable(&mut self, option: &str) {
+        //----------------------------------------------------------------------
+        self.options.insert(option.to_string());
+    }
+
+    /// Add a module that can be imported, by name, e.g. `::z80`.
+    /// A module already added of the same name is replaced, and one
+    /// found on the search path is not used.
+    ///
+    pub fn add_module(&mut self, name: &str, source: &str) {
+        //----------------------------------------------------------------------
+        self.modules.insert(name.to_string(), source.to_string());
+    }
+
+    /// Import modules from the directories of a search path, after those
+    /// of any added before. A module is read when it is first imported;
+    /// modules added with `add_module` take precedence over those of the
+    /// same name.
+    ///
+    pub fn add_search_path(&mut self, path: &SearchPath) {
+        //----------------------------------------------------------------------
+        self.path.extend(path);
+    }
+
+    /// Import the modules bundled with Retronym, e.g. `::z80`, after those
+    /// added and those within the directories of the search path.
+    ///
+    pub fn add_bundled(&mut self) {
+        //----------------------------------------------------------------------
+        self.path.add_bundled();
+    }
+
+    /// Read the memory map the segments are placed by when linked, from
+    /// source code already added, e.g. with `load`. A memory map already
+    /// read, e.g. a preset, is added to.
+    ///
+    pub fn add_map(&mut self, file: FileId) -> MaybeError {
+        //----------------------------------------------------------------------
+        self.map.read(self.sources.file(file)).err()
+    }
+
+    /// Add the source code of a memory map bundled with Retronym, by name,
+    /// e.g. "sms", returning the ID of its file to be read with `add_map`.
+    /// Returns `None` if there is no preset of that name; see `PRESETS`.
+    ///
+    pub fn add_preset(&mut self, name: &str) -> Option<FileId> {
+        //----------------------------------------------------------------------
+        let (_, source) = map::PRESETS.iter().find(|(n, _)| *n == name)?;
+        Some(self.add_source(name, source))
     }
 
-    /// Assembles from an `AST`.
+    /// Link the Objects assembled, placing their segments as described
+    /// by the memory map; without one, the segments are placed one after
+    /// another from address zero. See `Linker`.
     ///
-    pub fn assemble_ast(&self, ast: &AST) {
+    pub fn link(&self) -> ParseResult<Image> {
+        //----------------------------------------------------------------------
+        Linker::new(&self.objects).map(&self.map).link()
+    }
+
+    /// Warnings that occurred during assembly.
+    ///
+    pub fn warnings(&self) -> &[ParseError] {
+        //----------------------------------------------------------------------
+        &self.warnings
+    }
+
+    /// Add source code to be assembled, under the given name,
+    /// returning the ID of its file.
+    ///
+    pub fn add_source(&mut self, name: &str, source: &str) -> FileId {
+        //----------------------------------------------------------------------
+        self.sources.add(name, source.to_string())
+    }
+
+    /// Read a source file to be assembled, returning the ID of its file.
+    ///
+    pub fn load<P: AsRef<Path>>(&mut self, path: P) -> io::Result<FileId> {
+        //----------------------------------------------------------------------
+        self.sources.load(path)
+    }
+
+    /// Assembles a file added with `add_source` or `load`. If successful,
+    /// the resulting `Object` is added to the Assembler's list of Objects,
+    /// after those of the modules imported. An error parsing the source
+    /// code stops assembly before anything is packed.
+    ///
+    pub fn assemble_file(&mut self, file: FileId) -> MaybeError {
+        //----------------------------------------------------------------------
+        match AST::new(self.sources.file(file)) {
+            Ok(ast) => self.assemble_ast(&ast),
+            Err(e) => Some(e),
+        }
+    }
+
+    /// Assembles from an `AST`. If successful, the resulting `Object` is
+    /// added to the Assembler's list of Objects.
+    ///
+    pub fn assemble_ast(&mut self, ast: &AST) -> MaybeError {
+        //----------------------------------------------------------------------
+        match self.assemble_object(ast, "") {
+            Ok(object) => {
+                self.objects.push(object);
+                None
+            }
+            Err(e) => Some(e),
+        }
+    }
+
+    /// Assemble an `AST` into an `Object`, for the module of the given name
+    /// (or an empty name where the source code was not imported).
+    ///
+    fn assemble_object(
+        &mut self,
+        ast: &AST,
+        name: &str,
+    ) -> ParseResult<Object> {
         //----------------------------------------------------------------------
         // create the Object we'll be placing the assembled resources into.
-        // during assembly, new objects might be created (module references)
-        let mut object = Object::default();
+        // during assembly, new objects might be created (module imports)
+        let mut object = Object::new(name);
+
+        // gather the definitions first; a macro can be
+        // invoked before the point where it is defined
+        for node in ast.iter().filter(|n| n.is_def()) {
+            if let Some(e) = self.define(&mut object, node) {
+                return Err(e);
+            }
+        }
+
+        // macros *must* be expanded before packing -- we can't know how
+        // many list items a macro generates until it has been expanded.
+        // the modules imported are set aside whilst we do, as the Macros
+        // they export are expanded as seen from within their own Objects
+        self.shadows = Shadows::default();
+        let modules = mem::take(&mut self.objects);
+        let mut expanded = List::default();
+        let result = ast.iter().filter(|n| !n.is_def()).try_for_each(|node| {
+            self.expand(&modules, &object, node, &mut expanded, 0)
+        });
+        self.objects = modules;
+        result?;
+
+        // create a Node iterator from the expanded statements
+        let mut node_iter = expanded.iter();
 
-        // create a Node iterator from the AST
-        let mut node_iter = ast.into_iter();
+        while let Some(node) = node_iter.next() {
+            if let Some(e) =
+                self.assemble_root(&mut object, &mut node_iter, node)
+            {
+                return Err(e);
+            }
+        }
+        // Labels are defined as the data is packed,
+        // so exports can only be checked now
+        if let Some(e) = object.check_exports(&self.objects) {
+            return Err(e);
+        }
 
-        let node = node_iter.next().unwrap();
-        self.assemble_root(&mut object, &mut node_iter, node);
+        Ok(object)
+    }
+
+    /// Add a definition (Atom, Macro) to the Object's symbol pool, or import
+    /// a module (assembling it, if it has not been already) or export
+    /// symbols from the Object.
+    ///
+    fn define(&mut self, object: &mut Object, node: &Node) -> MaybeError {
+        //----------------------------------------------------------------------
+        if node.is_atom_def() {
+            // define a new Atom
+            return object.new_atom(node);
+        }
+        if node.is_macro_def() {
+            // define a new Macro
+            return object.new_macro(node);
+        }
+        if let NodeKind::Import(ref name) = node.kind {
+            if let Err(e) = self.import(name) {
+                return Some(e.at(node.location()));
+            }
+            object.new_import(name);
+        }
+        if node.is_export() {
+            return object.new_export(node);
+        }
+
+        None
+    }
+
+    /// Assemble the module of the given name, unless it already has been.
+    ///
+    fn import(&mut self, name: &str) -> ParseResult<()> {
+        //----------------------------------------------------------------------
+        if let Some(i) = self.importing.iter().position(|m| m == name) {
+            let mut chain = self.importing[i..].to_vec();
+            chain.push(name.to_string());
+            return Err(parse_error(ParseErrorKind::ImportCycle(chain)));
+        }
+        if self.objects.iter().any(|o| o.name() == name) {
+            return Ok(());
+        }
+        // a module added, or bundled, is named as its file would be,
+        // e.g. "z80.rym"; one found in a directory, by its path
+        let named = format!("{}.{}", name, module::EXTENSION);
+        let file = match (self.modules.get(name), self.path.find(name)) {
+            (Some(source), _) => self.sources.add(&named, source.clone()),
+            (None, Some(Found::File(path))) => self.sources.load(path)?,
+            (None, Some(Found::Bundled(source))) => {
+                self.sources.add(&named, source.to_string())
+            }
+            (None, None) => {
+                return Err(parse_error(ParseErrorKind::UndefinedModule(
+                    name.to_string(),
+                )))
+            }
+        };
+        let ast = AST::new(self.sources.file(file))?;
+        self.importing.push(name.to_string());
+        let result = self.assemble_object(&ast, name);
+        self.importing.pop();
+
+        self.objects.push(result?);
+        Ok(())
+    }
+
+    /// Expand a statement, appending the result to the given `List`.
+    ///
+    /// A Macro invocation is replaced by the body of the Macro, with each
+    /// statement within in turn expanded; all other statements are copied
+    /// as they are. If an error (or warning) occurs within the body of a
+    /// Macro, the chain of invocations is recorded in its backtrace.
+    ///
+    /// Macros are found, and their bodies expanded, as seen from within
+    /// the Object that defines them; the modules are those it can import.
+    ///
+    fn expand(
+        &mut self,
+        modules: &[Object],
+        object: &Object,
+        node: &Node,
+        list: &mut List,
+        depth: usize,
+    ) -> ParseResult<()> {
+        //----------------------------------------------------------------------
+        Self::check_hints(modules, object, node)?;
+
+        let (name, args) = match node.kind {
+            NodeKind::Macro(ref name, ref args) => (name, args),
+            NodeKind::Warn(ref message) => {
+                self.warnings.push(
+                    parse_error(ParseErrorKind::Warning(message.clone()))
+                        .at(node.location()),
+                );
+                return Ok(());
+            }
+            NodeKind::Error(ref message) => {
+                return Err(parse_error(ParseErrorKind::Error(
+                    message.clone(),
+                ))
+                .at(node.location()));
+            }
+            // options change for the statements that follow,
+            // e.g. the 65816's `rep`/`sep` changing register widths
+            NodeKind::Enable(ref option) => {
+                self.options.insert(option.clone());
+                return Ok(());
+            }
+            NodeKind::Disable(ref option) => {
+                self.options.remove(option);
+                return Ok(());
+            }
+            // an alternative to the statements of a Macro form
+            // is only kept where relaxation is enabled
+            NodeKind::Relax(ref block) => {
+                if self.options.contains(linker::OPTION) {
+                    let mut alternative = List::default();
+                    for inner in block.iter() {
+                        self.expand(
+                            modules,
+                            object,
+                            inner,
+                            &mut alternative,
+                            depth + 1,
+                        )?;
+                    }
+                    // a `Relax` node always has its directive token
+                    list.push(Node::new_relax(
+                        node.token.clone().unwrap(),
+                        alternative,
+                    ));
+                }
+                return Ok(());
+            }
+            _ => {
+                list.push(node.clone());
+                return Ok(());
+            }
+        };
+
+        // the Macro must have been defined
+        let chain = object
+            .find_macro(modules, name)
+            .map_err(|e| e.at(node.location()))?;
+        if chain.is_empty() {
+            return Err(parse_error(ParseErrorKind::UndefinedMacro(
+                name.clone(),
+            ))
+            .at(node.location()));
+        }
+
+        if depth >= MAX_EXPANSION_DEPTH {
+            return Err(parse_error(ParseErrorKind::Recursion(name.clone()))
+                .at(node.location()));
+        }
+
+        if self.options.contains(shadow::OPTION) {
+            let warnings = self.shadows.step(chain[0].1.name(), args);
+            self.warnings.extend(warnings);
+        }
+
+        // insert the arguments into the body of the macro; the body is
+        // expanded as seen from within the Object that defines the form
+        let macros: Vec<&Macro> = chain.iter().map(|(_, m)| *m).collect();
+        let (n, body) =
+            Macro::expand_chain(&macros, args, &node.token, &self.options)?;
+        let scope = chain[n].0;
+
+        // the body of the macro might contain invocations of other macros
+        let step = Expansion::new(name, &node.token);
+        let start = self.warnings.len();
+        let at = list.len();
+        for inner in body.iter() {
+            self.expand(modules, scope, inner, list, depth + 1)
+                .map_err(|e| e.in_expansion(step.clone()))?;
+        }
+        Self::join(list, at, node);
+        // the statements expanded record the invocation too, for errors
+        // found once they are packed, or linked
+        for inner in list.iter_mut().skip(at) {
+            inner.in_expansion(&step);
+        }
+        // warnings from within the body record the invocation too
+        let warnings: Vec<_> = self
+            .warnings
+            .drain(start..)
+            .map(|w| w.in_expansion(step.clone()))
+            .collect();
+        self.warnings.extend(warnings);
+
+        Ok(())
+    }
+
+    /// Gather the statements a Macro invocation expanded to, from the index
+    /// given, into a joint if the form gave an alternative with `\relax`;
+    /// the statements are its natural form. A joint is only made where a
+    /// value is not known until the Object is linked, e.g. the address of a
+    /// Label, otherwise the natural form is kept as it is.
+    ///
+    fn join(list: &mut List, at: usize, node: &Node) {
+        //----------------------------------------------------------------------
+        let is_relax = |n: &Node| matches!(n.kind, NodeKind::Relax(_));
+        if !list.iter().skip(at).any(is_relax) {
+            return;
+        }
+        let body = list.split_off(at);
+        let mut natural = List::default();
+        let mut forms = Vec::new();
+        for inner in body.iter() {
+            match inner.kind {
+                NodeKind::Relax(ref alternative) => {
+                    forms.push(Self::flatten(alternative))
+                }
+                _ => natural.push(inner.clone()),
+            }
+        }
+        let natural = Self::flatten(&natural);
+        if !natural.iter().any(|n| n.is_deferred()) {
+            for inner in natural.iter() {
+                list.push(inner.clone());
+            }
+            return;
+        }
+        forms.insert(0, natural);
+        list.push(Node::new_joint(node.token.clone(), forms));
+    }
+
+    /// The statements of a form with any joint within replaced by its
+    /// natural form, as joints do not nest.
+    ///
+    fn flatten(list: &List) -> List {
+        //----------------------------------------------------------------------
+        let mut flat = List::default();
+        for node in list.iter() {
+            match node.kind {
+                NodeKind::Joint(ref forms) => {
+                    for inner in forms[0].iter() {
+                        flat.push(inner.clone());
+                    }
+                }
+                _ => flat.push(node.clone()),
+            }
+        }
+        flat
+    }
+
+    /// Check that the shadow hints within a statement, including the
+    /// arguments of a Macro invocation, are on Atoms that have a shadow;
+    /// e.g. `AF'` can only be used where `atom AF'` has been defined.
+    ///
+    fn check_hints(
+        modules: &[Object],
+        object: &Object,
+        node: &Node,
+    ) -> ParseResult<()> {
+        //----------------------------------------------------------------------
+        match node.kind {
+            // the shadow must have been defined
+            NodeKind::Atom(ref name, ref hint)
+                if hint.is_shadow()
+                    && !object.has_atom(modules, &format!("{}'", name)) =>
+            {
+                return Err(parse_error(ParseErrorKind::NoShadow(
+                    name.clone(),
+                ))
+                .at(node.location()));
+            }
+            NodeKind::Macro(_, ref list)
+            | NodeKind::List(ref list)
+            | NodeKind::Indirect(_, ref list) => {
+                for node in list.iter() {
+                    Self::check_hints(modules, object, node)?;
+                }
+            }
+            NodeKind::Expr(ref expr) => {
+                Self::check_hints(modules, object, &expr.left)?;
+                Self::check_hints(modules, object, &expr.right)?;
+            }
+            _ => {}
+        }
+        Ok(())
     }
 
     /// Begins assembly at the 'root scope', that is, statements at the
@@ -78,33 +562,49 @@ impl<'token> Assembler<'token> {
     /// Results of the assembly are placed into the given `Object`,
     /// this method only returns an error if one occurred.
     ///
-    fn assemble_root(
+    fn assemble_root<'a>(
         &self,
-        object: &'token mut Object<'token>,
-        node_iter: &'token mut NodeIter<'token>,
-        node: &'token Node<'token>,
+        object: &mut Object,
+        node_iter: &mut NodeIter<'a>,
+        node: &'a Node,
     ) -> MaybeError {
         //----------------------------------------------------------------------
-        if node.is_atom_def() {
-            // define a new Atom
-            return object.new_atom(node);
-        }
         if node.is_record() {
             // return error if there was one
-            self.assemble_table(object, node_iter, node).err();
+            return self.assemble_table(object, node_iter, node).err();
+        }
+        if node.is_placement() {
+            return object.place(node).map(|e| e.expanded(&node.backtrace));
+        }
+        if node.is_joint() {
+            return self.assemble_joint(object, node).err();
+        }
+        // data cannot be packed without a record to say how
+        if node.is_data() {
+            return Some(
+                ParseError::no_record()
+                    .at(node.location())
+                    .expanded(&node.backtrace),
+            );
         }
 
         None
     }
 
-    /// Pack data into a Table.
+    /// Pack data into a Table, and the Table into the Object.
+    /// The Table continues up until the next record.
+    ///
+    /// A Label, segment or alignment within the Table packs the Rows so far
+    /// before placing the data that follows; the record carries on, so a
+    /// Row cannot be split across them. Likewise a joint, which brings
+    /// its own records.
     ///
-    fn assemble_table(
+    fn assemble_table<'a>(
         &self,
-        _object: &'token mut Object<'token>,
-        _node_iter: &'token mut NodeIter<'token>,
-        node: &'token Node<'token>,
-    ) -> ParseResult<&'token Node<'token>> {
+        object: &mut Object,
+        node_iter: &mut NodeIter<'a>,
+        node: &'a Node,
+    ) -> ParseResult<&'a Node> {
         //----------------------------------------------------------------------
         // the record must come first -- we can't pack data without knowing
         // what the fields are!
@@ -122,8 +622,76 @@ impl<'token> Assembler<'token> {
         let record = Struct::from(list);
 
         // start up a TableBuilder with the Record we now have
-        let _builder = TableBuilder::new(&record);
+        let mut builder = TableBuilder::new(&record);
+
+        // add the data that follows, stopping short of the next record
+        let mut last = node;
+        while let Some(next) = node_iter.as_slice().first() {
+            if next.is_record() {
+                break;
+            }
+            node_iter.next();
+            if next.is_placement() || next.is_joint() {
+                let table = builder.finish().map_err(|e| {
+                    e.at(last.location()).expanded(&last.backtrace)
+                })?;
+                object.pack(&table)?;
+                if next.is_joint() {
+                    self.assemble_joint(object, next)?;
+                } else if let Some(e) = object.place(next) {
+                    return Err(e.expanded(&next.backtrace));
+                }
+                builder = TableBuilder::new(&record);
+                continue;
+            }
+            last = next;
+            if let Some(e) = builder.add_data(next) {
+                return Err(e.expanded(&next.backtrace));
+            }
+        }
+
+        let table = builder
+            .finish()
+            .map_err(|e| e.at(last.location()).expanded(&last.backtrace))?;
+        object.pack(&table)?;
 
         Ok(node)
     }
+
+    /// Pack each form of a joint into a segment of its own, and the joint
+    /// into the Object. A form cannot define a Label, or change the
+    /// segment or its alignment.
+    ///
+    fn assemble_joint(
+        &self,
+        object: &mut Object,
+        node: &Node,
+    ) -> ParseResult<()> {
+        //----------------------------------------------------------------------
+        let forms = match node.kind {
+            NodeKind::Joint(ref forms) => forms,
+            _ => panic!("Not a joint `Node`."),
+        };
+
+        let mut segments: Vec<Segment> = Vec::new();
+        for form in forms.iter() {
+            let mut packed = Object::new("");
+            let mut node_iter = form.iter();
+            while let Some(inner) = node_iter.next() {
+                if inner.is_placement() {
+                    return Err(ParseError::unexpected()
+                        .at(inner.location())
+                        .expanded(&inner.backtrace));
+                }
+                if let Some(e) =
+                    self.assemble_root(&mut packed, &mut node_iter, inner)
+                {
+                    return Err(e);
+                }
+            }
+            segments.extend(packed.into_segments().into_iter().take(1));
+        }
+        object.joint(segments, node.location());
+        Ok(())
+    }
 }
@@ -9,55 +9,61 @@ use crate::list::List;
 use crate::node::Node;
 
 /// The "Abstract Syntax Tree" is a machine understandable respresentation of
-/// some source code. Because AST nodes can contain a reference back to the
-/// original source code (token) for errors, the `'token` lifetime is used
-/// so that the source code is not deallocated before the AST.
+/// some source code. AST nodes can contain a reference back to the original
+/// source code (token) for errors; the `SourceFile` it belongs to is kept
+/// alive for as long as the tokens are.
 #[derive(Default)]
-pub struct AST<'token> {
-    nodes: List<'token>,
+pub struct AST {
+    nodes: List,
 }
 
 use crate::node::NodeIter;
 
-impl<'token> IntoIterator for &'token AST<'token> {
+impl<'a> IntoIterator for &'a AST {
     //==========================================================================
-    type Item = &'token Node<'token>;
-    type IntoIter = NodeIter<'token>;
+    type Item = &'a Node;
+    type IntoIter = NodeIter<'a>;
 
-    fn into_iter(self) -> NodeIter<'token> {
+    fn into_iter(self) -> NodeIter<'a> {
         //----------------------------------------------------------------------
         self.nodes.into_iter()
     }
 }
 
+use crate::error::ParseResult;
 use crate::parser::Parser;
+use crate::source::SourceFile;
+use std::rc::Rc;
 
-impl<'token> AST<'token> {
+impl AST {
     //==========================================================================
-    pub fn new_from_str(source: &'token str) -> Self {
+    /// Build the AST of a file's source code. Returns the first
+    /// `ParseError` found, if any; the AST is not built.
+    ///
+    pub fn new(file: &Rc<SourceFile>) -> ParseResult<Self> {
         //----------------------------------------------------------------------
-        let parser = Parser::from_str(source);
+        let parser = Parser::new(file)?;
 
         let mut ast = AST::default();
 
         for node in parser {
-            match node {
-                Ok(o) => match o {
-                    Some(a) => ast.push(a),
-                    None => break,
-                },
-                Err(e) => {
-                    println!("! ERROR: {}", e);
-                    // TODO: return an error
-                    break;
-                }
+            match node? {
+                Some(a) => ast.push(a),
+                None => break,
             }
         }
 
-        ast
+        Ok(ast)
     }
 
-    fn push(&mut self, node: Node<'token>) {
+    /// Get an iterator over the top-level nodes of the AST.
+    ///
+    pub fn iter(&self) -> std::slice::Iter<'_, Node> {
+        //----------------------------------------------------------------------
+        self.nodes.iter()
+    }
+
+    fn push(&mut self, node: Node) {
         //----------------------------------------------------------------------
         self.nodes.push(node);
     }
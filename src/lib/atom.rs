@@ -3,23 +3,87 @@
 
 //! **Atoms** are unique symbols with no value. Used for machine registers,
 //! e.g. "`A`", "`X`", "`HL`" etc.
+//!
+//! Where an Atom is used, it may carry a **hint** documenting its use. A
+//! register hint follows a back-tick, e.g. ``HL`width``, and a shadow hint
+//! follows an apostrophe, e.g. `AF'` or `AF'flags`, to show that the shadow
+//! register is in play. Hints do not affect assembly, so `AF'` is the same
+//! Atom as `AF`, but a shadow hint can only be used on an Atom that has a
+//! shadow; that is, where the shadow has been defined too, e.g. `atom AF'`.
 
 use crate::token::Token;
 
 /// An Atom definition. Not an Atom reference -- these appear within
 /// the AST -- but an Atom that an Object file defines and exports.
 #[derive(Debug, Clone)]
-pub struct Atom<'token> {
+pub struct Atom {
     /// The name/symol of the Atom.
     name: String,
     /// A reference back to the original source code where the atom was
     /// defined, in case of error.
-    token: Token<'token>,
+    token: Token,
+    /// The documentation comment preceding the definition, if any.
+    doc: Option<String>,
+}
+
+/// A hint given where an Atom is used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Hint {
+    /// No hint.
+    None,
+    /// A register hint, e.g. ``HL`width``.
+    Register(String),
+    /// A shadow hint, e.g. `AF'flags`. The descriptor is optional,
+    /// so may be empty.
+    Shadow(String),
+}
+
+impl Hint {
+    //==========================================================================
+    /// Split the text of an Atom, as written, into the Atom's name
+    /// and its hint, if any.
+    ///
+    pub fn split(text: &str) -> (&str, Hint) {
+        //----------------------------------------------------------------------
+        if let Some(i) = text.find('`') {
+            (&text[..i], Hint::Register(text[i + 1..].to_string()))
+        } else if let Some(i) = text.find('\'') {
+            (&text[..i], Hint::Shadow(text[i + 1..].to_string()))
+        } else {
+            (text, Hint::None)
+        }
+    }
+
+    /// Is this a shadow hint?
+    ///
+    pub fn is_shadow(&self) -> bool {
+        //----------------------------------------------------------------------
+        matches!(self, Hint::Shadow(_))
+    }
+}
+
+use crate::error::Location;
+
+impl Atom {
+    //==========================================================================
+    /// The location in the source code where the Atom was defined.
+    ///
+    pub fn location(&self) -> Location {
+        //----------------------------------------------------------------------
+        self.token.location()
+    }
+
+    /// The documentation comment preceding the definition, if any.
+    ///
+    pub fn doc(&self) -> Option<&str> {
+        //----------------------------------------------------------------------
+        self.doc.as_deref()
+    }
 }
 
 use std::fmt::{self, *};
 
-impl Display for Atom<'_> {
+impl Display for Atom {
     //==========================================================================
     /// Print the Atom's symbol.
     ///
@@ -29,9 +93,23 @@ impl Display for Atom<'_> {
     }
 }
 
+impl Display for Hint {
+    //==========================================================================
+    /// Print the hint as it is written after the Atom's name.
+    ///
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        //----------------------------------------------------------------------
+        match self {
+            Hint::None => Ok(()),
+            Hint::Register(ref h) => write!(f, "`{}", h),
+            Hint::Shadow(ref h) => write!(f, "'{}", h),
+        }
+    }
+}
+
 use std::convert::From;
 
-impl<'token> From<&'token Token<'token>> for Atom<'token> {
+impl From<&Token> for Atom {
     //==========================================================================
     /// Create an `Atom` from a `Token`; a `Token` is required as the `Atom`
     /// contains a reference to the source code where it was defined, in case
@@ -39,34 +117,43 @@ impl<'token> From<&'token Token<'token>> for Atom<'token> {
     /// it's assumed this sort of logical test has been done by the caller.
     /// the string representation of the token is used as the atom name.
     ///
-    fn from(token: &'token Token<'token>) -> Self {
+    fn from(token: &Token) -> Self {
         //----------------------------------------------------------------------
         Self {
             name: token.to_string(),
             token: token.clone(),
+            doc: None,
         }
     }
 }
 
-use crate::node::Node;
+use crate::node::{Node, NodeKind};
 
 // you can also likewise create one from an AST Node
-impl<'token> From<&'token Node<'token>> for Atom<'token> {
+impl From<&Node> for Atom {
     //==========================================================================
     /// Create an `Atom` from an AST `Node`. See the description for
     /// `From<Token> for Atom` for details, this method just passes
-    /// the Node's internal token along.
+    /// the Node's internal token along, and the documentation comment
+    /// of an Atom definition.
     ///
-    fn from(node: &'token Node<'token>) -> Self {
+    fn from(node: &Node) -> Self {
         //----------------------------------------------------------------------
         // AST Nodes aren't forced to have an original source reference
-        match &node.token {
+        let atom = match &node.token {
             None => panic!(
                 "Cannot convert Node to Atom where the Node does not
                  contain a Token reference to the original source code."
             ),
             // use the already existing `Token` -> `Atom` conversion
             Some(t) => Self::from(t),
+        };
+        match node.kind {
+            NodeKind::DefAtom(_, ref doc) => Self {
+                doc: doc.clone(),
+                ..atom
+            },
+            _ => atom,
         }
     }
 }
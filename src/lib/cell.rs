@@ -1,18 +1,21 @@
 // retronym (C) copyright Kroc Camen 2017, 2018
 // BSD 2-clause licence; see LICENSE.TXT
 
+use crate::error::*;
 use crate::field::Field;
 use crate::node::Node;
+use crate::primitive::fits;
+use crate::segment::Segment;
 
 /// A **Table Cell**.
-/// 
+///
 #[derive(Clone)]
-pub struct Cell<'token> {
+pub struct Cell<'a> {
     /// Reference to the Record field that this cell aligns with (column).
     /// This is so that the Cell knows what its intended data-width is.
-    field: &'token Field<'token>,
+    field: &'a Field,
     /// AST Node containing the data value for this Cell.
-    node: &'token Node<'token>,
+    node: Node,
     /// The Row number of this Cell,
     /// i.e. its Row index in a Table.
     row: usize,
@@ -48,16 +51,11 @@ impl Debug for Cell<'_> {
     }
 }
 
-impl<'token> Cell<'token> {
+impl<'a> Cell<'a> {
     //==========================================================================
     /// Create a new Table Cell.
     ///
-    pub fn new(
-        node: &'token Node<'token>,
-        field: &'token Field<'token>,
-        row: usize,
-        col: usize,
-    ) -> Self {
+    pub fn new(node: Node, field: &'a Field, row: usize, col: usize) -> Self {
         //----------------------------------------------------------------------
         Self {
             field,
@@ -80,4 +78,55 @@ impl<'token> Cell<'token> {
         //----------------------------------------------------------------------
         self.col
     }
+
+    /// Pack the Cell's value into a segment, according to the width of its
+    /// field, in Little-Endian order. A value that is not known until the
+    /// Object is linked, e.g. a Label, is left for the linker to patch.
+    ///
+    /// Returns a `ParseError` if the value cannot be calculated or does
+    /// not fit the field.
+    ///
+    /// TODO: bit-packing; `bool` and `nybl` occupy a whole byte for now
+    ///
+    pub fn pack(&self, segment: &mut Segment) -> ParseResult<()> {
+        //----------------------------------------------------------------------
+        let bits = self.field.bits();
+        segment.begin(self.node.backtrace.last());
+        let value = match self.node.eval() {
+            Some(value) => value,
+            // only whole bytes can be patched
+            None if self.node.is_deferred() && bits.is_multiple_of(8) => {
+                segment.write_fixup(self.node.clone(), bits);
+                return Ok(());
+            }
+            None => {
+                return Err(parse_error(ParseErrorKind::NotStatic)
+                    .at(self.node.location())
+                    .expanded(&self.node.backtrace))
+            }
+        };
+
+        // a relative value is signed; a branch out of reach is left for
+        // the linker to report, once the address of the branch is known
+        let half = 1 << (bits - 1);
+        if self.node.is_relative()
+            && (value < -half || value >= half)
+            && bits.is_multiple_of(8)
+        {
+            segment.write_fixup(self.node.clone(), bits);
+            return Ok(());
+        }
+
+        let bytes = bits.div_ceil(8);
+        if !fits(value, bits) {
+            return Err(parse_error(ParseErrorKind::Overflow { value, bits })
+                .at(self.node.location())
+                .expanded(&self.node.backtrace));
+        }
+
+        let bytes: Vec<u8> =
+            (0..bytes).map(|i| (value >> (i * 8)) as u8).collect();
+        segment.write(&bytes);
+        Ok(())
+    }
 }
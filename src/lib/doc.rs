@@ -0,0 +1,131 @@
+// retronym (C) copyright Kroc Camen 2017, 2018
+// BSD 2-clause licence; see LICENSE.TXT
+
+//! **Documentation** of a module. Writes a Markdown reference of the symbols
+//! that an Object exports -- Atoms, Labels and the forms of Macros -- along
+//! with the documentation comments given with their definitions, e.g.
+//!
+//! ```
+//! export  { A, ld, :reset }
+//!
+//! ``the accumulator
+//! atom    A
+//!
+//! ``load the accumulator
+//! macro   ld      A, \n { ... }
+//!
+//! ``restart the machine
+//! :reset
+//! ```
+//!
+//! Symbols are listed in the order they were defined. A module can export
+//! what it imports, and extend the Macros it imports, e.g. `z80` those of
+//! `z80_base`; those symbols, and the forms of those Macros, are listed
+//! too, after its own. Structs cannot be defined, nor constants, yet, so
+//! there are none to document.
+//!
+
+use crate::object::Object;
+use itertools::Itertools;
+
+/// Write a Markdown reference of the symbols an Object exports,
+/// beneath a heading of the given title; the modules are those the
+/// Object can import.
+///
+pub fn markdown(title: &str, object: &Object, modules: &[Object]) -> String {
+    //--------------------------------------------------------------------------
+    let mut out = format!("# {}\n", title);
+
+    // a symbol defined by a module imported comes after the Object's own,
+    // in the order the modules were assembled
+    let order = |definer: &Object| {
+        modules
+            .iter()
+            .position(|m| std::ptr::eq(m, definer))
+            .map_or(0, |i| i + 1)
+    };
+
+    let mut atoms: Vec<_> = object
+        .exports()
+        .into_iter()
+        .filter_map(|name| object.find_atom(modules, name))
+        .collect();
+    atoms.sort_by_key(|(definer, atom)| {
+        let location = atom.location();
+        (order(definer), location.line, location.col)
+    });
+    if !atoms.is_empty() {
+        out.push_str("\n## Atoms\n\n");
+        for (_, atom) in atoms {
+            write_item(&mut out, &format!("`{}`", atom), atom.doc());
+        }
+    }
+
+    let mut labels: Vec<_> = object
+        .segments()
+        .iter()
+        .flat_map(|segment| segment.labels())
+        .filter(|label| object.is_exported(&format!(":{}", label.name)))
+        .collect();
+    labels.sort_by_key(|label| label.location.map(|l| (l.line, l.col)));
+    if !labels.is_empty() {
+        out.push_str("\n## Labels\n\n");
+        for label in labels {
+            let item = format!("`:{}`", label.name);
+            write_item(&mut out, &item, label.doc.as_deref());
+        }
+    }
+
+    // each Macro is listed with the forms of those it extends
+    let mut macros: Vec<_> = object
+        .exports()
+        .into_iter()
+        .filter_map(|name| object.find_export(modules, name))
+        .map(|(_, r#macro)| r#macro.name())
+        .filter_map(|name| object.find_macro(modules, name).ok())
+        .filter(|chain| !chain.is_empty())
+        .collect();
+    macros.sort_by_key(|chain| {
+        let (definer, r#macro) = chain[0];
+        let location = r#macro.token().location();
+        (order(definer), location.line, location.col)
+    });
+    if !macros.is_empty() {
+        out.push_str("\n## Macros\n");
+        for chain in macros {
+            out.push_str(&format!("\n### `{}`\n\n", chain[0].1.name()));
+            for form in chain.iter().flat_map(|(_, m)| m.forms()) {
+                let mut item = format!("`{}`", form);
+                if !form.requires().is_empty() {
+                    item.push_str(&format!(
+                        " (requires {})",
+                        form.requires()
+                            .iter()
+                            .map(|option| format!("`{}`", option))
+                            .join(", ")
+                    ));
+                }
+                write_item(&mut out, &item, form.doc());
+            }
+        }
+    }
+    out
+}
+
+/// Write a list item for a symbol, followed by its documentation, if any,
+/// indented so as to continue the item.
+///
+fn write_item(out: &mut String, item: &str, doc: Option<&str>) {
+    //--------------------------------------------------------------------------
+    out.push_str(&format!("* {}\n", item));
+    if let Some(doc) = doc {
+        out.push('\n');
+        for line in doc.lines() {
+            match line.trim_end() {
+                "" => out.push('\n'),
+                line => out.push_str(&format!("  {}\n", line)),
+            }
+        }
+        out.push('\n');
+    }
+}
@@ -14,10 +14,20 @@ use std::result;
 
 use std::fmt;
 
+use crate::source::Span;
+
 /// Our own wrapping Error-type that can contain a Rust std Error, such as
 /// `io::Error`, or our own according to the `ParseErrorKind` enum
 #[derive(Debug)]
-pub struct ParseError(Box<ParseErrorKind>);
+pub struct ParseError {
+    /// The specific type of error.
+    kind: Box<ParseErrorKind>,
+    /// Where in the source code the error occurred, if known.
+    location: Option<Location>,
+    /// When an error occurs within the body of a macro, the chain of macro
+    /// invocations that lead to it, inner-most first.
+    backtrace: Vec<Expansion>,
+}
 
 /// The specific type of an error:
 #[derive(Debug)]
@@ -29,6 +39,10 @@ pub enum ParseErrorKind {
 
     Unexpected,
 
+    /// The source code does not follow the grammar of the language;
+    /// the text, to the end of its line, that could not be read.
+    Syntax(String),
+
     /// Duplicate defintion.
     Duplicate,
 
@@ -39,6 +53,236 @@ pub enum ParseErrorKind {
     RowSatisfied,
     /// Cannot leave a record unsatisfied.
     Unsatisfied,
+    /// A value is too large (or small) to fit the data-type of its field.
+    Overflow {
+        /// The value that does not fit.
+        value: i64,
+        /// The width of the field, in bits.
+        bits: usize,
+    },
+
+    /// A macro was invoked that has not been defined.
+    UndefinedMacro(String),
+    /// A macro was invoked with the wrong number of arguments.
+    ArgumentCount {
+        /// Name of the macro invoked.
+        name: String,
+        /// Number of parameters the macro defines.
+        expected: usize,
+        /// Number of arguments given at the invocation.
+        found: usize,
+        /// A variadic macro accepts more arguments than it has parameters.
+        is_variadic: bool,
+    },
+    /// No form of a macro matches the arguments given.
+    NoMatchingForm {
+        /// The macro invocation, as normalised source code.
+        invocation: String,
+        /// The forms the invocation was likely meant for, e.g. those that
+        /// begin with the same Atoms; not those that require an option not
+        /// enabled.
+        forms: Vec<String>,
+        /// How many more such forms were left out of the list.
+        more: usize,
+    },
+    /// A macro body referred to a parameter the macro does not define.
+    UndefinedParam(String),
+    /// A value that must be calculated without outside information (such
+    /// as the test of a conditional block) cannot be.
+    NotStatic,
+    /// Macro expansion has nested too deeply; most likely a macro that
+    /// invokes itself without end.
+    Recursion(String),
+
+    /// A warning given by `\warn` within a macro. Warnings do not stop
+    /// assembly.
+    Warning(String),
+    /// An error given by `\error` within a macro.
+    Error(String),
+    /// A macro form was used that requires an option that is not enabled,
+    /// e.g. an undocumented instruction.
+    OptionRequired {
+        /// The macro invocation, as normalised source code.
+        invocation: String,
+        /// The option that must be enabled.
+        option: String,
+    },
+
+    /// A shadow hint was given on an Atom that has no shadow, e.g. `IX'`.
+    NoShadow(String),
+
+    /// A module was imported that cannot be found.
+    UndefinedModule(String),
+    /// Modules import each other in a cycle; the chain of imports,
+    /// beginning and ending with the same module.
+    ImportCycle(Vec<String>),
+    /// A module exports a symbol that it does not define.
+    UndefinedExport(String),
+    /// A macro is exported by more than one module imported,
+    /// and not qualified by which.
+    AmbiguousMacro {
+        /// The name of the macro.
+        name: String,
+        /// The modules that export it, in the order imported.
+        modules: Vec<String>,
+    },
+
+    /// A record names a user-defined type, e.g. `%point`;
+    /// these cannot be defined yet.
+    StructType(String),
+
+    /// Alignment was given that is not a power of two.
+    Alignment(i64),
+    /// A Label is defined more than once within an Object.
+    DuplicateLabel(String),
+
+    /// A Label is used that is neither defined by the Object using it,
+    /// nor exported by any other Object linked.
+    UndefinedLabel(String),
+    /// A Label is exported by more than one Object linked.
+    DuplicateSymbol(String),
+    /// A memory map names an area (region, RAM or slot) not given.
+    UndefinedArea(String),
+    /// A memory map has a statement that is not one it understands,
+    /// e.g. a misspelt `regoin`.
+    UnknownStatement(String),
+    /// A memory map statement is not given the arguments it takes; either
+    /// too few or too many, or one of the wrong kind.
+    StatementUsage {
+        /// The name of the statement, e.g. "region".
+        name: String,
+        /// How the statement is written, e.g. "region <name>, <start>, <size>".
+        usage: &'static str,
+        /// The number of arguments given, if that is what is wrong.
+        found: Option<usize>,
+    },
+    /// A memory map names an area that is not of a kind the statement
+    /// takes, e.g. `banks` given a region rather than a slot.
+    WrongAreaKind {
+        /// The name of the area.
+        area: String,
+        /// The kinds of area the statement takes, e.g. "a region or slot".
+        expected: String,
+    },
+    /// A memory map gives an area of the same name twice.
+    DuplicateArea(String),
+    /// A memory map gives the same memory twice, e.g. two regions that
+    /// overlap; each is described, e.g. "`ROM`" or "the mirror of `RAM`".
+    AreaOverlap {
+        /// The memory given first.
+        area: String,
+        /// The memory given later.
+        other: String,
+        /// The first address given by both.
+        address: usize,
+    },
+    /// A mirror is larger than the area it mirrors.
+    MirrorSize {
+        /// The name of the area mirrored.
+        area: String,
+        /// How many bytes the mirror runs past the size of the area.
+        overrun: usize,
+    },
+    /// A segment is placed in a slot that has no banks given.
+    NoBanks(String),
+    /// A segment is placed, or pinned, in a bank of a slot that is
+    /// reserved, e.g. one the mapper cannot page in.
+    ReservedBank {
+        /// The number of the bank.
+        bank: usize,
+        /// The name of the slot.
+        slot: String,
+    },
+    /// A segment has no area of the memory map to be placed in.
+    Unplaced(String),
+    /// A segment does not fit within the area of memory it is placed in.
+    AreaOverflow {
+        /// The name of the segment.
+        segment: String,
+        /// The name of the area.
+        area: String,
+        /// How many bytes the segment runs past the end of the area.
+        overrun: usize,
+    },
+    /// A segment is placed in a bank that is not one of a slot's banks.
+    UndefinedBank {
+        /// The number of the bank.
+        bank: usize,
+        /// The name of the slot.
+        slot: String,
+    },
+    /// The bank of a Label is wanted, but it does not lie in ROM.
+    NotBanked(String),
+    /// A Label is used from one bank of a slot, but lies in another;
+    /// both cannot be paged in at once.
+    CrossBank {
+        /// The name of the Label.
+        label: String,
+        /// The number of the bank the Label lies in.
+        bank: usize,
+        /// The number of the bank the Label is used from.
+        from: usize,
+        /// The name of the slot both banks are paged into.
+        slot: String,
+    },
+    /// A relative branch, e.g. `jr :loop`, is too far from its target for
+    /// the distance to fit its field.
+    BranchRange {
+        /// The address of the branch, e.g. of its instruction.
+        site: usize,
+        /// The target, as normalised source code, e.g. `:loop`.
+        target: String,
+        /// The address of the target; negative if a distance given as a
+        /// number lies before zero.
+        address: i64,
+        /// The distance to the target from the address after the field.
+        distance: i64,
+        /// The width of the field, in bits.
+        bits: usize,
+    },
+    /// A segment is pinned that no file defines.
+    UndefinedSegment(String),
+    /// A location in the output is pinned that no area of memory covers.
+    PinOutside(usize),
+    /// A Label is pinned at a location in the output,
+    /// but its segment places it elsewhere.
+    PinMismatch {
+        /// The name of the Label.
+        label: String,
+        /// Where in the output the Label is pinned.
+        pinned: usize,
+        /// Where in the output the Label lies.
+        placed: usize,
+    },
+    /// A segment is pinned at an address its alignment does not allow.
+    PinAlign {
+        /// The name of the segment pinned.
+        segment: String,
+        /// The alignment the segment requires, in bytes.
+        align: usize,
+        /// The address the segment is pinned at.
+        address: usize,
+    },
+    /// A segment is pinned at two different locations in the output.
+    PinnedTwice {
+        /// The name of the segment.
+        segment: String,
+        /// Where in the output it is pinned again.
+        pinned: usize,
+        /// Where in the output it was pinned first.
+        other: usize,
+    },
+    /// Two pinned segments overlap in the output.
+    Overlap {
+        /// The name of the segment pinned further into the output.
+        segment: String,
+        /// The name of the segment it overlaps.
+        other: String,
+        /// Where in the output the overlap begins.
+        offset: usize,
+        /// How many bytes overlap.
+        overrun: usize,
+    },
 
     #[doc(hidden)]
     Unimplemented,
@@ -57,12 +301,80 @@ pub enum ParseErrorKind {
     __Nonexhaustive,
 }
 
+/// A position in the source code, for error messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    /// The span of source code, within which file, that the position
+    /// begins; see `SourceMap` for the file's name.
+    pub span: Span,
+    /// Line number, beginning at 1.
+    pub line: usize,
+    /// Column number, beginning at 1.
+    pub col: usize,
+}
+
+impl fmt::Display for Location {
+    //==========================================================================
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        //----------------------------------------------------------------------
+        write!(f, "{}:{}", self.line, self.col)
+    }
+}
+
+/// One step of a macro-expansion backtrace; which macro was being expanded
+/// and where it was invoked from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expansion {
+    /// Name of the macro being expanded.
+    pub name: String,
+    /// Name of the file the macro was invoked from, e.g. "game.rym";
+    /// empty if not known.
+    pub file: String,
+    /// Location of the macro invocation, if known.
+    pub location: Option<Location>,
+}
+
+use crate::token::{MaybeToken, Token};
+
+impl Expansion {
+    //==========================================================================
+    /// The step of a backtrace for the expansion of the named macro,
+    /// invoked at the token given, if any.
+    ///
+    pub(crate) fn new(name: &str, token: &MaybeToken) -> Self {
+        //----------------------------------------------------------------------
+        Expansion {
+            name: name.to_string(),
+            file: token
+                .as_ref()
+                .map_or(String::new(), |t| t.file().name().to_string()),
+            location: token.as_ref().map(Token::location),
+        }
+    }
+}
+
+impl fmt::Display for Expansion {
+    //==========================================================================
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        //----------------------------------------------------------------------
+        write!(f, "in expansion of macro `{}`", self.name)?;
+        match self.location {
+            Some(l) => write!(f, ", invoked in `{}` at {}", self.file, l),
+            None => Ok(()),
+        }
+    }
+}
+
 /// A crate-private constructor for `ParseError`.
 /// Consumers of this library should never make their own ParseError!
 ///
 pub(crate) fn parse_error(kind: ParseErrorKind) -> ParseError {
     //--------------------------------------------------------------------------
-    ParseError(Box::new(kind))
+    ParseError {
+        kind: Box::new(kind),
+        location: None,
+        backtrace: Vec::new(),
+    }
 }
 
 /// When you only need to return a potential Error.
@@ -79,7 +391,7 @@ impl ParseError {
     #[allow(dead_code)]
     pub(crate) fn end_of_file() -> Self {
         //----------------------------------------------------------------------
-        ParseError(Box::new(ParseErrorKind::EndOfFile))
+        parse_error(ParseErrorKind::EndOfFile)
     }
 
     /// Create an `Unexpected` error.
@@ -87,7 +399,7 @@ impl ParseError {
     #[allow(dead_code)]
     pub(crate) fn unexpected() -> Self {
         //----------------------------------------------------------------------
-        ParseError(Box::new(ParseErrorKind::Unexpected))
+        parse_error(ParseErrorKind::Unexpected)
     }
 
     /// Create a `Duplicate` error.
@@ -95,7 +407,7 @@ impl ParseError {
     #[allow(dead_code)]
     pub(crate) fn duplicate() -> Self {
         //----------------------------------------------------------------------
-        ParseError(Box::new(ParseErrorKind::Duplicate))
+        parse_error(ParseErrorKind::Duplicate)
     }
 
     /// Create a `NoRecord` error.
@@ -103,7 +415,42 @@ impl ParseError {
     #[allow(dead_code)]
     pub(crate) fn no_record() -> Self {
         //----------------------------------------------------------------------
-        ParseError(Box::new(ParseErrorKind::NoRecord))
+        parse_error(ParseErrorKind::NoRecord)
+    }
+
+    /// Attach a source-code location to the error. If the error already has
+    /// a location, the existing (more specific) location is retained.
+    /// [Chainable]
+    ///
+    pub(crate) fn at(mut self, location: Option<Location>) -> Self {
+        //----------------------------------------------------------------------
+        if self.location.is_none() {
+            self.location = location;
+        }
+        self
+    }
+
+    /// Record that the error occurred within the expansion of a macro.
+    /// Called on the way back out of each level of macro expansion, so the
+    /// backtrace is built inner-most first. [Chainable]
+    ///
+    pub(crate) fn in_expansion(mut self, step: Expansion) -> Self {
+        //----------------------------------------------------------------------
+        self.backtrace.push(step);
+        self
+    }
+
+    /// Give the error the backtrace of the statement it occurred at, where
+    /// the statement was expanded from a macro, e.g. a value that does not
+    /// fit, found when packing or linking. A backtrace already recorded
+    /// is retained. [Chainable]
+    ///
+    pub(crate) fn expanded(mut self, backtrace: &[Expansion]) -> Self {
+        //----------------------------------------------------------------------
+        if self.backtrace.is_empty() {
+            self.backtrace = backtrace.to_vec();
+        }
+        self
     }
 
     /// Return the specific type of this error.
@@ -111,7 +458,7 @@ impl ParseError {
     pub fn kind(&self) -> &ParseErrorKind {
         //----------------------------------------------------------------------
         // return the embedded error
-        &self.0
+        &self.kind
     }
 
     /// Unwrap this error into its underlying type.
@@ -119,15 +466,27 @@ impl ParseError {
     pub fn into_kind(self) -> ParseErrorKind {
         //----------------------------------------------------------------------
         // dereference the embedded error
-        *self.0
+        *self.kind
+    }
+
+    /// Where in the source code the error occurred, if known.
+    ///
+    pub fn location(&self) -> Option<Location> {
+        //----------------------------------------------------------------------
+        self.location
+    }
+
+    /// The chain of macro invocations the error occurred within,
+    /// inner-most first. Empty if the error did not occur within a macro.
+    ///
+    pub fn backtrace(&self) -> &[Expansion] {
+        //----------------------------------------------------------------------
+        &self.backtrace
     }
 
     pub fn is_endoffile(&self) -> bool {
         //----------------------------------------------------------------------
-        match *self.0 {
-            ParseErrorKind::EndOfFile => true,
-            _ => false,
-        }
+        matches!(*self.kind, ParseErrorKind::EndOfFile)
     }
 
     /// Returns true if this is an I/O error.
@@ -137,26 +496,68 @@ impl ParseError {
     ///
     pub fn is_io_error(&self) -> bool {
         //----------------------------------------------------------------------
-        match *self.0 {
-            ParseErrorKind::Io(_) => true,
-            _ => false,
-        }
+        matches!(*self.kind, ParseErrorKind::Io(_))
     }
 }
 
 // "I'm a real boy!"
 impl StdError for ParseError {
     //==========================================================================
+    #[allow(deprecated)]
     fn description(&self) -> &str {
         //----------------------------------------------------------------------
-        match *self.0 {
+        match *self.kind {
             ParseErrorKind::Unimplemented => "Unimplemented",
             ParseErrorKind::EndOfFile => "End Of File",
             ParseErrorKind::Unexpected => "Unexpected",
+            ParseErrorKind::Syntax(_) => "Syntax error",
             ParseErrorKind::Duplicate => "Duplicate",
             ParseErrorKind::NoRecord => "No Record",
             ParseErrorKind::RowSatisfied => "Table row is full",
             ParseErrorKind::Unsatisfied => "Record Unsatisfied",
+            ParseErrorKind::Overflow { .. } => "Value does not fit",
+            ParseErrorKind::UndefinedMacro(_) => "Undefined macro",
+            ParseErrorKind::ArgumentCount { .. } => "Wrong number of arguments",
+            ParseErrorKind::NoMatchingForm { .. } => "No matching form",
+            ParseErrorKind::UndefinedParam(_) => "Undefined parameter",
+            ParseErrorKind::NotStatic => "Value cannot be calculated",
+            ParseErrorKind::Recursion(_) => "Macro recursion too deep",
+            ParseErrorKind::Warning(_) => "Warning",
+            ParseErrorKind::Error(_) => "Error",
+            ParseErrorKind::OptionRequired { .. } => "Option required",
+            ParseErrorKind::NoShadow(_) => "No shadow register",
+            ParseErrorKind::UndefinedModule(_) => "Undefined module",
+            ParseErrorKind::ImportCycle(_) => "Import cycle",
+            ParseErrorKind::UndefinedExport(_) => "Undefined export",
+            ParseErrorKind::AmbiguousMacro { .. } => "Ambiguous macro",
+            ParseErrorKind::StructType(_) => "User-defined type",
+            ParseErrorKind::Alignment(_) => "Alignment not a power of two",
+            ParseErrorKind::DuplicateLabel(_) => "Duplicate label",
+            ParseErrorKind::UndefinedLabel(_) => "Undefined label",
+            ParseErrorKind::DuplicateSymbol(_) => "Duplicate symbol",
+            ParseErrorKind::UndefinedArea(_) => "Undefined memory area",
+            ParseErrorKind::UnknownStatement(_) => {
+                "Unknown memory map statement"
+            }
+            ParseErrorKind::StatementUsage { .. } => "Wrong arguments",
+            ParseErrorKind::WrongAreaKind { .. } => "Wrong kind of memory area",
+            ParseErrorKind::DuplicateArea(_) => "Duplicate memory area",
+            ParseErrorKind::Unplaced(_) => "Segment not placed",
+            ParseErrorKind::AreaOverflow { .. } => "Segment does not fit",
+            ParseErrorKind::AreaOverlap { .. } => "Memory areas overlap",
+            ParseErrorKind::MirrorSize { .. } => "Mirror too large",
+            ParseErrorKind::NoBanks(_) => "Slot has no banks",
+            ParseErrorKind::ReservedBank { .. } => "Bank is reserved",
+            ParseErrorKind::UndefinedBank { .. } => "Undefined bank",
+            ParseErrorKind::NotBanked(_) => "Label does not lie in a bank",
+            ParseErrorKind::CrossBank { .. } => "Label lies in another bank",
+            ParseErrorKind::BranchRange { .. } => "Branch out of range",
+            ParseErrorKind::UndefinedSegment(_) => "Undefined segment",
+            ParseErrorKind::PinOutside(_) => "Pinned outside of memory",
+            ParseErrorKind::PinMismatch { .. } => "Label not where pinned",
+            ParseErrorKind::PinAlign { .. } => "Pinned segment misaligned",
+            ParseErrorKind::PinnedTwice { .. } => "Segment pinned twice",
+            ParseErrorKind::Overlap { .. } => "Pinned segments overlap",
             ParseErrorKind::Io(ref err) => err.description(),
             ParseErrorKind::ParseInt(ref err) => err.description(),
             _ => unreachable!(),
@@ -165,16 +566,11 @@ impl StdError for ParseError {
 
     fn cause(&self) -> Option<&dyn StdError> {
         //----------------------------------------------------------------------
-        match *self.0 {
-            ParseErrorKind::Unimplemented
-            | ParseErrorKind::EndOfFile
-            | ParseErrorKind::Unexpected
-            | ParseErrorKind::NoRecord
-            | ParseErrorKind::RowSatisfied
-            | ParseErrorKind::Unsatisfied => None,
+        match *self.kind {
             ParseErrorKind::Io(ref err) => Some(err),
             ParseErrorKind::ParseInt(ref err) => Some(err),
-            _ => unreachable!(),
+            ParseErrorKind::__Nonexhaustive => unreachable!(),
+            _ => None,
         }
     }
 }
@@ -183,18 +579,291 @@ impl fmt::Display for ParseError {
     //==========================================================================
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         //----------------------------------------------------------------------
-        match *self.0 {
+        match *self.kind {
             ParseErrorKind::Unimplemented => write!(f, "Unimplemented"),
             ParseErrorKind::EndOfFile => write!(f, "End Of File"),
             ParseErrorKind::Unexpected => write!(f, "Unexpected"),
+            ParseErrorKind::Syntax(ref text) => {
+                write!(f, "Syntax error in `{}`", text)
+            }
             ParseErrorKind::Duplicate => write!(f, "Duplicate"),
             ParseErrorKind::NoRecord => write!(f, "No Record"),
             ParseErrorKind::RowSatisfied => write!(f, "Table row is full"),
             ParseErrorKind::Unsatisfied => write!(f, "Record Unsatisfied"),
+            ParseErrorKind::Overflow { value, bits } => {
+                write!(f, "Value {} does not fit in {} bits", value, bits)
+            }
+            ParseErrorKind::UndefinedMacro(ref name) => {
+                write!(f, "Undefined macro `{}`", name)
+            }
+            ParseErrorKind::ArgumentCount {
+                ref name,
+                expected,
+                found,
+                is_variadic,
+            } => write!(
+                f,
+                "Macro `{}` expects {}{} argument(s), but {} given",
+                name,
+                if is_variadic { "at least " } else { "" },
+                expected,
+                found
+            ),
+            ParseErrorKind::NoMatchingForm { ref invocation, .. } => {
+                write!(f, "No matching form for `{}`", invocation)
+            }
+            ParseErrorKind::UndefinedParam(ref name) => {
+                write!(f, "Undefined macro parameter `\\{}`", name)
+            }
+            ParseErrorKind::NotStatic => {
+                write!(f, "Value cannot be calculated at this point")
+            }
+            ParseErrorKind::Recursion(ref name) => {
+                write!(f, "Macro recursion too deep expanding `{}`", name)
+            }
+            ParseErrorKind::Warning(ref message)
+            | ParseErrorKind::Error(ref message) => write!(f, "{}", message),
+            ParseErrorKind::OptionRequired {
+                ref invocation,
+                ref option,
+            } => write!(
+                f,
+                "`{}` requires the option `{}` to be enabled",
+                invocation, option
+            ),
+            ParseErrorKind::NoShadow(ref name) => {
+                write!(f, "`{}` does not have a shadow register", name)
+            }
+            ParseErrorKind::UndefinedModule(ref name) => {
+                write!(f, "Undefined module `{}`", name)
+            }
+            ParseErrorKind::ImportCycle(ref chain) => write!(
+                f,
+                "Modules import each other in a cycle: {}",
+                chain
+                    .iter()
+                    .map(|m| format!("`{}`", m))
+                    .collect::<Vec<_>>()
+                    .join(" -> ")
+            ),
+            ParseErrorKind::UndefinedExport(ref name) => {
+                write!(f, "`{}` is exported, but not defined", name)
+            }
+            ParseErrorKind::AmbiguousMacro {
+                ref name,
+                ref modules,
+            } => {
+                let names: Vec<_> =
+                    modules.iter().map(|m| format!("`{}`", m)).collect();
+                let (last, rest) = names.split_last().unwrap();
+                write!(
+                    f,
+                    "Macro `{}` is exported by {}{} and {}; qualify it \
+                     with the module's name, e.g. `{}.{}`",
+                    name,
+                    if rest.len() == 1 { "both " } else { "" },
+                    rest.join(", "),
+                    last,
+                    modules[0],
+                    name
+                )
+            }
+            ParseErrorKind::StructType(ref name) => {
+                write!(f, "User-defined type `{}` cannot be used yet", name)
+            }
+            ParseErrorKind::Alignment(value) => write!(
+                f,
+                "Alignment must be a power of two, but {} given",
+                value
+            ),
+            ParseErrorKind::DuplicateLabel(ref name) => {
+                write!(f, "Label `:{}` is already defined", name)
+            }
+            ParseErrorKind::UndefinedLabel(ref name) => {
+                write!(f, "Undefined label `:{}`", name)
+            }
+            ParseErrorKind::DuplicateSymbol(ref name) => {
+                write!(f, "Label `:{}` is exported by more than one file", name)
+            }
+            ParseErrorKind::UndefinedArea(ref name) => {
+                write!(f, "Undefined memory area `{}`", name)
+            }
+            ParseErrorKind::UnknownStatement(ref name) => {
+                write!(f, "Unknown memory map statement `{}`", name)
+            }
+            ParseErrorKind::StatementUsage {
+                ref name,
+                usage,
+                found,
+            } => {
+                write!(f, "`{}` is written `{}`", name, usage)?;
+                match found {
+                    Some(found) => write!(f, ", but {} argument(s) given", found),
+                    None => Ok(()),
+                }
+            }
+            ParseErrorKind::WrongAreaKind {
+                ref area,
+                ref expected,
+            } => write!(f, "`{}` is not {}", area, expected),
+            ParseErrorKind::DuplicateArea(ref name) => {
+                write!(f, "Area `{}` is already defined", name)
+            }
+            ParseErrorKind::AreaOverlap {
+                ref area,
+                ref other,
+                address,
+            } => write!(
+                f,
+                "Memory at ${:04X} is given by both {} and {}",
+                address, area, other
+            ),
+            ParseErrorKind::MirrorSize { ref area, overrun } => write!(
+                f,
+                "The mirror of `{}` is larger than it by {} byte(s)",
+                area, overrun
+            ),
+            ParseErrorKind::NoBanks(ref slot) => write!(
+                f,
+                "Slot `{}` has no banks; give the number with `banks`",
+                slot
+            ),
+            ParseErrorKind::ReservedBank { bank, ref slot } => {
+                write!(f, "Bank {} of `{}` is reserved", bank, slot)
+            }
+            ParseErrorKind::Unplaced(ref name) => write!(
+                f,
+                "Segment `{}` has no area of memory to be placed in",
+                name
+            ),
+            ParseErrorKind::AreaOverflow {
+                ref segment,
+                ref area,
+                overrun,
+            } => write!(
+                f,
+                "Segment `{}` overflows `{}` by {} byte(s)",
+                segment, area, overrun
+            ),
+            ParseErrorKind::UndefinedBank { bank, ref slot } => {
+                write!(f, "Bank {} is not one of the banks of `{}`", bank, slot)
+            }
+            ParseErrorKind::NotBanked(ref name) => {
+                write!(f, "Label `:{}` does not lie in a bank of ROM", name)
+            }
+            ParseErrorKind::CrossBank {
+                ref label,
+                bank,
+                from,
+                ref slot,
+            } => write!(
+                f,
+                "Label `:{}` lies in bank {} but is used from bank {}; \
+                 both are paged into `{}`",
+                label, bank, from, slot
+            ),
+            ParseErrorKind::BranchRange {
+                site,
+                ref target,
+                address,
+                distance,
+                bits,
+            } => write!(
+                f,
+                "Branch at ${:04X} to `{}` ({}${:04X}) is {} byte(s) away; \
+                 it must be within {} to +{}",
+                site,
+                target,
+                // a number far enough behind the branch lies before zero
+                if address < 0 { "-" } else { "" },
+                address.abs(),
+                distance,
+                -(1i64 << (bits - 1)),
+                (1i64 << (bits - 1)) - 1
+            ),
+            ParseErrorKind::UndefinedSegment(ref name) => {
+                write!(f, "Undefined segment `{}`", name)
+            }
+            ParseErrorKind::PinOutside(offset) => write!(
+                f,
+                "Pinned location ${:X} lies outside of the memory map",
+                offset
+            ),
+            ParseErrorKind::PinMismatch {
+                ref label,
+                pinned,
+                placed,
+            } => write!(
+                f,
+                "Label `:{}` is pinned at ${:X}, but lies at ${:X}",
+                label, pinned, placed
+            ),
+            ParseErrorKind::PinAlign {
+                ref segment,
+                align,
+                address,
+            } => write!(
+                f,
+                "Segment `{}` must be aligned to {} byte(s), but is pinned at ${:04X}",
+                segment, align, address
+            ),
+            ParseErrorKind::PinnedTwice {
+                ref segment,
+                pinned,
+                other,
+            } => write!(
+                f,
+                "Segment `{}` is pinned at ${:X}, but already pinned at ${:X}",
+                segment, pinned, other
+            ),
+            ParseErrorKind::Overlap {
+                ref segment,
+                ref other,
+                offset,
+                overrun,
+            } => write!(
+                f,
+                "Segment `{}` overlaps `{}` by {} byte(s) at ${:X}",
+                segment, other, overrun, offset
+            ),
             ParseErrorKind::Io(ref err) => err.fmt(f),
             ParseErrorKind::ParseInt(ref err) => err.fmt(f),
             _ => unreachable!(),
+        }?;
+        // where did it happen?
+        if let Some(l) = self.location {
+            write!(f, " at {}", l)?;
+        }
+        // list the alternatives
+        if let ParseErrorKind::NoMatchingForm {
+            ref forms, more, ..
+        } = *self.kind
+        {
+            if !forms.is_empty() {
+                write!(f, "\n    valid forms are:")?;
+            }
+            for form in forms.iter() {
+                write!(f, "\n        {}", form)?;
+            }
+            if more > 0 {
+                write!(f, "\n        ...and {} more", more)?;
+            }
+        }
+        // and how did we get there? a macro invoking itself would
+        // repeat the same step many times, so these are collapsed
+        let mut steps = self.backtrace.iter().peekable();
+        while let Some(step) = steps.next() {
+            write!(f, "\n    {}", step)?;
+            let mut repeats = 0;
+            while steps.peek() == Some(&step) {
+                steps.next();
+                repeats += 1;
+            }
+            if repeats > 0 {
+                write!(f, " (and {} more times)", repeats)?;
+            }
         }
+        Ok(())
     }
 }
 
@@ -209,7 +878,7 @@ impl From<ParseError> for io::Error {
     //==========================================================================
     fn from(err: ParseError) -> io::Error {
         //----------------------------------------------------------------------
-        io::Error::new(io::ErrorKind::Other, err)
+        io::Error::other(err)
     }
 }
 
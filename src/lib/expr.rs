@@ -8,19 +8,16 @@ use crate::token::Token;
 /// An Expression; a calculation consisting of a left-hand-side,
 /// a right-hand-side and an operator to combine the two.
 ///
-pub struct Expr<'token> {
-    pub left: Node<'token>,
+#[derive(Clone)]
+pub struct Expr {
+    pub left: Node,
     pub oper: Operator,
-    pub right: Node<'token>,
+    pub right: Node,
 }
 
-impl<'token> Expr<'token> {
+impl Expr {
     //==========================================================================
-    pub fn new(
-        left: Node<'token>,
-        oper: &Token<'token>,
-        right: Node<'token>,
-    ) -> Self {
+    pub fn new(left: Node, oper: &Token, right: Node) -> Self {
         //----------------------------------------------------------------------
         Self {
             // left hand side:
@@ -31,11 +28,19 @@ impl<'token> Expr<'token> {
             right,
         }
     }
+
+    /// Calculate the value of the expression, if both sides can be
+    /// calculated without outside information.
+    ///
+    pub fn eval(&self) -> Option<i64> {
+        //----------------------------------------------------------------------
+        self.oper.apply(self.left.eval()?, self.right.eval()?)
+    }
 }
 
 use std::fmt::{self, *};
 
-impl<'token> Display for Expr<'token> {
+impl Display for Expr {
     //==========================================================================
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         //----------------------------------------------------------------------
@@ -43,7 +48,7 @@ impl<'token> Display for Expr<'token> {
     }
 }
 
-impl<'token> Debug for Expr<'token> {
+impl Debug for Expr {
     //==========================================================================
     /// Debug printing an Expression includes the wrapping parentheses.
     ///
@@ -5,17 +5,17 @@ use crate::primitive::Primitive;
 use crate::r#struct::Struct;
 use crate::token::Token;
 
-pub struct Field<'token> {
+pub struct Field {
     /// Provide a reference back to the source code where the field was
     /// defined, for error messages when a value does not fit into a field.
-    _token: Option<Token<'token>>,
+    _token: Option<Token>,
     /// The data-type of the field, which can be a nested struct.
-    pub kind: FieldKind<'token>,
+    pub kind: FieldKind,
 }
 
 // properties for `Field`:
 //
-impl Field<'_> {
+impl Field {
     //==========================================================================
     pub fn cols(&self) -> usize {
         //----------------------------------------------------------------------
@@ -36,7 +36,7 @@ impl Field<'_> {
 
 use std::fmt::{self, *};
 
-impl<'token> Display for Field<'token> {
+impl Display for Field {
     //==========================================================================
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         //----------------------------------------------------------------------
@@ -44,7 +44,7 @@ impl<'token> Display for Field<'token> {
     }
 }
 
-impl<'token> Debug for Field<'token> {
+impl Debug for Field {
     //==========================================================================
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         //----------------------------------------------------------------------
@@ -52,15 +52,15 @@ impl<'token> Debug for Field<'token> {
     }
 }
 
-pub enum FieldKind<'token> {
+pub enum FieldKind {
     /// A native primitive type (on the target system),
     /// e.g. `byte`, `word`, `long` &c.
     Primitive(Primitive),
     /// Inception.
-    Struct(Box<Struct<'token>>),
+    Struct(Box<Struct>),
 }
 
-impl<'token> Display for FieldKind<'token> {
+impl Display for FieldKind {
     //==========================================================================
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         //----------------------------------------------------------------------
@@ -71,7 +71,7 @@ impl<'token> Display for FieldKind<'token> {
     }
 }
 
-impl<'token> Debug for FieldKind<'token> {
+impl Debug for FieldKind {
     //==========================================================================
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         //----------------------------------------------------------------------
@@ -84,7 +84,7 @@ impl<'token> Debug for FieldKind<'token> {
 
 use std::convert::From;
 
-impl From<Primitive> for Field<'_> {
+impl From<Primitive> for Field {
     //==========================================================================
     fn from(primitive: Primitive) -> Self {
         //----------------------------------------------------------------------
@@ -97,9 +97,9 @@ impl From<Primitive> for Field<'_> {
 
 use crate::node::{Node, NodeKind};
 
-impl<'token> From<&'token Node<'token>> for Field<'token> {
+impl From<&Node> for Field {
     //==========================================================================
-    fn from(node: &'token Node<'token>) -> Self {
+    fn from(node: &Node) -> Self {
         //----------------------------------------------------------------------
         match &node.token {
             // if the Node has a Token, we can convert from that
@@ -119,9 +119,9 @@ impl<'token> From<&'token Node<'token>> for Field<'token> {
 
 use crate::token::TokenKind;
 
-impl<'token> From<&'token Token<'token>> for Field<'token> {
+impl From<&Token> for Field {
     //==========================================================================
-    fn from(token: &'token Token<'token>) -> Self {
+    fn from(token: &Token) -> Self {
         //----------------------------------------------------------------------
         Self {
             kind: match token.kind() {
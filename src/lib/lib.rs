@@ -13,10 +13,15 @@ pub mod assembler;
 pub mod ast;
 pub mod atom;
 pub mod cell;
+pub mod doc;
 pub mod error;
 pub mod expr;
 pub mod field;
+pub mod linker;
 pub mod list;
+pub mod r#macro;
+pub mod map;
+pub mod module;
 pub mod node;
 pub mod object;
 pub mod ops;
@@ -24,14 +29,20 @@ pub mod parser;
 pub mod primitive;
 pub mod row;
 pub mod segment;
+pub mod shadow;
+pub mod source;
 pub mod r#struct;
 pub mod table;
 pub mod token;
 pub mod tokenizer;
 
 use crate::assembler::Assembler;
+use crate::error::ParseResult;
 
-pub fn assemble_str(source: &str) {
+/// Assemble source code, e.g. a line typed at the REPL; see
+/// `Assembler::assemble_str`.
+///
+pub fn assemble_str(source: &str) -> ParseResult<Assembler> {
     //--------------------------------------------------------------------------
-    Assembler::assemble_str(source);
+    Assembler::assemble_str(source)
 }
@@ -0,0 +1,1099 @@
+// retronym (C) copyright Kroc Camen 2017, 2018
+// BSD 2-clause licence; see LICENSE.TXT
+
+//! The **Linker** takes the Objects assembled and places their segments in
+//! memory, as described by a `MemoryMap`; each Label is then given its
+//! address and the values left for linking ("fixups"), e.g. `jp :main`,
+//! are calculated and patched into the packed data.
+//!
+//! Segments of the same name, from each Object, are placed together, in
+//! the order the Objects were assembled, and always in the same bank.
+//! Without a memory map, the segments are placed one after another in a
+//! single region.
+//!
+//! A Label is found within the Object that uses it, and then amongst the
+//! Labels exported by every Object linked, e.g. `export { :main }`. A Label
+//! that cannot be found, or that is exported more than once, is an error.
+//!
+//! A segment placed in a slot goes in one of its banks of ROM; its Labels
+//! have the address seen when that bank is paged in, and the bank number,
+//! for `bank(:label)`. A Label used from another bank of the same slot
+//! cannot be reached -- both banks cannot be paged in at once -- and is
+//! an error.
+//!
+//! Segments and Labels pinned by the memory map are placed first, at the
+//! location in the output given. The rest are then packed into the space
+//! left, in the order their names were first used: the segments of each
+//! name, kept together, go in the space -- of the area, or the banks of
+//! the slot, they are placed in -- that fits them with the least room to
+//! spare. The space left unused in the output is reported as gaps, see
+//! `Image::gaps`, and how full each area and bank is, see `Image::fills`.
+//!
+//! With the "relax" option enabled, a Macro form can give an alternative
+//! with `\relax`, e.g. `jr` for `jp`, and the site becomes a "joint" that
+//! takes whichever of the two fits. Each joint begins in its shortest
+//! form and the segments are placed; a joint whose values do not fit,
+//! e.g. a relative jump out of reach, is lengthened to its next form and
+//! the segments are placed again, until no joint changes. The sites
+//! that do not take their natural form are reported, see
+//! `Image::relaxations`.
+
+use crate::error::*;
+use crate::map::{AreaKind, MemoryMap, Pinned, Place};
+use crate::node::{Node, NodeKind};
+use crate::object::Object;
+use crate::primitive::fits;
+use crate::segment::{Fixup, Joint, Segment};
+use std::collections::HashMap;
+use std::mem;
+
+/// The name of the option that enables relaxation.
+pub const OPTION: &str = "relax";
+
+pub struct Linker<'a> {
+    /// The Objects to link, in the order assembled.
+    objects: &'a [Object],
+    /// Without a memory map, the address at which the first segment
+    /// is placed.
+    origin: usize,
+    /// Where in memory the segments are placed.
+    map: Option<&'a MemoryMap>,
+    /// The segments with joints, by the index of the Object and segment,
+    /// laid out with the form chosen for each joint.
+    relaxed: HashMap<(usize, usize), Segment>,
+}
+
+/// A segment of an Object, placed at an address.
+#[derive(Debug, Clone, Copy)]
+struct Section {
+    /// The index of the Object in those linked.
+    object: usize,
+    /// The index of the segment within the Object.
+    segment: usize,
+    /// The index of the area of the memory map the segment is placed in.
+    area: usize,
+    /// The address the segment is placed at.
+    address: usize,
+    /// Where the segment lies within the output of its area.
+    offset: usize,
+    /// For a slot, the number of the bank the segment is placed in.
+    bank: Option<usize>,
+}
+
+/// A Label, given its address.
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    /// The name of the Label, without the colon.
+    pub name: String,
+    /// The index of the Object, in those linked, that defines the Label.
+    pub object: usize,
+    /// The index of the area of the memory map the Label lies in.
+    pub area: usize,
+    /// The address of the Label, as seen when its bank is paged in.
+    pub address: usize,
+    /// Where the Label lies in the output; `None` for RAM.
+    pub load: Option<usize>,
+    /// The number of the bank of ROM the Label lies in, if any.
+    pub bank: Option<usize>,
+    /// Where in the source code the Label was defined.
+    pub location: Option<Location>,
+}
+
+/// Space in the output where nothing is placed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Gap {
+    /// The name of the area of the memory map the gap lies in.
+    pub area: String,
+    /// For a slot, the number of the bank the gap lies in.
+    pub bank: Option<usize>,
+    /// The address of the gap, as seen when its bank is paged in.
+    pub address: usize,
+    /// Where the gap lies in the output.
+    pub load: usize,
+    /// The size of the gap, in bytes.
+    pub size: usize,
+}
+
+/// How much of an area of memory, or a bank, is used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fill {
+    /// The name of the area of the memory map.
+    pub area: String,
+    /// For a slot, the number of the bank.
+    pub bank: Option<usize>,
+    /// The number of bytes placed, less any padding for alignment.
+    pub used: usize,
+    /// The size of the area, or bank, in bytes.
+    pub size: usize,
+}
+
+/// A site that takes a form other than its natural form once linked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Relaxation {
+    /// The index of the Object, in those linked, the site lies in.
+    pub object: usize,
+    /// The address of the site, as seen when its bank is paged in.
+    pub address: usize,
+    /// Where the site lies in the output; `None` for RAM.
+    pub load: Option<usize>,
+    /// The length of the natural form, in bytes.
+    pub from: usize,
+    /// The length of the form taken, in bytes.
+    pub to: usize,
+    /// Where in the source code the site is.
+    pub location: Option<Location>,
+}
+
+/// The result of linking; the packed binary data, placed in memory.
+#[derive(Debug)]
+pub struct Image {
+    /// The address of the first byte of the data.
+    origin: usize,
+    /// The packed binary data of each area of the memory map written to
+    /// the output, one after another, with every value patched.
+    data: Vec<u8>,
+    /// Every Label of every Object, with its address.
+    symbols: Vec<Symbol>,
+    /// The space in the output where nothing is placed.
+    gaps: Vec<Gap>,
+    /// How much of each area of memory, and each bank, is used.
+    fills: Vec<Fill>,
+    /// The sites that take a form other than their natural form.
+    relaxations: Vec<Relaxation>,
+}
+
+impl<'a> Linker<'a> {
+    //==========================================================================
+    /// Create a Linker for the Objects given, in the order assembled;
+    /// the segments are placed from address zero.
+    ///
+    pub fn new(objects: &'a [Object]) -> Self {
+        //----------------------------------------------------------------------
+        Self {
+            objects,
+            origin: 0,
+            map: None,
+            relaxed: HashMap::new(),
+        }
+    }
+
+    /// Without a memory map, place the first segment at the given address.
+    ///
+    pub fn origin(mut self, origin: usize) -> Self {
+        //----------------------------------------------------------------------
+        self.origin = origin;
+        self
+    }
+
+    /// Place the segments in memory as described by the memory map given.
+    ///
+    pub fn map(mut self, map: &'a MemoryMap) -> Self {
+        //----------------------------------------------------------------------
+        self.map = Some(map);
+        self
+    }
+
+    /// Link the Objects, placing their segments and patching the values
+    /// left for linking. Returns a `ParseError` if a segment does not fit
+    /// in memory, or a Label cannot be found, is exported more than once,
+    /// or its value does not fit.
+    ///
+    pub fn link(&self) -> ParseResult<Image> {
+        //----------------------------------------------------------------------
+        let flat;
+        let map = match self.map {
+            Some(map) if !map.is_empty() => map,
+            _ => {
+                flat = MemoryMap::flat(self.origin);
+                &flat
+            }
+        };
+
+        // each joint begins in its shortest form
+        let mut forms = HashMap::new();
+        for (i, object) in self.objects.iter().enumerate() {
+            for (j, segment) in object.segments().iter().enumerate() {
+                if !segment.joints().is_empty() {
+                    let shortest: Vec<usize> = segment
+                        .joints()
+                        .iter()
+                        .map(|joint| Self::order(joint)[0])
+                        .collect();
+                    forms.insert((i, j), shortest);
+                }
+            }
+        }
+
+        // lengthening a joint moves what follows it, which can put another
+        // out of reach, so the segments are placed again until none change
+        loop {
+            let linker = Linker {
+                objects: self.objects,
+                origin: self.origin,
+                map: self.map,
+                relaxed: forms
+                    .iter()
+                    .map(|(&(i, j), f)| {
+                        ((i, j), self.objects[i].segments()[j].relax(f))
+                    })
+                    .collect(),
+            };
+            let sections = linker.place(map)?;
+            let symbols = linker.symbols(map, &sections);
+            let exports = linker.exports(&symbols)?;
+            if linker.lengthen(map, &sections, &symbols, &exports, &mut forms) {
+                continue;
+            }
+            let data = linker.write(map, &sections, &symbols, &exports)?;
+            return Ok(Image {
+                origin: map
+                    .output()
+                    .first()
+                    .map_or(0, |&i| map.areas()[i].start),
+                data,
+                gaps: linker.gaps(map, &sections),
+                fills: linker.fills(map, &sections),
+                relaxations: linker.relaxations(map, &sections),
+                symbols,
+            });
+        }
+    }
+
+    /// Give each Label of each segment placed its address.
+    ///
+    fn symbols(&self, map: &MemoryMap, sections: &[Section]) -> Vec<Symbol> {
+        //----------------------------------------------------------------------
+        let mut symbols = Vec::new();
+        for section in sections.iter() {
+            let segment = self.segment(section);
+            let load = map.load(section.area);
+            for label in segment.labels() {
+                let load = load.map(|l| l + section.offset + label.offset);
+                // a Label in a region lies in the bank
+                // that its place in the output falls in
+                let bank =
+                    section.bank.or_else(|| Some(load? / map.bank_size()?));
+                symbols.push(Symbol {
+                    name: label.name.clone(),
+                    object: section.object,
+                    area: section.area,
+                    address: section.address + label.offset,
+                    load,
+                    bank,
+                    location: label.location,
+                });
+            }
+        }
+        symbols
+    }
+
+    /// Lengthen each joint whose values do not fit the form it takes to
+    /// its next longer form; the longest is kept regardless. Returns
+    /// whether any joint was lengthened.
+    ///
+    fn lengthen(
+        &self,
+        map: &MemoryMap,
+        sections: &[Section],
+        symbols: &[Symbol],
+        exports: &HashMap<&str, &Symbol>,
+        forms: &mut HashMap<(usize, usize), Vec<usize>>,
+    ) -> bool {
+        //----------------------------------------------------------------------
+        let mut is_changed = false;
+        for section in sections.iter() {
+            let find =
+                |name: &str| Self::find(symbols, exports, section.object, name);
+            let segment = self.segment(section);
+            for (k, joint) in segment.joints().iter().enumerate() {
+                let order = Self::order(joint);
+                let next = order
+                    .iter()
+                    .position(|&f| f == joint.form)
+                    .and_then(|p| order.get(p + 1));
+                let next = match next {
+                    Some(&next) => next,
+                    None => continue,
+                };
+                let is_fit =
+                    joint.forms[joint.form].fixups().iter().all(|fixup| {
+                        let at = joint.offset + fixup.offset;
+                        match Self::value(fixup, at, &find, section, map) {
+                            Ok(value) => Self::is_in_range(fixup, value),
+                            Err(_) => false,
+                        }
+                    });
+                if !is_fit {
+                    if let Some(f) =
+                        forms.get_mut(&(section.object, section.segment))
+                    {
+                        f[k] = next;
+                        is_changed = true;
+                    }
+                }
+            }
+        }
+        is_changed
+    }
+
+    /// The forms of a joint, by index, from shortest to longest.
+    ///
+    fn order(joint: &Joint) -> Vec<usize> {
+        //----------------------------------------------------------------------
+        let mut order: Vec<usize> = (0..joint.forms.len()).collect();
+        order.sort_by_key(|&f| joint.forms[f].len());
+        order
+    }
+
+    /// The sites that take a form other than their natural form.
+    ///
+    fn relaxations(
+        &self,
+        map: &MemoryMap,
+        sections: &[Section],
+    ) -> Vec<Relaxation> {
+        //----------------------------------------------------------------------
+        let mut relaxations = Vec::new();
+        for section in sections.iter() {
+            let load = map.load(section.area);
+            for joint in self.segment(section).joints() {
+                if joint.form == 0 {
+                    continue;
+                }
+                relaxations.push(Relaxation {
+                    object: section.object,
+                    address: section.address + joint.offset,
+                    load: load.map(|l| l + section.offset + joint.offset),
+                    from: joint.forms[0].len(),
+                    to: joint.forms[joint.form].len(),
+                    location: joint.location,
+                });
+            }
+        }
+        relaxations
+    }
+
+    /// Fill each area of memory with the packed data of the segments
+    /// placed there, patching the values left for linking, and write the
+    /// areas to the output. Returns a `ParseError` if a Label cannot be
+    /// found, or its value does not fit.
+    ///
+    fn write(
+        &self,
+        map: &MemoryMap,
+        sections: &[Section],
+        symbols: &[Symbol],
+        exports: &HashMap<&str, &Symbol>,
+    ) -> ParseResult<Vec<u8>> {
+        //----------------------------------------------------------------------
+        // the packed data of each area, up to the last byte placed
+        let mut contents = vec![Vec::new(); map.areas().len()];
+        for section in sections.iter() {
+            let segment = self.segment(section);
+            let area = &map.areas()[section.area];
+            let data = &mut contents[section.area];
+            let start = section.offset;
+            let end = start + segment.len();
+            if data.len() < end {
+                data.resize(end, area.fill.unwrap_or(0));
+            }
+            data[start..end].copy_from_slice(&segment.to_bytes());
+
+            // find a Label used within the segment:
+            // its own Object's first, then those exported
+            let find =
+                |name: &str| Self::find(symbols, exports, section.object, name);
+            // the values within the form each joint takes, too; each
+            // is given with the offset of the segment it lies within
+            let joints = segment.joints().iter().flat_map(|joint| {
+                joint.forms[joint.form]
+                    .fixups()
+                    .iter()
+                    .map(move |fixup| (joint.offset, fixup))
+            });
+            let fixups = segment.fixups().iter().map(|f| (0, f));
+            for (base, fixup) in fixups.chain(joints) {
+                let at = base + fixup.offset;
+                let value = Self::value(fixup, at, &find, section, map)
+                    .map_err(|e| e.expanded(&fixup.value.backtrace))?;
+                if !Self::is_in_range(fixup, value) {
+                    let site = section.address + base + fixup.start;
+                    return Err(Self::overflow(
+                        fixup,
+                        site,
+                        section.address + at,
+                        value,
+                    )
+                    .expanded(&fixup.value.backtrace));
+                }
+                for i in 0..fixup.bits / 8 {
+                    data[start + at + i] = (value >> (i * 8)) as u8;
+                }
+            }
+        }
+
+        // each area is written to the output at its full size, but for
+        // the last, which ends with its last byte placed, unless filled;
+        // the banks of a slot may be padded to begin on a whole bank
+        let mut data = Vec::new();
+        for (n, &i) in map.output().iter().enumerate() {
+            let area = &map.areas()[i];
+            data.resize(map.load(i).unwrap_or(0), area.fill.unwrap_or(0));
+            let mut bytes = mem::take(&mut contents[i]);
+            if area.fill.is_some() || n + 1 < map.output().len() {
+                bytes.resize(area.output_size(), area.fill.unwrap_or(0));
+            }
+            data.extend(bytes);
+        }
+        Ok(data)
+    }
+
+    /// Give each segment of each Object an address within the area of
+    /// memory it is placed in; those pinned first, then the rest around
+    /// them. Returns a `ParseError` if a segment has no area to be placed
+    /// in, does not fit, is pinned out of alignment, or overlaps another
+    /// pinned.
+    ///
+    fn place(&self, map: &MemoryMap) -> ParseResult<Vec<Section>> {
+        //----------------------------------------------------------------------
+        let mut names: Vec<&str> = Vec::new();
+        for object in self.objects.iter() {
+            for segment in object.segments() {
+                if !names.contains(&segment.name()) {
+                    names.push(segment.name());
+                }
+            }
+        }
+
+        // the spans of addresses used in each area; for a slot, in each bank
+        let mut used: Vec<Vec<Vec<(usize, usize)>>> = map
+            .areas()
+            .iter()
+            .map(|a| vec![Vec::new(); a.banks.max(1)])
+            .collect();
+        let mut sections = Vec::new();
+
+        // the segments of the same name, from each Object, are
+        // pinned one after another from the location given
+        let pins = self.pins(map)?;
+        let mut spans = Vec::new();
+        for &(name, offset, location) in pins.iter() {
+            let (area, k, address) = map.locate(offset).ok_or_else(|| {
+                parse_error(ParseErrorKind::PinOutside(offset)).at(location)
+            })?;
+            if let Some(bank) = map.first_bank(area).map(|n| n + k) {
+                let slot = &map.areas()[area];
+                if slot.reserved.contains(&bank) {
+                    return Err(parse_error(ParseErrorKind::ReservedBank {
+                        bank,
+                        slot: slot.name.clone(),
+                    })
+                    .at(location));
+                }
+            }
+            let (group, len, align) = self.group(name);
+            if address % align != 0 {
+                return Err(parse_error(ParseErrorKind::PinAlign {
+                    segment: name.to_string(),
+                    align,
+                    address,
+                })
+                .at(location));
+            }
+            let end = address + len;
+            if end > map.areas()[area].end() {
+                return Err(parse_error(ParseErrorKind::AreaOverflow {
+                    segment: name.to_string(),
+                    area: map.areas()[area].name.clone(),
+                    overrun: end - map.areas()[area].end(),
+                })
+                .at(location));
+            }
+            for &(i, j, rel) in group.iter() {
+                sections.push(Section {
+                    object: i,
+                    segment: j,
+                    area,
+                    address: address + rel,
+                    offset: offset + rel - map.load(area).unwrap_or(0),
+                    bank: map.first_bank(area).map(|n| n + k),
+                });
+            }
+            used[area][k].push((address, end));
+            spans.push((offset, offset + len, name, location));
+        }
+        spans.sort_by_key(|&(start, ..)| start);
+        for pair in spans.windows(2) {
+            let (a, b) = (&pair[0], &pair[1]);
+            if b.0 < a.1 {
+                return Err(parse_error(ParseErrorKind::Overlap {
+                    segment: b.2.to_string(),
+                    other: a.2.to_string(),
+                    offset: b.0,
+                    overrun: a.1.min(b.1) - b.0,
+                })
+                .at(b.3));
+            }
+        }
+
+        for name in names {
+            if pins.iter().any(|&(n, ..)| n == name) {
+                continue;
+            }
+            let area = map.place(name).ok_or_else(|| {
+                parse_error(ParseErrorKind::Unplaced(name.to_string()))
+            })?;
+            let first = map.first_bank(area);
+            let banks = Self::banks(map, area, map.placement(name))?;
+            let (group, len, align) = self.group(name);
+            let (k, address) =
+                Self::fit(map, area, &used[area], &banks, name, len, align)?;
+            used[area][k].push((address, address + len));
+            let size = map.areas()[area].size;
+            for &(i, j, rel) in group.iter() {
+                sections.push(Section {
+                    object: i,
+                    segment: j,
+                    area,
+                    address: address + rel,
+                    offset: k * size + address + rel - map.areas()[area].start,
+                    bank: first.map(|n| n + k),
+                });
+            }
+        }
+        Ok(sections)
+    }
+
+    /// The segments pinned by the memory map, by name, with where in the
+    /// output each begins. Returns a `ParseError` if a segment or Label
+    /// pinned cannot be found, or is pinned in two places.
+    ///
+    fn pins(
+        &self,
+        map: &MemoryMap,
+    ) -> ParseResult<Vec<(&'a str, usize, Option<Location>)>> {
+        //----------------------------------------------------------------------
+        let mut pins: Vec<(&str, usize, Option<Location>)> = Vec::new();
+        for pin in map.pins() {
+            let (name, within) = match pin.pinned {
+                Pinned::Segment(ref name) => {
+                    let found = self
+                        .objects
+                        .iter()
+                        .flat_map(|o| o.segments())
+                        .find(|s| s.name() == name)
+                        .ok_or_else(|| {
+                            parse_error(ParseErrorKind::UndefinedSegment(
+                                name.clone(),
+                            ))
+                            .at(pin.location)
+                        })?;
+                    (found.name(), 0)
+                }
+                Pinned::Label(ref name) => {
+                    let (i, segment, offset) =
+                        self.find_label(name).ok_or_else(|| {
+                            parse_error(ParseErrorKind::UndefinedLabel(
+                                name.clone(),
+                            ))
+                            .at(pin.location)
+                        })?;
+                    let (group, ..) = self.group(segment);
+                    let rel = group
+                        .iter()
+                        .find(|&&(o, ..)| o == i)
+                        .map_or(0, |&(.., rel)| rel);
+                    (segment, rel + offset)
+                }
+            };
+            let base = pin.offset.checked_sub(within).ok_or_else(|| {
+                parse_error(ParseErrorKind::PinOutside(pin.offset))
+                    .at(pin.location)
+            })?;
+            match pins.iter().find(|&&(n, ..)| n == name) {
+                Some(&(_, other, _)) if other != base => {
+                    return Err(match pin.pinned {
+                        Pinned::Label(ref label) => {
+                            parse_error(ParseErrorKind::PinMismatch {
+                                label: label.clone(),
+                                pinned: pin.offset,
+                                placed: other + within,
+                            })
+                        }
+                        Pinned::Segment(_) => {
+                            parse_error(ParseErrorKind::PinnedTwice {
+                                segment: name.to_string(),
+                                pinned: pin.offset,
+                                other,
+                            })
+                        }
+                    }
+                    .at(pin.location));
+                }
+                Some(_) => {}
+                None => pins.push((name, base, pin.location)),
+            }
+        }
+        Ok(pins)
+    }
+
+    /// Find a Label to pin amongst those exported, then in the first
+    /// Object to define it; returning the index of the Object, the name
+    /// of the segment and the Label's offset within it.
+    ///
+    fn find_label(&self, name: &str) -> Option<(usize, &'a str, usize)> {
+        //----------------------------------------------------------------------
+        let export = format!(":{}", name);
+        let found = || {
+            self.objects.iter().enumerate().filter_map(move |(i, o)| {
+                o.segments().iter().enumerate().find_map(|(j, s)| {
+                    let segment = self.segment_of(i, j);
+                    let label =
+                        segment.labels().iter().find(|l| l.name == name);
+                    label.map(|l| (i, s.name(), l.offset))
+                })
+            })
+        };
+        found()
+            .find(|&(i, ..)| self.objects[i].is_exported(&export))
+            .or_else(|| found().next())
+    }
+
+    /// The segments of the given name from each Object, placed one after
+    /// another; the index of each Object and segment, with the segment's
+    /// offset from the first, the length of them all, and the alignment
+    /// the first must be placed at for each to be aligned.
+    ///
+    fn group(&self, name: &str) -> (Vec<(usize, usize, usize)>, usize, usize) {
+        //----------------------------------------------------------------------
+        let mut group = Vec::new();
+        let mut len: usize = 0;
+        let mut align = 1;
+        for (i, object) in self.objects.iter().enumerate() {
+            let found = object.segments().iter().position(|s| s.name() == name);
+            if let Some(j) = found {
+                let segment = self.segment_of(i, j);
+                let rel = len.next_multiple_of(segment.align());
+                group.push((i, j, rel));
+                len = rel + segment.len();
+                align = align.max(segment.align());
+            }
+        }
+        (group, len, align)
+    }
+
+    /// The space in the output where nothing is placed; the space after
+    /// the last byte placed is not written, unless the area is filled.
+    ///
+    fn gaps(&self, map: &MemoryMap, sections: &[Section]) -> Vec<Gap> {
+        //----------------------------------------------------------------------
+        let mut gaps = Vec::new();
+        for (n, &i) in map.output().iter().enumerate() {
+            let area = &map.areas()[i];
+            let first = map.first_bank(i);
+            let count = first.map_or(1, |_| area.banks);
+            for k in 0..count {
+                let bank = first.map(|f| f + k);
+                let mut spans: Vec<(usize, usize)> = sections
+                    .iter()
+                    .filter(|s| s.area == i && s.bank == bank)
+                    .map(|s| (s.address, s.address + self.segment(s).len()))
+                    .filter(|&(start, end)| end > start)
+                    .collect();
+                spans.sort();
+                let is_last = n + 1 == map.output().len() && k + 1 == count;
+                if area.fill.is_some() || !is_last {
+                    spans.push((area.end(), area.end()));
+                }
+                let mut address = area.start;
+                for (start, end) in spans {
+                    if start > address {
+                        gaps.push(Gap {
+                            area: area.name.clone(),
+                            bank,
+                            address,
+                            load: map.load(i).unwrap_or(0)
+                                + k * area.size
+                                + address
+                                - area.start,
+                            size: start - address,
+                        });
+                    }
+                    address = address.max(end);
+                }
+            }
+        }
+        gaps
+    }
+
+    /// How much of each area of memory is used; for a slot, each bank.
+    ///
+    fn fills(&self, map: &MemoryMap, sections: &[Section]) -> Vec<Fill> {
+        //----------------------------------------------------------------------
+        let mut fills = Vec::new();
+        for (i, area) in map.areas().iter().enumerate() {
+            let first = map.first_bank(i);
+            let count = first.map_or(1, |_| area.banks);
+            for k in 0..count {
+                let bank = first.map(|f| f + k);
+                fills.push(Fill {
+                    area: area.name.clone(),
+                    bank,
+                    used: sections
+                        .iter()
+                        .filter(|s| s.area == i && s.bank == bank)
+                        .map(|s| self.segment(s).len())
+                        .sum(),
+                    size: area.size,
+                });
+            }
+        }
+        fills
+    }
+
+    /// The banks, by index within the slot, that a segment may be placed
+    /// in, less those reserved; for an area that is not a slot, only the
+    /// area itself. Returns a `ParseError` if the slot has no banks, or the
+    /// bank a segment is placed in is not one of the slot's banks, or is
+    /// reserved.
+    ///
+    fn banks(
+        map: &MemoryMap,
+        area: usize,
+        place: Option<&Place>,
+    ) -> ParseResult<Vec<usize>> {
+        //----------------------------------------------------------------------
+        let slot = &map.areas()[area];
+        if slot.kind == AreaKind::Slot && slot.banks == 0 {
+            return Err(parse_error(ParseErrorKind::NoBanks(
+                slot.name.clone(),
+            ))
+            .at(place.and_then(|p| p.location)));
+        }
+        match (map.first_bank(area), place.and_then(|p| p.bank)) {
+            (Some(first), Some(bank)) => {
+                if bank < first || bank - first >= slot.banks {
+                    return Err(parse_error(ParseErrorKind::UndefinedBank {
+                        bank,
+                        slot: slot.name.clone(),
+                    })
+                    .at(place.and_then(|p| p.location)));
+                }
+                if slot.reserved.contains(&bank) {
+                    return Err(parse_error(ParseErrorKind::ReservedBank {
+                        bank,
+                        slot: slot.name.clone(),
+                    })
+                    .at(place.and_then(|p| p.location)));
+                }
+                Ok(vec![bank - first])
+            }
+            (Some(first), None) => Ok((0..slot.banks)
+                .filter(|k| !slot.reserved.contains(&(first + k)))
+                .collect()),
+            _ => Ok(vec![0]),
+        }
+    }
+
+    /// Find room for the segments of the given name, of the length and
+    /// alignment given, in the banks given; of the spaces left unused,
+    /// the one that leaves the least room spare. Returns the index of the
+    /// bank and the address of the segments, or a `ParseError` if they
+    /// do not fit in any space.
+    ///
+    fn fit(
+        map: &MemoryMap,
+        area: usize,
+        used: &[Vec<(usize, usize)>],
+        banks: &[usize],
+        name: &str,
+        len: usize,
+        align: usize,
+    ) -> ParseResult<(usize, usize)> {
+        //----------------------------------------------------------------------
+        let (start, end) = (map.areas()[area].start, map.areas()[area].end());
+        let mut best: Option<(usize, usize, usize)> = None;
+        let mut overrun = len;
+        for &k in banks.iter() {
+            let mut spans = used[k].clone();
+            spans.sort();
+            spans.push((end, end));
+            // the space between each span used
+            let mut from = start;
+            for (next, after) in spans {
+                let address = from.next_multiple_of(align);
+                if address + len <= next {
+                    let spare = next - (address + len);
+                    if best.is_none_or(|(s, ..)| spare < s) {
+                        best = Some((spare, k, address));
+                    }
+                } else if next == end {
+                    overrun = overrun.min(address + len - end);
+                }
+                from = from.max(after);
+            }
+        }
+        match best {
+            Some((_, k, address)) => Ok((k, address)),
+            None => Err(parse_error(ParseErrorKind::AreaOverflow {
+                segment: name.to_string(),
+                area: map.areas()[area].name.clone(),
+                overrun,
+            })),
+        }
+    }
+
+    /// The Labels exported by each Object, by name.
+    ///
+    fn exports<'s>(
+        &self,
+        symbols: &'s [Symbol],
+    ) -> ParseResult<HashMap<&'s str, &'s Symbol>> {
+        //----------------------------------------------------------------------
+        let mut exports = HashMap::new();
+        for symbol in symbols.iter() {
+            let object = &self.objects[symbol.object];
+            if !object.is_exported(&format!(":{}", symbol.name)) {
+                continue;
+            }
+            if exports.insert(symbol.name.as_str(), symbol).is_some() {
+                return Err(parse_error(ParseErrorKind::DuplicateSymbol(
+                    symbol.name.clone(),
+                ))
+                .at(symbol.location));
+            }
+        }
+        Ok(exports)
+    }
+
+    /// The segment of an Object that a section places.
+    ///
+    fn segment(&self, section: &Section) -> &Segment {
+        //----------------------------------------------------------------------
+        self.segment_of(section.object, section.segment)
+    }
+
+    /// A segment of an Object, by index; laid out with the form chosen
+    /// for each joint, if it has any.
+    ///
+    fn segment_of(&self, object: usize, segment: usize) -> &Segment {
+        //----------------------------------------------------------------------
+        self.relaxed
+            .get(&(object, segment))
+            .unwrap_or(&self.objects[object].segments()[segment])
+    }
+
+    /// Find a Label used within an Object: those it defines first,
+    /// then those exported.
+    ///
+    fn find<'s>(
+        symbols: &'s [Symbol],
+        exports: &HashMap<&str, &'s Symbol>,
+        object: usize,
+        name: &str,
+    ) -> Option<&'s Symbol> {
+        //----------------------------------------------------------------------
+        symbols
+            .iter()
+            .find(|s| s.object == object && s.name == name)
+            .or_else(|| exports.get(name).copied())
+    }
+
+    /// Calculate the value of a fixup, lying at the offset given within
+    /// the section's segment. A relative value is the distance from the
+    /// address that follows it; a number is the distance itself.
+    ///
+    fn value<'s, F>(
+        fixup: &Fixup,
+        at: usize,
+        find: &F,
+        site: &Section,
+        map: &MemoryMap,
+    ) -> ParseResult<i64>
+    where
+        F: Fn(&str) -> Option<&'s Symbol>,
+    {
+        //----------------------------------------------------------------------
+        match fixup.value.kind {
+            NodeKind::Relative(ref target) if target.is_static => {
+                target.eval().ok_or_else(|| {
+                    parse_error(ParseErrorKind::NotStatic).at(target.location())
+                })
+            }
+            NodeKind::Relative(ref target) => {
+                let target = Self::resolve(target, find, site, map)?;
+                let next = site.address + at + fixup.bits / 8;
+                Ok(target - next as i64)
+            }
+            _ => Self::resolve(&fixup.value, find, site, map),
+        }
+    }
+
+    /// The error for a value of a fixup that does not fit its width, the
+    /// fixup lying at the address given, within the statement at the site
+    /// given. A relative branch names its site and its target, given where
+    /// the branch is used.
+    ///
+    fn overflow(
+        fixup: &Fixup,
+        site: usize,
+        at: usize,
+        value: i64,
+    ) -> ParseError {
+        //----------------------------------------------------------------------
+        match fixup.value.kind {
+            NodeKind::Relative(ref target) => {
+                let next = (at + fixup.bits / 8) as i64;
+                parse_error(ParseErrorKind::BranchRange {
+                    site,
+                    target: target.to_string(),
+                    address: next + value,
+                    distance: value,
+                    bits: fixup.bits,
+                })
+                .at(target.location())
+            }
+            _ => parse_error(ParseErrorKind::Overflow {
+                value,
+                bits: fixup.bits,
+            })
+            .at(fixup.value.location()),
+        }
+    }
+
+    /// Does the value of a fixup fit its width? A relative value is signed.
+    ///
+    fn is_in_range(fixup: &Fixup, value: i64) -> bool {
+        //----------------------------------------------------------------------
+        if fixup.value.is_relative() {
+            let half = 1 << (fixup.bits - 1);
+            value >= -half && value < half
+        } else {
+            fits(value, fixup.bits)
+        }
+    }
+
+    /// Calculate a value left for linking within the section given,
+    /// finding each Label within it with the function given. Returns a
+    /// `ParseError` if a Label cannot be found, or lies in a bank that
+    /// cannot be paged in alongside the section's.
+    ///
+    fn resolve<'s, F>(
+        node: &Node,
+        find: &F,
+        site: &Section,
+        map: &MemoryMap,
+    ) -> ParseResult<i64>
+    where
+        F: Fn(&str) -> Option<&'s Symbol>,
+    {
+        //----------------------------------------------------------------------
+        let symbol = |name: &String| {
+            find(name).ok_or_else(|| {
+                parse_error(ParseErrorKind::UndefinedLabel(name.clone()))
+                    .at(node.location())
+            })
+        };
+        match node.kind {
+            NodeKind::Label(ref name) => {
+                let symbol = symbol(name)?;
+                match (site.bank, symbol.bank) {
+                    (Some(from), Some(bank))
+                        if symbol.area == site.area && bank != from =>
+                    {
+                        Err(parse_error(ParseErrorKind::CrossBank {
+                            label: name.clone(),
+                            bank,
+                            from,
+                            slot: map.areas()[site.area].name.clone(),
+                        })
+                        .at(node.location()))
+                    }
+                    _ => Ok(symbol.address as i64),
+                }
+            }
+            NodeKind::Bank(ref name) => match symbol(name)?.bank {
+                Some(bank) => Ok(bank as i64),
+                None => {
+                    Err(parse_error(ParseErrorKind::NotBanked(name.clone()))
+                        .at(node.location()))
+                }
+            },
+            NodeKind::Expr(ref expr) => {
+                let left = Self::resolve(&expr.left, find, site, map)?;
+                let right = Self::resolve(&expr.right, find, site, map)?;
+                expr.oper.apply(left, right).ok_or_else(|| {
+                    parse_error(ParseErrorKind::NotStatic).at(node.location())
+                })
+            }
+            _ => node.eval().ok_or_else(|| {
+                parse_error(ParseErrorKind::NotStatic).at(node.location())
+            }),
+        }
+    }
+}
+
+impl Image {
+    //==========================================================================
+    /// The address of the first byte of the data.
+    ///
+    pub fn origin(&self) -> usize {
+        //----------------------------------------------------------------------
+        self.origin
+    }
+
+    /// The packed binary data, with every value patched.
+    ///
+    pub fn data(&self) -> &[u8] {
+        //----------------------------------------------------------------------
+        &self.data
+    }
+
+    /// Every Label of every Object, with its address,
+    /// in the order the segments were placed.
+    ///
+    pub fn symbols(&self) -> &[Symbol] {
+        //----------------------------------------------------------------------
+        &self.symbols
+    }
+
+    /// The space in the output where nothing is placed, in order.
+    ///
+    pub fn gaps(&self) -> &[Gap] {
+        //----------------------------------------------------------------------
+        &self.gaps
+    }
+
+    /// How much of each area of memory is used, in the order given by
+    /// the memory map; for a slot, each of its banks in order.
+    ///
+    pub fn fills(&self) -> &[Fill] {
+        //----------------------------------------------------------------------
+        &self.fills
+    }
+
+    /// The sites that take a form other than their natural form, in the
+    /// order the segments were placed.
+    ///
+    pub fn relaxations(&self) -> &[Relaxation] {
+        //----------------------------------------------------------------------
+        &self.relaxations
+    }
+
+    /// The address of a Label defined by the Object of the given index,
+    /// in those linked.
+    ///
+    pub fn address(&self, object: usize, name: &str) -> Option<usize> {
+        //----------------------------------------------------------------------
+        self.symbol(object, name).map(|s| s.address)
+    }
+
+    /// A Label defined by the Object of the given index, in those linked.
+    ///
+    pub fn symbol(&self, object: usize, name: &str) -> Option<&Symbol> {
+        //----------------------------------------------------------------------
+        self.symbols
+            .iter()
+            .find(|s| s.object == object && s.name == name)
+    }
+}
@@ -10,13 +10,13 @@
 
 use crate::node::Node;
 
-#[derive(Debug)]
-pub struct List<'token> {
-    nodes: Vec<Node<'token>>,
+#[derive(Debug, Clone)]
+pub struct List {
+    nodes: Vec<Node>,
     is_static: bool,
 }
 
-impl Default for List<'_> {
+impl Default for List {
     //==========================================================================
     /// Get a default list (no nodes).
     ///
@@ -31,7 +31,7 @@ impl Default for List<'_> {
 
 use std::fmt::{self, *};
 
-impl Display for List<'_> {
+impl Display for List {
     //==========================================================================
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         //----------------------------------------------------------------------
@@ -49,22 +49,22 @@ impl Display for List<'_> {
 
 use std::slice;
 
-impl<'token> IntoIterator for &'token List<'token> {
+impl<'a> IntoIterator for &'a List {
     //==========================================================================
-    type Item = &'token Node<'token>;
-    type IntoIter = slice::Iter<'token, Node<'token>>;
+    type Item = &'a Node;
+    type IntoIter = slice::Iter<'a, Node>;
 
     /// Get an iterator over the nodes in the list.
     ///
-    fn into_iter(self) -> slice::Iter<'token, Node<'token>> {
+    fn into_iter(self) -> slice::Iter<'a, Node> {
         //----------------------------------------------------------------------
         self.nodes.iter()
     }
 }
 
-impl<'token> List<'token> {
+impl List {
     //==========================================================================
-    pub fn push(&mut self, node: Node<'token>) {
+    pub fn push(&mut self, node: Node) {
         //----------------------------------------------------------------------
         // if a node is added that is not static, then the whole list is now
         // considered non static.
@@ -75,6 +75,48 @@ impl<'token> List<'token> {
         self.nodes.push(node);
     }
 
+    /// Get an iterator over the nodes in the list. Unlike `into_iter`,
+    /// the borrow of the list need not live as long as the source code.
+    ///
+    pub fn iter(&self) -> slice::Iter<'_, Node> {
+        //----------------------------------------------------------------------
+        self.nodes.iter()
+    }
+
+    /// Get an iterator over the nodes in the list, that can change them.
+    ///
+    pub fn iter_mut(&mut self) -> slice::IterMut<'_, Node> {
+        //----------------------------------------------------------------------
+        self.nodes.iter_mut()
+    }
+
+    /// Split the list at the given index, returning the nodes from the
+    /// index onward and leaving those before.
+    ///
+    pub fn split_off(&mut self, at: usize) -> List {
+        //----------------------------------------------------------------------
+        let nodes = self.nodes.split_off(at);
+        self.is_static = self.nodes.iter().all(|n| n.is_static);
+        List {
+            is_static: nodes.iter().all(|n| n.is_static),
+            nodes,
+        }
+    }
+
+    /// The number of nodes in the list.
+    ///
+    pub fn len(&self) -> usize {
+        //----------------------------------------------------------------------
+        self.nodes.len()
+    }
+
+    /// Is the list empty?
+    ///
+    pub fn is_empty(&self) -> bool {
+        //----------------------------------------------------------------------
+        self.nodes.is_empty()
+    }
+
     /// Does this list contain only static nodes? A static node is one that
     /// does not require external information to compute a value.
     ///
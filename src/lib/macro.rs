@@ -0,0 +1,988 @@
+// retronym (C) copyright Kroc Camen 2017, 2018
+// BSD 2-clause licence; see LICENSE.TXT
+
+//! **Macros** are named lists of statements. Invoking a macro copies its body
+//! in place of the invocation, replacing the parameters with the arguments
+//! given. Macros are expanded before any data is packed, so objects have no
+//! internal notion of macros.
+//!
+//! A macro consumes the list elements between it and the next macro as its
+//! arguments, so there's no call syntax; e.g. `ld A, B`.
+//!
+//! ## Examples ##
+//!
+//! Parameters may be referred to by their number (from 1), or by name if
+//! named in the definition:
+//!
+//! ```
+//! macro   adc#    \1      { byte $69, \1 }
+//! macro   ld      \to, \from
+//! {
+//!         byte \to, \2
+//! }
+//! ```
+//!
+//! A macro can be defined more than once with different patterns of
+//! arguments -- "forms" -- to dispatch on the Atoms, numbers and
+//! indirection (parentheses) given. The first form that matches is expanded.
+//! Where any form includes Atoms, numbers or parentheses, the parameters of
+//! every form of the macro only match values, not Atoms or indirection:
+//!
+//! ```
+//! macro   ld      A, B            { byte $78 }
+//! macro   ld      A, \n           { byte $3E, \n }
+//! macro   ld      A, (HL)         { byte $7E }
+//! macro   ld      A, (\nn)        { byte $3A, word \nn }
+//! macro   ld      A, (IX + \d)    { byte $DD, $7E, \d }
+//! ```
+//!
+//! Indirection may be given with square brackets instead, i.e. `ld A, [HL]`
+//! matches `ld A, (HL)`, unless the forms of the macro use both styles, in
+//! which case they are told apart:
+//!
+//! ```
+//! macro   lda     (\zp)           { byte $B2, \zp }
+//! macro   lda     [\zp]           { byte $A7, \zp }
+//! ```
+//!
+//! A parameter given a type only matches a value that is known and fits
+//! the type, so a form can be chosen by the size of a value:
+//!
+//! ```
+//! macro   lda     byte \zp        { byte $A5, \zp }
+//! macro   lda     \nn             { byte $AD, word \nn }
+//! ```
+//!
+//! `\warn` reports a warning when the macro is expanded, without stopping
+//! assembly; `\error` stops assembly with an error:
+//!
+//! ```
+//! macro   jmp     (\nn)
+//! {
+//!         \if \nn & $FF + 1 >> 8 { \warn "crosses a page boundary" }
+//!         byte $6C, word \nn
+//! }
+//! ```
+//!
+//! A form that uses `\requires` can only be used if the option named has
+//! been enabled for assembly, e.g. for undocumented instructions:
+//!
+//! ```
+//! macro   sll     B               { \requires "z80-undocumented" byte $CB, $30 }
+//! ```
+//!
+//! Where the option is not enabled, the form is passed over for the next
+//! that matches, so forms with the same pattern can depend upon an option.
+//! Options can be enabled, or disabled, for the statements that follow the
+//! expansion of a macro with `\enable` and `\disable`:
+//!
+//! ```
+//! macro   rep#    \n
+//! {
+//!         byte $C2, \n
+//!         \if \n & $20 { \enable "65816-a16" }
+//! }
+//! macro   lda#    \n              { \requires "65816-a16" byte $A9, word \n }
+//! macro   lda#    \n              { byte $A9, \n }
+//! ```
+//!
+//! A form can give an alternative to its statements with `\relax`, e.g. a
+//! shorter relative jump for an absolute one. Where the "relax" option is
+//! enabled, and the statements give a value not known until the Object is
+//! linked, the Linker chooses the shortest of the two whose values fit:
+//!
+//! ```
+//! macro   jp      \nn             { \relax { jr \nn } byte $C3, word \nn }
+//! ```
+//!
+//! A macro that uses `\shift` takes any number of arguments (at least as
+//! many as its parameters); the body is repeated, with each `\shift`
+//! dropping the first argument, until all of the arguments are consumed:
+//!
+//! ```
+//! macro   bytes   \1
+//! {
+//!         \if \string { \end }
+//!         byte \1
+//!         \shift
+//! }
+//! ```
+//!
+
+use crate::list::List;
+use crate::token::{MaybeToken, Token};
+
+/// A Macro definition. Not a Macro invocation -- these appear within the
+/// AST -- but a Macro that an Object file defines and exports.
+#[derive(Debug, Clone)]
+pub struct Macro {
+    /// The name of the Macro.
+    name: String,
+    /// The forms of the Macro, in the order they were defined. An invocation
+    /// expands the first form whose pattern matches the arguments given.
+    forms: Vec<Form>,
+}
+
+/// One form of a Macro; the pattern of arguments it accepts and the body
+/// to expand when they match.
+#[derive(Debug, Clone)]
+pub struct Form {
+    /// The arguments that the form accepts: parameters (which may be
+    /// typed), Atoms, numbers and parenthesised lists (indirection) thereof.
+    pattern: List,
+    /// Names of the form's parameters, in order. Numbered parameters are
+    /// named by their number, e.g. "1".
+    params: Vec<String>,
+    /// The statements that will be inserted in place of an invocation.
+    body: List,
+    /// Does the form take a variable number of arguments?
+    /// This is the case if the body uses `\shift`.
+    is_variadic: bool,
+    /// Options that must be enabled to use the form,
+    /// given by `\requires` within the body.
+    requires: Vec<String>,
+    /// Does the pattern contain anything other than parameters?
+    /// If any form of a Macro does, the parameters of all its forms only
+    /// match values, not Atoms or indirection.
+    is_pattern: bool,
+    /// The styles of brackets used for indirection within the pattern.
+    /// If the forms of a Macro use both, they are told apart.
+    brackets: Vec<Brackets>,
+    /// A reference back to the original source code where the form was
+    /// defined, in case of error.
+    token: Token,
+    /// The documentation comment preceding the definition, if any.
+    doc: Option<String>,
+}
+
+/// Directives control the expansion of a Macro
+/// and can only be used within a Macro body.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Directive {
+    /// `\shift`: drop the first argument,
+    /// moving the remaining arguments down one place.
+    Shift,
+    /// `\end`: stop expanding the Macro.
+    End,
+    /// `\if`: begins a conditional block.
+    If,
+    /// `\else`: begins the alternative to a conditional block.
+    Else,
+    /// `\atom`: 1 if the first argument is an Atom, otherwise 0.
+    Atom,
+    /// `\number`: 1 if the first argument is a number, otherwise 0.
+    Number,
+    /// `\string`: 1 if the first argument is a string, otherwise 0.
+    String,
+    /// `\warn`: report a warning, but continue assembly.
+    Warn,
+    /// `\error`: stop assembly with an error.
+    Error,
+    /// `\requires`: the form can only be used if an option is enabled.
+    Requires,
+    /// `\enable`: enable an option for the rest of assembly.
+    Enable,
+    /// `\disable`: disable an option for the rest of assembly.
+    Disable,
+    /// `\relax`: an alternative to the statements of the form.
+    Relax,
+}
+
+use std::fmt::{self, *};
+
+impl Display for Directive {
+    //==========================================================================
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        //----------------------------------------------------------------------
+        f.write_str(match self {
+            Directive::Shift => "\\shift",
+            Directive::End => "\\end",
+            Directive::If => "\\if",
+            Directive::Else => "\\else",
+            Directive::Atom => "\\atom",
+            Directive::Number => "\\number",
+            Directive::String => "\\string",
+            Directive::Warn => "\\warn",
+            Directive::Error => "\\error",
+            Directive::Requires => "\\requires",
+            Directive::Enable => "\\enable",
+            Directive::Disable => "\\disable",
+            Directive::Relax => "\\relax",
+        })
+    }
+}
+
+/// A conditional block within a Macro body: `\if test { ... } \else { ... }`.
+/// The test is calculated when the Macro is expanded and if non-zero, the
+/// first block is expanded, otherwise the `\else` block (if any).
+#[derive(Debug, Clone)]
+pub struct If {
+    /// The value to test.
+    pub test: Node,
+    /// Statements to expand if the test is true (non-zero).
+    pub then: List,
+    /// Statements to expand if the test is false (zero); empty if there
+    /// is no `\else` block.
+    pub other: List,
+}
+
+impl Display for If {
+    //==========================================================================
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        //----------------------------------------------------------------------
+        write!(f, "\\if {} {{ {} }}", self.test, self.then.iter().join(" "))?;
+        if !self.other.is_empty() {
+            write!(f, " \\else {{ {} }}", self.other.iter().join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether expansion of a Macro body should continue.
+#[derive(PartialEq)]
+enum Flow {
+    Continue,
+    /// `\end` was encountered.
+    End,
+}
+
+/// How the arguments of an invocation are matched against the patterns of
+/// a Macro's forms; these depend upon the forms the Macro has.
+#[derive(Clone, Copy)]
+struct Rules {
+    /// Parameters only match values, not Atoms or indirection.
+    is_strict: bool,
+    /// Indirection only matches the same style of brackets.
+    is_distinct: bool,
+}
+
+use crate::assembler::Options;
+use crate::error::*;
+use crate::node::{Brackets, Node, NodeKind, Value};
+
+/// The most forms listed by the error for an invocation that matches none;
+/// the number left out is given instead.
+const MAX_LISTED_FORMS: usize = 8;
+
+impl Macro {
+    //==========================================================================
+    /// Create a new Macro from its name token, the pattern of arguments it
+    /// accepts, its already parsed body and the documentation comment
+    /// preceding the definition, if any. Further forms can be added to
+    /// the Macro with `add_forms`.
+    ///
+    pub fn new(
+        token: Token,
+        pattern: List,
+        body: List,
+        doc: Option<String>,
+    ) -> ParseResult<Self> {
+        //----------------------------------------------------------------------
+        Ok(Self {
+            name: token.to_string(),
+            forms: vec![Form::new(token, pattern, body, doc)?],
+        })
+    }
+
+    /// The name of the Macro.
+    ///
+    pub fn name(&self) -> &str {
+        //----------------------------------------------------------------------
+        &self.name
+    }
+
+    /// The forms of the Macro, in the order they were defined.
+    ///
+    pub fn forms(&self) -> &[Form] {
+        //----------------------------------------------------------------------
+        &self.forms
+    }
+
+    /// The token of the Macro name, where it was first defined.
+    ///
+    pub fn token(&self) -> &Token {
+        //----------------------------------------------------------------------
+        &self.forms[0].token
+    }
+
+    /// Add the forms of another definition of the same Macro to this one.
+    /// Returns a `ParseError` if a form has the same pattern and required
+    /// options as one that already exists, as it could never be matched.
+    ///
+    pub fn add_forms(&mut self, other: &Macro) -> ParseResult<()> {
+        //----------------------------------------------------------------------
+        for form in other.forms.iter() {
+            if self
+                .forms
+                .iter()
+                .any(|f| f.is_same_pattern(form) && f.requires == form.requires)
+            {
+                return Err(
+                    ParseError::duplicate().at(Some(form.token.location()))
+                );
+            }
+            self.forms.push(form.clone());
+        }
+        Ok(())
+    }
+
+    /// Expand the Macro with the given arguments, returning a copy of the
+    /// body of the first form that matches the arguments, with each
+    /// parameter replaced by its argument. Any macro invocations within the
+    /// body are *not* expanded; that is left to the caller, who holds the
+    /// pool of Macro definitions.
+    ///
+    /// The `token` is that of the invocation, and is used in any error.
+    /// A form that requires an option that is not amongst the `options`
+    /// enabled is passed over; if no other form matches, a `ParseError`
+    /// naming the option is returned.
+    ///
+    pub fn expand(
+        &self,
+        args: &List,
+        token: &MaybeToken,
+        options: &Options,
+    ) -> ParseResult<List> {
+        //----------------------------------------------------------------------
+        Self::expand_chain(&[self], args, token, options).map(|(_, b)| b)
+    }
+
+    /// Expand the first of a chain of Macros of the same name whose form
+    /// matches the arguments, as with `expand`, returning its index along
+    /// with the body; e.g. a Macro that extends one its module imports,
+    /// followed by that one. The forms are matched as if of one Macro.
+    ///
+    pub fn expand_chain(
+        chain: &[&Macro],
+        args: &List,
+        token: &MaybeToken,
+        options: &Options,
+    ) -> ParseResult<(usize, List)> {
+        //----------------------------------------------------------------------
+        let forms = || {
+            chain
+                .iter()
+                .enumerate()
+                .flat_map(|(n, m)| m.forms.iter().map(move |form| (n, form)))
+        };
+        let rules = Rules {
+            // where a Macro matches Atoms or indirection, its parameters only
+            // stand in for values, otherwise `ld A, \n` would also match
+            // `ld A, B`, and `jp \nn` would also match `jp (HL)`
+            is_strict: forms().any(|(_, form)| form.is_pattern),
+            // where a Macro uses both styles of brackets for indirection,
+            // they mean different things, e.g. `lda ($12)` & `lda [$12]`
+            is_distinct: [Brackets::Round, Brackets::Square]
+                .iter()
+                .all(|b| forms().any(|(_, form)| form.brackets.contains(b))),
+        };
+        let name = &chain[0].name;
+
+        // a form that requires an option not enabled is passed over, so
+        // that a later form can stand in for it, e.g. an 8-bit immediate
+        // for a 16-bit one. if none can, we report the last option missing
+        let mut missing = None;
+        let (n, form, bindings) = match forms()
+            .filter_map(|(n, form)| {
+                form.bind(args, rules).map(|b| (n, form, b))
+            })
+            .find(|(_, form, _)| {
+                match form.requires.iter().find(|o| !options.contains(*o)) {
+                    Some(option) => {
+                        missing = Some(option);
+                        false
+                    }
+                    None => true,
+                }
+            }) {
+            Some(m) => m,
+            None => {
+                return Err(match missing {
+                    Some(option) => {
+                        parse_error(ParseErrorKind::OptionRequired {
+                            invocation: Self::invocation(name, args),
+                            option: option.clone(),
+                        })
+                    }
+                    None => {
+                        let forms: Vec<_> = forms().map(|(_, f)| f).collect();
+                        Self::no_match(name, &forms, args, rules, options)
+                    }
+                }
+                .at(token.as_ref().map(Token::location)))
+            }
+        };
+
+        form.expand(bindings)
+            .map(|body| (n, body))
+            .map_err(|e| e.in_expansion(Expansion::new(name, token)))
+    }
+
+    /// Build the error for when no form of the Macro of the given name
+    /// matches the arguments. Only the forms the invocation was likely
+    /// meant for are listed.
+    ///
+    fn no_match(
+        name: &str,
+        forms: &[&Form],
+        args: &List,
+        rules: Rules,
+        options: &Options,
+    ) -> ParseError {
+        //----------------------------------------------------------------------
+        match forms {
+            // the parameters of a plain form match any argument, so only
+            // the number of arguments can be wrong
+            [form] if !form.is_pattern => {
+                parse_error(ParseErrorKind::ArgumentCount {
+                    name: name.to_string(),
+                    expected: form.params.len(),
+                    found: args.len(),
+                    is_variadic: form.is_variadic,
+                })
+            }
+            _ => {
+                // of the forms that can be used with the options enabled,
+                // those that begin the same as the invocation, e.g. all of
+                // `ld (HL), ...` for `ld (HL), SP`, or else those that take
+                // as many arguments
+                let usable: Vec<&Form> = forms
+                    .iter()
+                    .filter(|f| f.requires.iter().all(|o| options.contains(o)))
+                    .copied()
+                    .collect();
+                let leading = |form: &Form| form.leading(args, rules);
+                let best = usable.iter().map(|f| leading(f)).max();
+                let near: Vec<String> = usable
+                    .iter()
+                    .filter(|f| match best {
+                        Some(n) if n > 0 => leading(f) == n,
+                        _ => f.pattern.len() == args.len(),
+                    })
+                    .map(|f| f.to_string())
+                    .collect();
+                parse_error(ParseErrorKind::NoMatchingForm {
+                    invocation: Self::invocation(name, args),
+                    more: near.len().saturating_sub(MAX_LISTED_FORMS),
+                    forms: near.into_iter().take(MAX_LISTED_FORMS).collect(),
+                })
+            }
+        }
+    }
+
+    /// Give an invocation of the Macro as normalised source code,
+    /// for error messages.
+    ///
+    fn invocation(name: &str, args: &List) -> String {
+        //----------------------------------------------------------------------
+        if args.is_empty() {
+            name.to_string()
+        } else {
+            format!("{} {}", name, args.iter().join(", "))
+        }
+    }
+}
+
+impl Form {
+    //==========================================================================
+    /// Create a new form from the token of the Macro name, the pattern of
+    /// arguments it accepts, its already parsed body and its documentation.
+    ///
+    /// Numbered parameters must appear in order, i.e. `\2` must be the
+    /// second parameter, otherwise a `ParseError` is returned.
+    ///
+    fn new(
+        token: Token,
+        pattern: List,
+        body: List,
+        doc: Option<String>,
+    ) -> ParseResult<Self> {
+        //----------------------------------------------------------------------
+        let mut params = Vec::new();
+        for node in pattern.iter() {
+            Self::collect_params(node, &mut params)?;
+        }
+
+        Ok(Self {
+            is_pattern: pattern
+                .iter()
+                .any(|node| !matches!(node.kind, NodeKind::Param(_))),
+            brackets: [Brackets::Round, Brackets::Square]
+                .iter()
+                .filter(|b| Self::has_brackets(&pattern, **b))
+                .cloned()
+                .collect(),
+            pattern,
+            params,
+            is_variadic: Self::has_shift(&body),
+            requires: body
+                .iter()
+                .filter_map(|node| match node.kind {
+                    NodeKind::Requires(ref option) => Some(option.clone()),
+                    _ => None,
+                })
+                .collect(),
+            body,
+            token,
+            doc,
+        })
+    }
+
+    /// Gather the names of the parameters within a pattern node, checking
+    /// that the node is something that can be matched against.
+    ///
+    fn collect_params(
+        node: &Node,
+        params: &mut Vec<String>,
+    ) -> ParseResult<()> {
+        //----------------------------------------------------------------------
+        match node.kind {
+            NodeKind::Param(ref name) | NodeKind::TypedParam(_, ref name) => {
+                // a numbered parameter must match its position
+                if let Ok(n) = name.parse::<usize>() {
+                    if n != params.len() + 1 {
+                        return Err(
+                            ParseError::unexpected().at(node.location())
+                        );
+                    }
+                }
+                // a parameter name cannot be used twice
+                if params.contains(name) {
+                    return Err(ParseError::duplicate().at(node.location()));
+                }
+                params.push(name.clone());
+            }
+            NodeKind::Atom(..) | NodeKind::Value(_) => {}
+            NodeKind::Indirect(_, ref list) => {
+                for node in list.iter() {
+                    Self::collect_params(node, params)?;
+                }
+            }
+            NodeKind::Expr(ref expr) => {
+                Self::collect_params(&expr.left, params)?;
+                Self::collect_params(&expr.right, params)?;
+            }
+            _ => return Err(ParseError::unexpected().at(node.location())),
+        }
+        Ok(())
+    }
+
+    /// Does a pattern use the given style of brackets for indirection,
+    /// including within other indirection?
+    ///
+    fn has_brackets(list: &List, brackets: Brackets) -> bool {
+        //----------------------------------------------------------------------
+        list.iter().any(|node| match node.kind {
+            NodeKind::Indirect(b, ref list) => {
+                b == brackets || Self::has_brackets(list, brackets)
+            }
+            _ => false,
+        })
+    }
+
+    /// Does a list of statements use `\shift`, including within any
+    /// conditional blocks?
+    ///
+    fn has_shift(list: &List) -> bool {
+        //----------------------------------------------------------------------
+        list.iter().any(|node| match node.kind {
+            NodeKind::Directive(Directive::Shift) => true,
+            NodeKind::If(ref cond) => {
+                Self::has_shift(&cond.then) || Self::has_shift(&cond.other)
+            }
+            _ => false,
+        })
+    }
+
+    /// The names of the form's parameters, in order.
+    ///
+    pub fn params(&self) -> &[String] {
+        //----------------------------------------------------------------------
+        &self.params
+    }
+
+    /// Does the form take a variable number of arguments?
+    ///
+    pub fn is_variadic(&self) -> bool {
+        //----------------------------------------------------------------------
+        self.is_variadic
+    }
+
+    /// Options that must be enabled to use the form.
+    ///
+    pub fn requires(&self) -> &[String] {
+        //----------------------------------------------------------------------
+        &self.requires
+    }
+
+    /// The documentation comment preceding the form's definition, if any.
+    ///
+    pub fn doc(&self) -> Option<&str> {
+        //----------------------------------------------------------------------
+        self.doc.as_deref()
+    }
+
+    /// Do two forms have the same pattern? Parameters are considered the
+    /// same regardless of name.
+    ///
+    fn is_same_pattern(&self, other: &Form) -> bool {
+        //----------------------------------------------------------------------
+        is_same_list(&self.pattern, &other.pattern)
+    }
+
+    /// Match the arguments of an invocation against the pattern. If they
+    /// match, returns the arguments for each parameter, in order, followed
+    /// by any further arguments given to a variadic form.
+    ///
+    fn bind<'a>(&self, args: &'a List, rules: Rules) -> Option<Vec<&'a Node>> {
+        //----------------------------------------------------------------------
+        // we must be given one argument for each element of the pattern;
+        // a variadic form can be given more than that
+        if args.len() < self.pattern.len()
+            || (args.len() > self.pattern.len() && !self.is_variadic)
+        {
+            return None;
+        }
+
+        let mut bindings = Vec::with_capacity(args.len());
+        for (pattern, arg) in self.pattern.iter().zip(args.iter()) {
+            if !Self::is_match(pattern, arg, rules, &mut bindings) {
+                return None;
+            }
+        }
+        bindings.extend(args.iter().skip(self.pattern.len()));
+
+        Some(bindings)
+    }
+
+    /// How many of the arguments, from the first, match the pattern, each
+    /// on its own; how near the invocation comes to the form.
+    ///
+    fn leading(&self, args: &List, rules: Rules) -> usize {
+        //----------------------------------------------------------------------
+        let mut bindings = Vec::new();
+        self.pattern
+            .iter()
+            .zip(args.iter())
+            .take_while(|(p, a)| Self::is_match(p, a, rules, &mut bindings))
+            .count()
+    }
+
+    /// Match a single argument against an element of the pattern, adding
+    /// the argument for any parameters within to `bindings`.
+    ///
+    fn is_match<'a>(
+        pattern: &Node,
+        arg: &'a Node,
+        rules: Rules,
+        bindings: &mut Vec<&'a Node>,
+    ) -> bool {
+        //----------------------------------------------------------------------
+        match (&pattern.kind, &arg.kind) {
+            (NodeKind::Param(_), _) if rules.is_strict && !is_value(arg) => {
+                false
+            }
+            (NodeKind::Param(_), _) => {
+                bindings.push(arg);
+                true
+            }
+            // a typed parameter only matches a value
+            // that is known, and fits the type
+            (NodeKind::TypedParam(t, _), _) => match arg.eval() {
+                Some(value) if t.fits(value) => {
+                    bindings.push(arg);
+                    true
+                }
+                // ...or an address, not known until the Object is linked,
+                // where the type is wide enough to hold one; a bank number
+                // is assumed to fit a byte
+                None if arg.is_deferred()
+                    && (t.bits() >= 16 || (arg.is_bank() && t.bits() >= 8)) =>
+                {
+                    bindings.push(arg);
+                    true
+                }
+                _ => false,
+            },
+            // hints do not affect assembly, so are not compared
+            (NodeKind::Atom(p, _), NodeKind::Atom(a, _)) => p == a,
+            (NodeKind::Value(_), _) => {
+                arg.eval().is_some() && arg.eval() == pattern.eval()
+            }
+            // where the Macro uses only one style of brackets,
+            // either style can be given
+            (NodeKind::Indirect(pb, p), NodeKind::Indirect(ab, a)) => {
+                (pb == ab || !rules.is_distinct)
+                    && p.len() == a.len()
+                    && p.iter()
+                        .zip(a.iter())
+                        .all(|(p, a)| Self::is_match(p, a, rules, bindings))
+            }
+            (NodeKind::Expr(p), NodeKind::Expr(a)) => {
+                p.oper == a.oper
+                    && Self::is_match(&p.left, &a.left, rules, bindings)
+                    && Self::is_match(&p.right, &a.right, rules, bindings)
+            }
+            _ => false,
+        }
+    }
+
+    /// Expand the form with the arguments bound to its parameters,
+    /// returning a copy of the body with each parameter replaced.
+    ///
+    fn expand(&self, mut args: Vec<&Node>) -> ParseResult<List> {
+        //----------------------------------------------------------------------
+        let mut list = List::default();
+
+        loop {
+            let remaining = args.len();
+            let flow = self.expand_block(&self.body, &mut args, &mut list)?;
+
+            // a variadic form repeats until all arguments are consumed,
+            // but we must stop if no argument was consumed on this pass
+            if flow == Flow::End
+                || !self.is_variadic
+                || args.is_empty()
+                || args.len() == remaining
+            {
+                break;
+            }
+        }
+
+        Ok(list)
+    }
+
+    /// Expand a list of statements from the Macro body, appending the result
+    /// to `list`. Directives are acted upon here and do not appear in the
+    /// output.
+    ///
+    fn expand_block(
+        &self,
+        block: &List,
+        args: &mut Vec<&Node>,
+        list: &mut List,
+    ) -> ParseResult<Flow> {
+        //----------------------------------------------------------------------
+        for node in block.iter() {
+            match node.kind {
+                NodeKind::Directive(Directive::Shift) => {
+                    if !args.is_empty() {
+                        args.remove(0);
+                    }
+                }
+                NodeKind::Directive(Directive::End) => return Ok(Flow::End),
+                // already checked before expansion
+                NodeKind::Requires(_) => {}
+                // the alternative is expanded with the same arguments;
+                // the Assembler decides whether to keep it
+                NodeKind::Relax(ref block) => {
+                    let mut alternative = List::default();
+                    self.expand_block(block, args, &mut alternative)?;
+                    // a `Relax` node always has its directive token
+                    list.push(Node::new_relax(
+                        node.token.clone().unwrap(),
+                        alternative,
+                    ));
+                }
+                NodeKind::If(ref cond) => {
+                    let test = self.substitute(&cond.test, args)?;
+                    // the test has to be calculable at this point
+                    let value = test.eval().ok_or_else(|| {
+                        parse_error(ParseErrorKind::NotStatic)
+                            .at(cond.test.location())
+                    })?;
+                    let branch =
+                        if value != 0 { &cond.then } else { &cond.other };
+                    if self.expand_block(branch, args, list)? == Flow::End {
+                        return Ok(Flow::End);
+                    }
+                }
+                _ => list.push(self.substitute(node, args)?),
+            }
+        }
+        Ok(Flow::Continue)
+    }
+
+    /// Returns a copy of a `Node` where any parameters, including those of
+    /// nested nodes, are replaced with the matching argument.
+    ///
+    fn substitute(&self, node: &Node, args: &[&Node]) -> ParseResult<Node> {
+        //----------------------------------------------------------------------
+        Ok(match node.kind {
+            NodeKind::Param(ref name) => {
+                // insert a copy of the argument
+                args[self.param_index(name, args).ok_or_else(|| {
+                    parse_error(ParseErrorKind::UndefinedParam(name.clone()))
+                        .at(node.location())
+                })?]
+                .clone()
+            }
+            NodeKind::Directive(d) => {
+                // argument introspection
+                let test = match (d, args.first().map(|a| &a.kind)) {
+                    (Directive::Atom, Some(NodeKind::Atom(..))) => true,
+                    (Directive::Number, Some(_)) => args[0].eval().is_some(),
+                    (Directive::String, Some(NodeKind::Str(_))) => true,
+                    (Directive::Atom, _)
+                    | (Directive::Number, _)
+                    | (Directive::String, _) => false,
+                    // other directives are not values
+                    _ => {
+                        return Err(ParseError::unexpected().at(node.location()))
+                    }
+                };
+                Node::new_value(Value::Int(test as i32), node.token.clone())
+            }
+            NodeKind::Expr(ref expr) => Node::new_expr(
+                self.substitute(&expr.left, args)?,
+                // an `Expr` node always has its operator token
+                node.token.clone().unwrap(),
+                self.substitute(&expr.right, args)?,
+            ),
+            NodeKind::Record(ref list) => {
+                Node::new_record(self.substitute_list(list, args)?)
+            }
+            NodeKind::List(ref list) => Node {
+                token: node.token.clone(),
+                ..Node::new_list(self.substitute_list(list, args)?)
+            },
+            NodeKind::Indirect(brackets, ref list) => {
+                let list = self.substitute_list(list, args)?;
+                Node {
+                    is_static: list.is_static(),
+                    kind: NodeKind::Indirect(brackets, Box::new(list)),
+                    token: node.token.clone(),
+                    backtrace: Vec::new(),
+                }
+            }
+            NodeKind::Macro(_, ref list) => Node::new_macro(
+                // a macro invocation always has its name token
+                node.token.clone().unwrap(),
+                self.substitute_list(list, args)?,
+            ),
+            NodeKind::Relative(ref value) => Node::new_relative(
+                // a relative value always has its keyword token
+                node.token.clone().unwrap(),
+                self.substitute(value, args)?,
+            ),
+            // any other node has nothing to substitute within
+            _ => node.clone(),
+        })
+    }
+
+    fn substitute_list(
+        &self,
+        list: &List,
+        args: &[&Node],
+    ) -> ParseResult<List> {
+        //----------------------------------------------------------------------
+        let mut out = List::default();
+        for node in list.iter() {
+            out.push(self.substitute(node, args)?);
+        }
+        Ok(out)
+    }
+
+    /// Find the index of an argument by parameter name, or by number;
+    /// numbered parameters can be used regardless of whether the parameter
+    /// has been given a name in the definition. Returns `None` if there is
+    /// no such parameter, or no argument remains for it (due to `\shift`).
+    ///
+    fn param_index(&self, name: &str, args: &[&Node]) -> Option<usize> {
+        //----------------------------------------------------------------------
+        let index = match name.parse::<usize>() {
+            Ok(n) if n >= 1 => n - 1,
+            Ok(_) => return None,
+            Err(_) => self.params.iter().position(|p| p == name)?,
+        };
+        if index < args.len() {
+            Some(index)
+        } else {
+            None
+        }
+    }
+}
+
+/// Is the Node a value -- one that does not involve Atoms or indirection?
+///
+fn is_value(node: &Node) -> bool {
+    //--------------------------------------------------------------------------
+    match node.kind {
+        NodeKind::Atom(..) | NodeKind::List(_) | NodeKind::Indirect(..) => {
+            false
+        }
+        NodeKind::Expr(ref expr) => {
+            is_value(&expr.left) && is_value(&expr.right)
+        }
+        _ => true,
+    }
+}
+
+/// Do two lists of pattern nodes have the same shape?
+///
+fn is_same_list(a: &List, b: &List) -> bool {
+    //--------------------------------------------------------------------------
+    a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| is_same(a, b))
+}
+
+/// Do two pattern nodes have the same shape?
+///
+fn is_same(a: &Node, b: &Node) -> bool {
+    //--------------------------------------------------------------------------
+    match (&a.kind, &b.kind) {
+        (NodeKind::Param(_), NodeKind::Param(_)) => true,
+        (NodeKind::TypedParam(a, _), NodeKind::TypedParam(b, _)) => a == b,
+        (NodeKind::Atom(a, _), NodeKind::Atom(b, _)) => a == b,
+        (NodeKind::Value(_), NodeKind::Value(_)) => a.eval() == b.eval(),
+        (NodeKind::Indirect(ab, a), NodeKind::Indirect(bb, b)) => {
+            ab == bb && is_same_list(a, b)
+        }
+        (NodeKind::Expr(a), NodeKind::Expr(b)) => {
+            a.oper == b.oper
+                && is_same(&a.left, &b.left)
+                && is_same(&a.right, &b.right)
+        }
+        _ => false,
+    }
+}
+
+use itertools::Itertools;
+
+impl Display for Macro {
+    //==========================================================================
+    /// Print the Macro definition as normalised source code;
+    /// one definition for each form.
+    ///
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        //----------------------------------------------------------------------
+        for (i, form) in self.forms.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(
+                f,
+                "macro {} {{\n{}}}",
+                form,
+                form.body.iter().fold(String::new(), |acc, node| format!(
+                    "{}\t{}\n",
+                    acc, node
+                ))
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Form {
+    //==========================================================================
+    /// Print the name and pattern of the form, e.g. `ld (HL), \n`.
+    ///
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        //----------------------------------------------------------------------
+        write!(f, "{}", self.token)?;
+        if !self.pattern.is_empty() {
+            write!(f, " {}", self.pattern.iter().join(", "))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,700 @@
+// retronym (C) copyright Kroc Camen 2017, 2018
+// BSD 2-clause licence; see LICENSE.TXT
+
+//! A **Memory Map** describes the memory of a system -- where its ROM and
+//! RAM lie, how its ROM is paged in -- and which segments are placed where,
+//! for the `Linker`. A memory map is written in Retronym syntax, a statement
+//! per line, e.g. for a 32 KB SEGA Master System cartridge:
+//!
+//! ```
+//! ; name, start address, size
+//! region  ROM,    $0000,  $8000
+//! ram     RAM,    $C000,  $2000
+//! ; the RAM is seen again, less the mapper registers at the top
+//! mirror  RAM,    $E000,  $1FF0
+//! ; pad the ROM to its full size
+//! fill    ROM,    $FF
+//! ; the segment "vars" is placed in RAM, the rest in ROM
+//! place   "vars", RAM
+//! ```
+//!
+//! - `region` is memory whose contents are written to the output, e.g.
+//!   a cartridge ROM, or a program loaded into RAM. Regions are written
+//!   one after another, in the order given.
+//! - `ram` is memory whose contents are not written to the output;
+//!   data placed there only reserves the space.
+//! - `mirror` is where the memory of a region is seen again; the size
+//!   is that of the region unless given, and cannot be larger. Nothing is
+//!   placed in a mirror.
+//! - `slot` is a window of memory that a bank of ROM is paged into.
+//!   A slot can lie over a region or RAM, e.g. ROM paged over RAM, but
+//!   otherwise the areas and mirrors given cannot overlap.
+//! - `banks` writes the given number of banks, each the size of the slot
+//!   they are paged into, to the output after the regions given so far,
+//!   e.g. `banks SLOT2, 30`. A bank is numbered by where it lies in the
+//!   output, e.g. the first 16 KB bank after 32 KB of regions is bank 2;
+//!   so that it is, the banks begin on a multiple of the slot's size,
+//!   the output padded up to it if need be.
+//! - `reserve` leaves banks of a slot empty, e.g. those a mapper cannot
+//!   page in: `reserve ROMX, $20, $40, $60`. They are still written to
+//!   the output, but nothing is placed in them.
+//! - `fill` pads a region, or the banks of a slot, to its full size with
+//!   the byte given. Without a fill, the last region or bank written to
+//!   the output ends with its last byte placed.
+//! - `place` places a segment, named by a string, in a region, RAM, or the
+//!   banks of a slot; segments not placed go in the first region given.
+//!   A segment placed in a slot goes in the bank that fits it with the
+//!   least room to spare, unless a bank number is given, e.g.
+//!   `place "level1", SLOT2, 5`.
+//! - `pin` fixes a segment, named by a string, or a Label at a location in
+//!   the output, e.g. `pin "header", $7FF0` or `pin :main, $0100`; this
+//!   is how an original ROM is rebuilt byte-for-byte. A pinned segment is
+//!   placed in the region or bank the location falls in, regardless of
+//!   `place`, and pinned segments cannot overlap. Segments not pinned are
+//!   packed into the space left around them.
+//!
+//! A segment placed in a bank is given two addresses: its *load* address,
+//! where it lies in the output, and its *run* address, where it is seen
+//! when its bank is paged into the slot. Labels have the run address;
+//! `bank(:label)` gives the number of the bank a Label lies in.
+//!
+//! Memory maps of common systems are bundled with Retronym as presets,
+//! selected by name, e.g. "sms"; see `PRESETS`. A memory map read after
+//! a preset adds to it, e.g. giving the number of banks of a cartridge.
+
+use crate::error::*;
+use crate::node::{Node, NodeKind};
+use crate::parser::Parser;
+use crate::source::SourceFile;
+use std::rc::Rc;
+
+/// The memory maps bundled with Retronym, by name.
+pub const PRESETS: &[(&str, &str)] = &[
+    ("cpc", include_str!("../../maps/cpc.rym")),
+    ("gb-mbc1", include_str!("../../maps/gb-mbc1.rym")),
+    ("gb-mbc5", include_str!("../../maps/gb-mbc5.rym")),
+    ("pcw", include_str!("../../maps/pcw.rym")),
+    ("sms", include_str!("../../maps/sms.rym")),
+];
+
+/// The statements of a memory map, by name, with how each is written.
+const STATEMENTS: &[(&str, &str)] = &[
+    ("region", "region <name>, <start>, <size>"),
+    ("ram", "ram <name>, <start>, <size>"),
+    ("slot", "slot <name>, <start>, <size>"),
+    ("mirror", "mirror <area>, <start>[, <size>]"),
+    ("banks", "banks <slot>, <count>"),
+    ("reserve", "reserve <slot>, <bank>..."),
+    ("fill", "fill <area>, <byte>"),
+    ("place", "place \"<segment>\", <area>[, <bank>]"),
+    ("pin", "pin \"<segment>\" | :<label>, <location>"),
+];
+
+/// A memory map; see the module documentation.
+#[derive(Debug, Default, Clone)]
+pub struct MemoryMap {
+    /// The regions, RAM and slots, in the order given.
+    areas: Vec<Area>,
+    /// The areas written to the output, in order, by index.
+    output: Vec<usize>,
+    /// Where regions are seen again.
+    mirrors: Vec<Mirror>,
+    /// The segments placed, in the order given.
+    places: Vec<Place>,
+    /// The segments and Labels pinned, in the order given.
+    pins: Vec<Pin>,
+}
+
+/// A range of memory, named, e.g. `ROM`.
+#[derive(Debug, Clone)]
+pub struct Area {
+    /// The name of the area, e.g. "ROM".
+    pub name: String,
+    /// What the memory is for.
+    pub kind: AreaKind,
+    /// The address of the first byte of the area.
+    pub start: usize,
+    /// The size of the area, in bytes.
+    pub size: usize,
+    /// For a slot, the number of banks written to the output.
+    pub banks: usize,
+    /// For a slot, the numbers of the banks nothing is placed in.
+    pub reserved: Vec<usize>,
+    /// The byte the area is padded to its full size with, if given.
+    pub fill: Option<u8>,
+    /// Where in the source code the area was given.
+    pub location: Option<Location>,
+}
+
+/// What the memory of an area is for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AreaKind {
+    /// Memory whose contents are written to the output.
+    Region,
+    /// Memory whose contents are not written to the output.
+    Ram,
+    /// A window of memory that banks of ROM are paged into.
+    Slot,
+}
+
+/// Where the memory of a region is seen again.
+#[derive(Debug, Clone)]
+pub struct Mirror {
+    /// The name of the region mirrored.
+    pub area: String,
+    /// The address the mirror begins at.
+    pub start: usize,
+    /// The size of the mirror, in bytes.
+    pub size: usize,
+}
+
+/// A segment placed in an area.
+#[derive(Debug, Clone)]
+pub struct Place {
+    /// The name of the segment, e.g. "code".
+    pub segment: String,
+    /// The name of the area the segment is placed in.
+    pub area: String,
+    /// For a slot, the number of the bank the segment is placed in, if given.
+    pub bank: Option<usize>,
+    /// Where in the source code the segment was placed.
+    pub location: Option<Location>,
+}
+
+/// A segment, or Label, fixed at a location in the output.
+#[derive(Debug, Clone)]
+pub struct Pin {
+    /// What is pinned.
+    pub pinned: Pinned,
+    /// Where in the output it is pinned, in bytes from the first.
+    pub offset: usize,
+    /// Where in the source code it was pinned.
+    pub location: Option<Location>,
+}
+
+/// What is pinned at a location in the output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pinned {
+    /// A segment, by name, e.g. "header".
+    Segment(String),
+    /// A Label, by name, without the colon.
+    Label(String),
+}
+
+impl Area {
+    //==========================================================================
+    /// The address just past the last byte of the area.
+    ///
+    pub fn end(&self) -> usize {
+        //----------------------------------------------------------------------
+        self.start + self.size
+    }
+
+    /// The number of bytes the area writes to the output, at its full
+    /// size; for a slot, that of its banks.
+    ///
+    pub fn output_size(&self) -> usize {
+        //----------------------------------------------------------------------
+        match self.kind {
+            AreaKind::Region => self.size,
+            AreaKind::Ram => 0,
+            AreaKind::Slot => self.size * self.banks,
+        }
+    }
+}
+
+impl MemoryMap {
+    //==========================================================================
+    /// A memory map of a single region, named "MEMORY", beginning at the
+    /// given address and spanning the rest of memory; used where no memory
+    /// map is given.
+    ///
+    pub fn flat(origin: usize) -> Self {
+        //----------------------------------------------------------------------
+        Self {
+            areas: vec![Area {
+                name: "MEMORY".to_string(),
+                kind: AreaKind::Region,
+                start: origin,
+                size: usize::MAX - origin,
+                banks: 0,
+                reserved: Vec::new(),
+                fill: None,
+                location: None,
+            }],
+            output: vec![0],
+            ..Self::default()
+        }
+    }
+
+    /// Read a memory map from a file's source code.
+    ///
+    /// Returns a `ParseError` if a statement is not understood, or names
+    /// an area that has not been given, or an area is given twice, or
+    /// memory is given twice.
+    ///
+    pub fn parse(file: &Rc<SourceFile>) -> ParseResult<Self> {
+        //----------------------------------------------------------------------
+        let mut map = Self::default();
+        map.read(file)?;
+        Ok(map)
+    }
+
+    /// Add the statements of a file's source code to the memory map,
+    /// e.g. to add to a preset. Returns a `ParseError` as with `parse`.
+    ///
+    pub fn read(&mut self, file: &Rc<SourceFile>) -> ParseResult<()> {
+        //----------------------------------------------------------------------
+        for node in Parser::new(file)? {
+            match node? {
+                Some(node) => self.add(&node)?,
+                None => break,
+            }
+        }
+        Ok(())
+    }
+
+    /// Is the memory map empty? That is, no area has been given.
+    ///
+    pub fn is_empty(&self) -> bool {
+        //----------------------------------------------------------------------
+        self.areas.is_empty()
+    }
+
+    /// The regions, RAM and slots, in the order given.
+    ///
+    pub fn areas(&self) -> &[Area] {
+        //----------------------------------------------------------------------
+        &self.areas
+    }
+
+    /// The areas written to the output, in order, by index.
+    ///
+    pub fn output(&self) -> &[usize] {
+        //----------------------------------------------------------------------
+        &self.output
+    }
+
+    /// Where regions are seen again.
+    ///
+    pub fn mirrors(&self) -> &[Mirror] {
+        //----------------------------------------------------------------------
+        &self.mirrors
+    }
+
+    /// Find an area by name.
+    ///
+    pub fn area(&self, name: &str) -> Option<&Area> {
+        //----------------------------------------------------------------------
+        self.areas.iter().find(|a| a.name == name)
+    }
+
+    /// The index of the area a segment is placed in; the area it was
+    /// placed in by the memory map, otherwise the first region.
+    ///
+    pub fn place(&self, segment: &str) -> Option<usize> {
+        //----------------------------------------------------------------------
+        match self.places.iter().find(|p| p.segment == segment) {
+            Some(p) => self.areas.iter().position(|a| a.name == p.area),
+            None => self.areas.iter().position(|a| a.kind == AreaKind::Region),
+        }
+    }
+
+    /// Where a segment was placed by the memory map, if it was.
+    ///
+    pub fn placement(&self, segment: &str) -> Option<&Place> {
+        //----------------------------------------------------------------------
+        self.places.iter().find(|p| p.segment == segment)
+    }
+
+    /// The segments and Labels pinned, in the order given.
+    ///
+    pub fn pins(&self) -> &[Pin] {
+        //----------------------------------------------------------------------
+        &self.pins
+    }
+
+    /// Find where a location in the output lies in memory, returning the
+    /// index of the area, the index of the bank within the area (zero for
+    /// a region), and the address of the location when paged in.
+    ///
+    pub fn locate(&self, offset: usize) -> Option<(usize, usize, usize)> {
+        //----------------------------------------------------------------------
+        // the padding before the banks of a slot lies in no area
+        self.loads()
+            .find(|&(i, load)| {
+                offset >= load && offset < load + self.areas[i].output_size()
+            })
+            .map(|(i, load)| {
+                let area = &self.areas[i];
+                let within = offset - load;
+                (i, within / area.size, area.start + within % area.size)
+            })
+    }
+
+    /// Where, in the output, the area of the given index begins;
+    /// for RAM, which is not written to the output, `None`.
+    ///
+    pub fn load(&self, area: usize) -> Option<usize> {
+        //----------------------------------------------------------------------
+        self.loads().find(|&(i, _)| i == area).map(|(_, load)| load)
+    }
+
+    /// The areas written to the output, in order, by index, each with
+    /// where in the output it begins. The banks of a slot begin on a
+    /// multiple of the slot's size, so that they are numbered by where
+    /// they lie.
+    ///
+    fn loads(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        //----------------------------------------------------------------------
+        self.output.iter().scan(0usize, move |base, &i| {
+            let area = &self.areas[i];
+            if area.kind == AreaKind::Slot && area.size > 0 {
+                *base = base.next_multiple_of(area.size);
+            }
+            let load = *base;
+            *base += area.output_size();
+            Some((i, load))
+        })
+    }
+
+    /// The number of the first bank of the slot of the given index.
+    /// Banks are numbered by where they lie in the output, in units of
+    /// the size of the slot.
+    ///
+    pub fn first_bank(&self, slot: usize) -> Option<usize> {
+        //----------------------------------------------------------------------
+        let area = &self.areas[slot];
+        match area.kind {
+            AreaKind::Slot if area.size > 0 => {
+                Some(self.load(slot).unwrap_or(0) / area.size)
+            }
+            _ => None,
+        }
+    }
+
+    /// The size of a bank of ROM; that of the first slot, if there is one.
+    ///
+    pub fn bank_size(&self) -> Option<usize> {
+        //----------------------------------------------------------------------
+        self.areas
+            .iter()
+            .find(|a| a.kind == AreaKind::Slot && a.size > 0)
+            .map(|a| a.size)
+    }
+
+    /// Add a statement of a memory map.
+    ///
+    fn add(&mut self, node: &Node) -> ParseResult<()> {
+        //----------------------------------------------------------------------
+        let (name, args) = match (&node.kind, &node.token) {
+            (NodeKind::Macro(ref name, ref args), _) => (name.as_str(), args),
+            (_, Some(ref token)) => {
+                return Err(parse_error(ParseErrorKind::UnknownStatement(
+                    token.as_str().to_string(),
+                ))
+                .at(node.location()))
+            }
+            (_, None) => {
+                return Err(ParseError::unexpected().at(node.location()))
+            }
+        };
+        if !STATEMENTS.iter().any(|&(n, _)| n == name) {
+            return Err(parse_error(ParseErrorKind::UnknownStatement(
+                name.to_string(),
+            ))
+            .at(node.location()));
+        }
+        let args: Vec<&Node> = args.iter().collect();
+        match name {
+            "region" | "ram" | "slot" => {
+                let kind = match name {
+                    "region" => AreaKind::Region,
+                    "ram" => AreaKind::Ram,
+                    _ => AreaKind::Slot,
+                };
+                Self::check_args(name, node, &args, 3, 3)?;
+                let area = Area {
+                    name: Self::name(name, args[0])?,
+                    kind,
+                    start: Self::number(args[1])?,
+                    size: Self::number(args[2])?,
+                    banks: 0,
+                    reserved: Vec::new(),
+                    fill: None,
+                    location: node.location(),
+                };
+                if self.area(&area.name).is_some() {
+                    return Err(parse_error(ParseErrorKind::DuplicateArea(
+                        area.name,
+                    ))
+                    .at(args[0].location()));
+                }
+                let what = format!("`{}`", area.name);
+                self.check_overlap(what, area.start, area.size, kind)
+                    .map_err(|e| e.at(node.location()))?;
+                if kind == AreaKind::Region {
+                    self.output.push(self.areas.len());
+                }
+                self.areas.push(area);
+            }
+            "mirror" => {
+                Self::check_args(name, node, &args, 2, 3)?;
+                let area = self.find(
+                    name,
+                    args[0],
+                    &[AreaKind::Region, AreaKind::Ram],
+                )?;
+                let size = match args.len() {
+                    3 => Self::number(args[2])?,
+                    _ => self.areas[area].size,
+                };
+                let name = self.areas[area].name.clone();
+                if size > self.areas[area].size {
+                    return Err(parse_error(ParseErrorKind::MirrorSize {
+                        overrun: size - self.areas[area].size,
+                        area: name,
+                    })
+                    .at(args[2].location()));
+                }
+                let start = Self::number(args[1])?;
+                let what = format!("the mirror of `{}`", name);
+                self.check_overlap(what, start, size, AreaKind::Ram)
+                    .map_err(|e| e.at(node.location()))?;
+                self.mirrors.push(Mirror {
+                    area: name,
+                    start,
+                    size,
+                });
+            }
+            "banks" => {
+                Self::check_args(name, node, &args, 2, 2)?;
+                let slot = self.find(name, args[0], &[AreaKind::Slot])?;
+                self.areas[slot].banks += Self::number(args[1])?;
+                if !self.output.contains(&slot) {
+                    self.output.push(slot);
+                }
+            }
+            "reserve" => {
+                Self::check_args(name, node, &args, 2, usize::MAX)?;
+                let slot = self.find(name, args[0], &[AreaKind::Slot])?;
+                for arg in args[1..].iter() {
+                    let bank = Self::number(arg)?;
+                    self.areas[slot].reserved.push(bank);
+                }
+            }
+            "fill" => {
+                Self::check_args(name, node, &args, 2, 2)?;
+                let area = self.find(
+                    name,
+                    args[0],
+                    &[AreaKind::Region, AreaKind::Slot],
+                )?;
+                let fill = Self::number(args[1])?;
+                if fill > 0xFF {
+                    return Err(parse_error(ParseErrorKind::Overflow {
+                        value: fill as i64,
+                        bits: 8,
+                    })
+                    .at(args[1].location()));
+                }
+                self.areas[area].fill = Some(fill as u8);
+            }
+            "place" => {
+                Self::check_args(name, node, &args, 2, 3)?;
+                let segment = match args[0].kind {
+                    // drop the speech-marks
+                    NodeKind::Str(ref s) => s[1..s.len() - 1].to_string(),
+                    _ => {
+                        return Err(
+                            Self::usage(name, None).at(args[0].location())
+                        )
+                    }
+                };
+                let area = self.find(
+                    name,
+                    args[1],
+                    &[AreaKind::Region, AreaKind::Ram, AreaKind::Slot],
+                )?;
+                // a bank can only be given for a slot
+                let bank = match args.get(2) {
+                    Some(arg) if self.areas[area].kind == AreaKind::Slot => {
+                        Some(Self::number(arg)?)
+                    }
+                    Some(_) => {
+                        return Err(parse_error(
+                            ParseErrorKind::WrongAreaKind {
+                                area: self.areas[area].name.clone(),
+                                expected: Self::describe(&[AreaKind::Slot]),
+                            },
+                        )
+                        .at(args[1].location()))
+                    }
+                    None => None,
+                };
+                self.places.push(Place {
+                    segment,
+                    area: self.areas[area].name.clone(),
+                    bank,
+                    location: node.location(),
+                });
+            }
+            "pin" => {
+                Self::check_args(name, node, &args, 2, 2)?;
+                let pinned = match args[0].kind {
+                    // drop the speech-marks
+                    NodeKind::Str(ref s) => {
+                        Pinned::Segment(s[1..s.len() - 1].to_string())
+                    }
+                    NodeKind::Label(ref name) => Pinned::Label(name.clone()),
+                    _ => {
+                        return Err(
+                            Self::usage(name, None).at(args[0].location())
+                        )
+                    }
+                };
+                self.pins.push(Pin {
+                    pinned,
+                    offset: Self::number(args[1])?,
+                    location: node.location(),
+                });
+            }
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    /// Check that memory about to be given, described as given, does not
+    /// overlap memory already given. A slot is paged over other memory, so
+    /// can only overlap another slot; mirrors are as RAM.
+    ///
+    fn check_overlap(
+        &self,
+        what: String,
+        start: usize,
+        size: usize,
+        kind: AreaKind,
+    ) -> ParseResult<()> {
+        //----------------------------------------------------------------------
+        let is_slot = kind == AreaKind::Slot;
+        let areas = self
+            .areas
+            .iter()
+            .filter(|a| (a.kind == AreaKind::Slot) == is_slot)
+            .map(|a| (format!("`{}`", a.name), a.start, a.size));
+        let mirrors =
+            self.mirrors.iter().filter(|_| !is_slot).map(|m| {
+                (format!("the mirror of `{}`", m.area), m.start, m.size)
+            });
+        match areas.chain(mirrors).find(|&(_, s, n)| {
+            n > 0 && size > 0 && s < start + size && start < s + n
+        }) {
+            Some((area, s, _)) => {
+                Err(parse_error(ParseErrorKind::AreaOverlap {
+                    area,
+                    other: what,
+                    address: s.max(start),
+                }))
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Check the number of arguments given to a statement.
+    ///
+    fn check_args(
+        statement: &str,
+        node: &Node,
+        args: &[&Node],
+        min: usize,
+        max: usize,
+    ) -> ParseResult<()> {
+        //----------------------------------------------------------------------
+        if args.len() < min || args.len() > max {
+            return Err(
+                Self::usage(statement, Some(args.len())).at(node.location())
+            );
+        }
+        Ok(())
+    }
+
+    /// An error giving how a statement is written, for when it is given
+    /// the wrong number, or kind, of arguments.
+    ///
+    fn usage(statement: &str, found: Option<usize>) -> ParseError {
+        //----------------------------------------------------------------------
+        let usage = STATEMENTS
+            .iter()
+            .find(|&&(n, _)| n == statement)
+            .map_or("", |&(_, usage)| usage);
+        parse_error(ParseErrorKind::StatementUsage {
+            name: statement.to_string(),
+            usage,
+            found,
+        })
+    }
+
+    /// Find the area named by an argument of a statement, which must be
+    /// one of the kinds given, returning its index.
+    ///
+    fn find(
+        &self,
+        statement: &str,
+        node: &Node,
+        kinds: &[AreaKind],
+    ) -> ParseResult<usize> {
+        //----------------------------------------------------------------------
+        let name = Self::name(statement, node)?;
+        match self.areas.iter().position(|a| a.name == name) {
+            Some(i) if kinds.contains(&self.areas[i].kind) => Ok(i),
+            Some(_) => Err(parse_error(ParseErrorKind::WrongAreaKind {
+                area: name,
+                expected: Self::describe(kinds),
+            })
+            .at(node.location())),
+            None => Err(parse_error(ParseErrorKind::UndefinedArea(name))
+                .at(node.location())),
+        }
+    }
+
+    /// Describe the kinds of area a statement takes, e.g. "a region or slot".
+    ///
+    fn describe(kinds: &[AreaKind]) -> String {
+        //----------------------------------------------------------------------
+        let names: Vec<&str> = kinds
+            .iter()
+            .map(|kind| match kind {
+                AreaKind::Region => "region",
+                AreaKind::Ram => "RAM",
+                AreaKind::Slot => "slot",
+            })
+            .collect();
+        match names.split_last() {
+            Some((last, [])) => format!("a {}", last),
+            Some((last, rest)) => format!("a {} or {}", rest.join(", "), last),
+            None => String::new(),
+        }
+    }
+
+    /// The name of an area given as an argument of a statement, e.g. `ROM`.
+    ///
+    fn name(statement: &str, node: &Node) -> ParseResult<String> {
+        //----------------------------------------------------------------------
+        match node.kind {
+            NodeKind::Atom(ref name, _) => Ok(name.clone()),
+            _ => Err(Self::usage(statement, None).at(node.location())),
+        }
+    }
+
+    /// A number given as an argument; an address, size or count.
+    ///
+    fn number(node: &Node) -> ParseResult<usize> {
+        //----------------------------------------------------------------------
+        match node.eval() {
+            Some(n) if n >= 0 => Ok(n as usize),
+            _ => {
+                Err(parse_error(ParseErrorKind::NotStatic).at(node.location()))
+            }
+        }
+    }
+}
@@ -0,0 +1,107 @@
+// retronym (C) copyright Kroc Camen 2017, 2018
+// BSD 2-clause licence; see LICENSE.TXT
+
+//! **Modules** are source files that other source files can import, e.g.
+//! `::z80`. A module is named after its file, without the extension, so
+//! "z80.rym" is the module `z80`.
+//!
+//! The `SearchPath` holds a list of directories for the `Assembler` to
+//! import modules from. A module is read from its file only when it is
+//! imported, so a directory can hold files that are not modules, or that
+//! have errors, without stopping assembly. The modules bundled with
+//! Retronym -- the CPU modules, e.g. `::z80` -- are built into the program
+//! and can be searched after the directories, so a directory can override a
+//! bundled module with its own of the same name.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The file extension of Retronym source code.
+pub const EXTENSION: &str = "rym";
+
+/// The modules bundled with Retronym, by name.
+pub const BUNDLED: &[(&str, &str)] = &[
+    ("c6502", include_str!("../../modules/c6502.rym")),
+    ("gb80", include_str!("../../modules/gb80.rym")),
+    ("z80", include_str!("../../modules/z80.rym")),
+    ("z80_base", include_str!("../../modules/z80_base.rym")),
+];
+
+/// Where a module was found on a search path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Found {
+    /// The path of the module's file, within one of the directories.
+    File(PathBuf),
+    /// The source code of a module bundled with Retronym.
+    Bundled(&'static str),
+}
+
+/// A list of directories to find modules within.
+#[derive(Debug, Default, Clone)]
+pub struct SearchPath {
+    /// The directories searched, in order.
+    dirs: Vec<PathBuf>,
+    /// Are the bundled modules searched, after the directories?
+    is_bundled: bool,
+}
+
+impl SearchPath {
+    //==========================================================================
+    /// Add a directory to the search path. Where modules have the same
+    /// name, the one found in the directory added first is used. Returns
+    /// an error if the directory cannot be read.
+    ///
+    pub fn add_dir<P: AsRef<Path>>(&mut self, dir: P) -> io::Result<()> {
+        //----------------------------------------------------------------------
+        let dir = dir.as_ref();
+        if !fs::metadata(dir)?.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("`{}` is not a directory", dir.display()),
+            ));
+        }
+        self.dirs.push(dir.to_path_buf());
+        Ok(())
+    }
+
+    /// Search the bundled modules, after the directories of the search
+    /// path, regardless of the order they are added in.
+    ///
+    pub fn add_bundled(&mut self) {
+        //----------------------------------------------------------------------
+        self.is_bundled = true;
+    }
+
+    /// Add the directories of another search path after these, and its
+    /// bundled modules, if searched.
+    ///
+    pub fn extend(&mut self, other: &SearchPath) {
+        //----------------------------------------------------------------------
+        self.dirs.extend(other.dirs.iter().cloned());
+        self.is_bundled |= other.is_bundled;
+    }
+
+    /// Find the module of the given name, e.g. "z80.rym" within the
+    /// directories for `z80`, or else the bundled module.
+    ///
+    pub fn find(&self, name: &str) -> Option<Found> {
+        //----------------------------------------------------------------------
+        let file = format!("{}.{}", name, EXTENSION);
+        if let Some(path) = self
+            .dirs
+            .iter()
+            .map(|d| d.join(&file))
+            .find(|p| p.is_file())
+        {
+            return Some(Found::File(path));
+        }
+        if !self.is_bundled {
+            return None;
+        }
+        BUNDLED
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, s)| Found::Bundled(s))
+    }
+}
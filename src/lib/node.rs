@@ -5,41 +5,67 @@
 //! it uses an enum to differentiate numbers, strings, macros and so on.
 //! AST nodes can contain other nodes, such as with expressions.
 
+use crate::error::Expansion;
 use crate::token::MaybeToken;
 
 /// The AST is made up of a series of nodes where each node is a top-level
 /// "statement" and may contain descendants based on type. In practice,
 /// Retronym's top-level statements are either macros or expressions.
-pub struct Node<'token> {
+#[derive(Clone)]
+pub struct Node {
     /// The 'type' of the node, e.g. whether this is a literal number,
     /// an expression, a macro invocation etc. This can contain nested nodes!
-    pub kind: NodeKind<'token>,
+    pub kind: NodeKind,
     /// An optional reference back to the original source code,
     /// for error messages.
-    pub token: MaybeToken<'token>,
+    pub token: MaybeToken,
     /// A node is static if it, and any descendents, contain only literal
     /// values that can be calculated without outside information.
     pub is_static: bool,
+    /// The chain of Macro invocations the node was expanded from, inner-most
+    /// first, for error messages; empty if it was not expanded from a Macro.
+    pub backtrace: Vec<Expansion>,
 }
 
-pub type MaybeNode<'token> = Option<Node<'token>>;
+pub type MaybeNode = Option<Node>;
 
 /// If you need to iterate on Nodes,
 /// you can use this shorthand type.
 ///
-pub type NodeIter<'token> = std::slice::Iter<'token, Node<'token>>;
+pub type NodeIter<'a> = std::slice::Iter<'a, Node>;
 
+use crate::atom::Hint;
 use crate::expr::Expr;
 use crate::list::List;
 use crate::primitive::Primitive;
+use crate::r#macro::{Directive, If, Macro};
 
-#[derive(Debug)]
-pub enum NodeKind<'token> {
+#[derive(Debug, Clone)]
+pub enum NodeKind {
     /// An empty node.
     Void,
     /// An Atom definition. Defines a new Atom and exports it.
     /// When the final linking occurs, all Atoms used must be defined.
-    DefAtom(String),
+    /// The documentation comment preceding the definition is kept.
+    DefAtom(String, Option<String>),
+    /// A Macro definition. Defines a new Macro and exports it.
+    DefMacro(Box<Macro>),
+    /// A module import, e.g. `::z80`; the name of the module.
+    Import(String),
+    /// The symbols that a module exports, e.g. `export { A, ld }`;
+    /// a list of Atoms and Macro names.
+    Export(Box<List>),
+    /// A Label definition, e.g. `:loop` without indent; names the address
+    /// of the data that follows. Unlike the other definitions, where it
+    /// appears amongst the statements matters. The documentation comment
+    /// preceding the definition is kept.
+    DefLabel(String, Option<String>),
+    /// The segment the data that follows is placed into,
+    /// e.g. `segment code`; the name of the segment.
+    Segment(String),
+    /// Padding of the segment to a boundary, e.g. `align $100`;
+    /// the boundary, a power of two.
+    Align(usize),
     /// A primitive type.
     /// `byte`, `word`, `long`, for example.
     Primitive(Primitive),
@@ -48,15 +74,62 @@ pub enum NodeKind<'token> {
     /// have been parsed, so the AST stores the record type as a `List` of
     /// `Nodes` and resolves the struct names later to build the actual
     /// record-type.
-    Record(Box<List<'token>>),
+    Record(Box<List>),
     /// A list.
-    List(Box<List<'token>>),
+    List(Box<List>),
     /// An expression -- i.e. a calculation
-    Expr(Box<Expr<'token>>),
-    /// An Atom invocation.
-    Atom(String),
-    /// A Macro invocation.
-    Macro(String),
+    Expr(Box<Expr>),
+    /// Indirection given to a Macro, e.g. `(HL)` or `[HL]`;
+    /// the style of brackets and the list within.
+    Indirect(Brackets, Box<List>),
+    /// A Label recalled as a value, e.g. `jp :loop`; the name of the Label.
+    /// Its value, an address, is not known until the Object is linked.
+    Label(String),
+    /// The number of the bank of ROM a Label lies in, e.g. `bank(:level)`;
+    /// the name of the Label. Not known until the Object is linked.
+    Bank(String),
+    /// A value relative to the address that follows it, e.g. `rel :loop`
+    /// for a relative jump. A number is taken as the distance itself.
+    Relative(Box<Node>),
+    /// An Atom invocation; the name of the Atom and the hint given, if any.
+    /// The hint is kept for display, but is not part of the Atom's name.
+    Atom(String, Hint),
+    /// A Macro invocation; the name of the Macro and the list of arguments.
+    Macro(String, Box<List>),
+    /// A Macro parameter, within the body of a Macro definition. The name
+    /// does not include the backslash.
+    Param(String),
+    /// A Macro parameter given a type, within the pattern of a Macro
+    /// definition, e.g. `byte \zp`. Only matches a value that fits the type.
+    TypedParam(Primitive, String),
+    /// A Macro directive, within the body of a Macro definition.
+    Directive(Directive),
+    /// A conditional block, within the body of a Macro definition.
+    If(Box<If>),
+    /// A warning, within the body of a Macro definition, e.g.
+    /// `\warn "message"`. The message is reported when the Macro is
+    /// expanded, but assembly continues.
+    Warn(String),
+    /// An error, within the body of a Macro definition, e.g.
+    /// `\error "message"`. Expanding the Macro stops assembly.
+    Error(String),
+    /// An option that must be enabled to use a Macro form, within the body
+    /// of the Macro definition, e.g. `\requires "z80-undocumented"`.
+    Requires(String),
+    /// An option to enable, within the body of a Macro definition, e.g.
+    /// `\enable "65c02"`. The option is enabled when the Macro is expanded,
+    /// for the statements that follow.
+    Enable(String),
+    /// An option to disable, within the body of a Macro definition, e.g.
+    /// `\disable "65816-a16"`. The opposite of `Enable`.
+    Disable(String),
+    /// An alternative to the statements of a Macro form, within its body,
+    /// e.g. `\relax { jr \nn }`; used in their place where it fits, if
+    /// the "relax" option is enabled.
+    Relax(Box<List>),
+    /// A site that can take more than one form once the Object is linked;
+    /// the expanded statements of each form, the natural form first.
+    Joint(Vec<List>),
     /// A string literal. Since strings are self-contained lists, these are
     /// not treated as expression values.
     Str(String),
@@ -64,7 +137,17 @@ pub enum NodeKind<'token> {
     Value(Value),
 }
 
-#[derive(Debug)]
+/// The style of brackets used for indirection. A CPU module can use either
+/// style or, where it uses both, give them different meanings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Brackets {
+    /// Parentheses, e.g. `(HL)`.
+    Round,
+    /// Square brackets, e.g. `[HL]`.
+    Square,
+}
+
+#[derive(Debug, Clone)]
 pub enum Value {
     /// An integer literal value.
     Int(i32),
@@ -87,7 +170,7 @@ impl Display for Value {
     }
 }
 
-impl Default for Node<'_> {
+impl Default for Node {
     //==========================================================================
     fn default() -> Self {
         //----------------------------------------------------------------------
@@ -95,17 +178,14 @@ impl Default for Node<'_> {
             kind: NodeKind::Void,
             token: None,
             is_static: true,
+            backtrace: Vec::new(),
         }
     }
 }
 
-impl<'token> Node<'token> {
+impl Node {
     //==========================================================================
-    pub fn new_expr(
-        left: Node<'token>,
-        oper: Token<'token>,
-        right: Node<'token>,
-    ) -> Self {
+    pub fn new_expr(left: Node, oper: Token, right: Node) -> Self {
         //----------------------------------------------------------------------
         Self {
             // the expression can only be static if *both* sides
@@ -113,6 +193,7 @@ impl<'token> Node<'token> {
             is_static: left.is_static && right.is_static,
             kind: NodeKind::Expr(Box::new(Expr::new(left, &oper, right))),
             token: Some(oper),
+            backtrace: Vec::new(),
         }
     }
 
@@ -121,20 +202,302 @@ impl<'token> Node<'token> {
     /// meaning that you cannot just convert the token into a node like with
     /// the literals, e.g. `Node::from(token)`.
     ///
-    pub fn new_atom(atom: Token<'token>) -> Self {
+    /// The documentation comment preceding the definition, if any,
+    /// is kept with the Atom.
+    ///
+    pub fn new_atom(atom: Token, doc: Option<String>) -> Self {
         //----------------------------------------------------------------------
         Self {
             // create the Atom and embed it in the node
-            kind: NodeKind::DefAtom(atom.to_string()),
+            kind: NodeKind::DefAtom(atom.to_string(), doc),
             // store the reference back to the original source code;
             // this will be at the Atom name, not the "atom" keyword
             token: Some(atom),
             // node is static because it does not require name resolution
             is_static: true,
+            backtrace: Vec::new(),
+        }
+    }
+
+    /// Returns a node that defines a Label, from the token of the Label
+    /// name, e.g. `:loop`, and the documentation comment, if any.
+    ///
+    pub fn new_def_label(token: Token, doc: Option<String>) -> Self {
+        //----------------------------------------------------------------------
+        Self {
+            kind: match token.kind() {
+                TokenKind::Label(s) => NodeKind::DefLabel(s, doc),
+                _ => panic!("Not a label `Token`."),
+            },
+            token: Some(token),
+            is_static: true,
+            backtrace: Vec::new(),
+        }
+    }
+
+    /// Returns a node that begins (or returns to) a segment,
+    /// from the token of the "segment" keyword.
+    ///
+    pub fn new_segment(token: Token, name: String) -> Self {
+        //----------------------------------------------------------------------
+        Self {
+            kind: NodeKind::Segment(name),
+            token: Some(token),
+            is_static: true,
+            backtrace: Vec::new(),
+        }
+    }
+
+    /// Returns a node that pads the segment to a boundary,
+    /// from the token of the "align" keyword.
+    ///
+    pub fn new_align(token: Token, boundary: usize) -> Self {
+        //----------------------------------------------------------------------
+        Self {
+            kind: NodeKind::Align(boundary),
+            token: Some(token),
+            is_static: true,
+            backtrace: Vec::new(),
+        }
+    }
+
+    /// Returns a node that defines a new Macro.
+    ///
+    pub fn new_def_macro(r#macro: Macro) -> Self {
+        //----------------------------------------------------------------------
+        Self {
+            // the token of the macro name,
+            // for the reference back to the source code
+            token: Some(r#macro.token().clone()),
+            kind: NodeKind::DefMacro(Box::new(r#macro)),
+            is_static: true,
+            backtrace: Vec::new(),
+        }
+    }
+
+    /// Returns a node for a module import, e.g. `::z80`.
+    ///
+    pub fn new_import(token: Token) -> Self {
+        //----------------------------------------------------------------------
+        Self {
+            kind: match token.kind() {
+                TokenKind::Import(s) => NodeKind::Import(s),
+                _ => panic!("Not an import `Token`."),
+            },
+            token: Some(token),
+            is_static: true,
+            backtrace: Vec::new(),
+        }
+    }
+
+    /// Returns a node for the list of symbols a module exports,
+    /// from the token of the "export" keyword.
+    ///
+    pub fn new_export(token: Token, list: List) -> Self {
+        //----------------------------------------------------------------------
+        Self {
+            kind: NodeKind::Export(Box::new(list)),
+            token: Some(token),
+            is_static: true,
+            backtrace: Vec::new(),
+        }
+    }
+
+    /// Returns a node for a Macro invocation, from the token of the
+    /// Macro name and the list of arguments given.
+    ///
+    pub fn new_macro(token: Token, args: List) -> Self {
+        //----------------------------------------------------------------------
+        Self {
+            kind: NodeKind::Macro(token.to_string(), Box::new(args)),
+            token: Some(token),
+            // a macro's output cannot be known until it is expanded
+            is_static: false,
+            backtrace: Vec::new(),
+        }
+    }
+
+    /// Returns a node for a conditional block within a Macro body.
+    ///
+    pub fn new_if(token: Token, cond: If) -> Self {
+        //----------------------------------------------------------------------
+        Self {
+            kind: NodeKind::If(Box::new(cond)),
+            token: Some(token),
+            is_static: false,
+            backtrace: Vec::new(),
+        }
+    }
+
+    /// Returns a node for a parameter given a type within the pattern of a
+    /// Macro definition, from the token of the parameter.
+    ///
+    pub fn new_typed_param(primitive: Primitive, token: Token) -> Self {
+        //----------------------------------------------------------------------
+        Self {
+            kind: match token.kind() {
+                TokenKind::Param(s) => NodeKind::TypedParam(primitive, s),
+                _ => panic!("Not a parameter `Token`."),
+            },
+            token: Some(token),
+            is_static: false,
+            backtrace: Vec::new(),
+        }
+    }
+
+    /// Returns a node for a warning within a Macro body, from the token of
+    /// the `\warn` directive and the message (without speech-marks).
+    ///
+    pub fn new_warn(token: Token, message: String) -> Self {
+        //----------------------------------------------------------------------
+        Self {
+            kind: NodeKind::Warn(message),
+            token: Some(token),
+            is_static: false,
+            backtrace: Vec::new(),
+        }
+    }
+
+    /// Returns a node for an error within a Macro body, from the token of
+    /// the `\error` directive and the message (without speech-marks).
+    ///
+    pub fn new_error(token: Token, message: String) -> Self {
+        //----------------------------------------------------------------------
+        Self {
+            kind: NodeKind::Error(message),
+            token: Some(token),
+            is_static: false,
+            backtrace: Vec::new(),
+        }
+    }
+
+    /// Returns a node for an option required by a Macro form, from the token
+    /// of the `\requires` directive and the option (without speech-marks).
+    ///
+    pub fn new_requires(token: Token, option: String) -> Self {
+        //----------------------------------------------------------------------
+        Self {
+            kind: NodeKind::Requires(option),
+            token: Some(token),
+            is_static: false,
+            backtrace: Vec::new(),
+        }
+    }
+
+    /// Returns a node for an option to enable within a Macro body, from the
+    /// token of the `\enable` directive and the option (without
+    /// speech-marks).
+    ///
+    pub fn new_enable(token: Token, option: String) -> Self {
+        //----------------------------------------------------------------------
+        Self {
+            kind: NodeKind::Enable(option),
+            token: Some(token),
+            is_static: false,
+            backtrace: Vec::new(),
+        }
+    }
+
+    /// Returns a node for an option to disable within a Macro body, from the
+    /// token of the `\disable` directive and the option (without
+    /// speech-marks).
+    ///
+    pub fn new_disable(token: Token, option: String) -> Self {
+        //----------------------------------------------------------------------
+        Self {
+            kind: NodeKind::Disable(option),
+            token: Some(token),
+            is_static: false,
+            backtrace: Vec::new(),
+        }
+    }
+
+    /// Returns a node for a value relative to the address that follows it,
+    /// from the token of the "rel" keyword and the value, e.g. `rel :loop`.
+    ///
+    pub fn new_relative(token: Token, value: Node) -> Self {
+        //----------------------------------------------------------------------
+        Self {
+            is_static: value.is_static,
+            kind: NodeKind::Relative(Box::new(value)),
+            token: Some(token),
+            backtrace: Vec::new(),
+        }
+    }
+
+    /// Returns a node for an alternative to the statements of a Macro form,
+    /// from the token of the `\relax` directive and the block given.
+    ///
+    pub fn new_relax(token: Token, list: List) -> Self {
+        //----------------------------------------------------------------------
+        Self {
+            kind: NodeKind::Relax(Box::new(list)),
+            token: Some(token),
+            is_static: false,
+            backtrace: Vec::new(),
+        }
+    }
+
+    /// Returns a node for a site that can take any of the forms given,
+    /// the natural form first, from the token of the Macro invocation.
+    ///
+    pub fn new_joint(token: MaybeToken, forms: Vec<List>) -> Self {
+        //----------------------------------------------------------------------
+        Self {
+            kind: NodeKind::Joint(forms),
+            token,
+            is_static: false,
+            backtrace: Vec::new(),
+        }
+    }
+
+    /// Returns a node for a literal value that was not written as such in the
+    /// source code, e.g. the result of a calculation. The token given, if
+    /// any, is the source of the value for the purpose of error messages.
+    ///
+    pub fn new_value(value: Value, token: MaybeToken) -> Self {
+        //----------------------------------------------------------------------
+        Self {
+            kind: NodeKind::Value(value),
+            token,
+            is_static: true,
+            backtrace: Vec::new(),
         }
     }
 
-    pub fn new_record(list: List<'token>) -> Self {
+    /// Returns a node for a list, such as the parenthesised list `(1, 2, 3)`.
+    ///
+    pub fn new_list(list: List) -> Self {
+        //----------------------------------------------------------------------
+        Self {
+            is_static: list.is_static(),
+            kind: NodeKind::List(Box::new(list)),
+            token: None,
+            backtrace: Vec::new(),
+        }
+    }
+
+    /// Returns a node for indirection, e.g. `(HL)` or `[HL]`, from the token
+    /// of the opening bracket and the list within the brackets.
+    ///
+    pub fn new_indirect(token: Token, list: List) -> Self {
+        //----------------------------------------------------------------------
+        Self {
+            is_static: list.is_static(),
+            kind: NodeKind::Indirect(
+                if token.is_bracket_open() {
+                    Brackets::Square
+                } else {
+                    Brackets::Round
+                },
+                Box::new(list),
+            ),
+            token: Some(token),
+            backtrace: Vec::new(),
+        }
+    }
+
+    pub fn new_record(list: List) -> Self {
         //----------------------------------------------------------------------
         Self {
             kind: NodeKind::Record(Box::new(list)),
@@ -143,20 +506,135 @@ impl<'token> Node<'token> {
             token: None,
             // TODO: the list can report if it is static to us
             is_static: false,
+            backtrace: Vec::new(),
         }
     }
 
-    /// Does this Node contain data? That is, a literal value, expression
-    /// or a symbol that can be resolved into a value.
+    /// Record that the Node was expanded from a Macro invocation. Called on
+    /// the way back out of each level of expansion, so the backtrace is
+    /// built inner-most first. The statements of a joint's forms, or of an
+    /// alternative, record it too.
     ///
-    pub fn is_data(&self) -> bool {
+    pub fn in_expansion(&mut self, step: &Expansion) {
+        //----------------------------------------------------------------------
+        self.backtrace.push(step.clone());
+        match self.kind {
+            NodeKind::Joint(ref mut forms) => {
+                for node in forms.iter_mut().flat_map(List::iter_mut) {
+                    node.in_expansion(step);
+                }
+            }
+            NodeKind::Relax(ref mut list) => {
+                for node in list.iter_mut() {
+                    node.in_expansion(step);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// The location in the source code of the Node's token, if it has one.
+    ///
+    pub fn location(&self) -> Option<Location> {
+        //----------------------------------------------------------------------
+        self.token.as_ref().map(Token::location)
+    }
+
+    /// Calculate the value of the Node, if it is a number or an expression
+    /// that can be calculated without outside information. Returns `None`
+    /// for any other kind of Node, or if the calculation fails, e.g.
+    /// division by zero.
+    ///
+    pub fn eval(&self) -> Option<i64> {
+        //----------------------------------------------------------------------
+        match self.kind {
+            NodeKind::Value(Value::Int(i)) => Some(i64::from(i)),
+            NodeKind::Value(Value::UInt(u)) => Some(i64::from(u)),
+            NodeKind::Expr(ref expr) => expr.eval(),
+            NodeKind::Relative(ref value) => value.eval(),
+            _ => None,
+        }
+    }
+
+    /// Is the value of the Node deferred until the Object is linked? That
+    /// is, a Label (or its bank), or an expression of Labels and numbers,
+    /// whose value is not known until the Labels have been placed.
+    ///
+    pub fn is_deferred(&self) -> bool {
+        //----------------------------------------------------------------------
+        self.eval().is_none() && self.is_linkable()
+    }
+
+    /// Can the Node be calculated once Labels have addresses?
+    ///
+    fn is_linkable(&self) -> bool {
         //----------------------------------------------------------------------
         match self.kind {
-            NodeKind::Value(_) | NodeKind::Expr(_) | NodeKind::Str(_) => true,
+            NodeKind::Label(_) | NodeKind::Bank(_) | NodeKind::Value(_) => true,
+            NodeKind::Expr(ref expr) => {
+                expr.left.is_linkable() && expr.right.is_linkable()
+            }
+            NodeKind::Relative(ref value) => value.is_linkable(),
             _ => false,
         }
     }
 
+    /// Does this Node contain data? That is, a literal value, expression
+    /// or a symbol that can be resolved into a value.
+    ///
+    pub fn is_data(&self) -> bool {
+        //----------------------------------------------------------------------
+        matches!(
+            self.kind,
+            NodeKind::Value(_)
+                | NodeKind::Expr(_)
+                | NodeKind::Str(_)
+                | NodeKind::Label(_)
+                | NodeKind::Bank(_)
+                | NodeKind::Relative(_)
+        )
+    }
+
+    /// Is this Node the bank of a Label, e.g. `bank(:level)`?
+    ///
+    pub fn is_bank(&self) -> bool {
+        //----------------------------------------------------------------------
+        matches!(self.kind, NodeKind::Bank(_))
+    }
+
+    /// Is this Node a value relative to the address that follows it,
+    /// e.g. `rel :loop`?
+    ///
+    pub fn is_relative(&self) -> bool {
+        //----------------------------------------------------------------------
+        matches!(self.kind, NodeKind::Relative(_))
+    }
+
+    /// Is this Node a site that can take more than one form?
+    ///
+    pub fn is_joint(&self) -> bool {
+        //----------------------------------------------------------------------
+        matches!(self.kind, NodeKind::Joint(_))
+    }
+
+    /// Is this Node a Label definition?
+    ///
+    pub fn is_label_def(&self) -> bool {
+        //----------------------------------------------------------------------
+        matches!(self.kind, NodeKind::DefLabel(..))
+    }
+
+    /// Does this Node place the data that follows? That is, a Label
+    /// definition, the beginning of a segment, or alignment.
+    ///
+    pub fn is_placement(&self) -> bool {
+        //----------------------------------------------------------------------
+        matches!(
+            self.kind,
+            NodeKind::DefLabel(..) | NodeKind::Segment(_) | NodeKind::Align(_)
+        )
+    }
+
     /// Is this Node a Record?
     ///
     /// The AST only stores Records as a `List` because any struct-nesting
@@ -165,51 +643,73 @@ impl<'token> Node<'token> {
     ///
     pub fn is_record(&self) -> bool {
         //----------------------------------------------------------------------
-        match self.kind {
-            NodeKind::Record(_) => true,
-            _ => false,
-        }
+        matches!(self.kind, NodeKind::Record(_))
     }
 
     /// Is this Node an Atom definition?
     ///
     pub fn is_atom_def(&self) -> bool {
         //----------------------------------------------------------------------
-        match self.kind {
-            NodeKind::DefAtom(_) => true,
-            _ => false,
-        }
+        matches!(self.kind, NodeKind::DefAtom(..))
+    }
+
+    /// Is this Node a Macro definition?
+    ///
+    pub fn is_macro_def(&self) -> bool {
+        //----------------------------------------------------------------------
+        matches!(self.kind, NodeKind::DefMacro(_))
+    }
+
+    /// Is this Node a module import?
+    ///
+    pub fn is_import(&self) -> bool {
+        //----------------------------------------------------------------------
+        matches!(self.kind, NodeKind::Import(_))
+    }
+
+    /// Is this Node the list of symbols a module exports?
+    ///
+    pub fn is_export(&self) -> bool {
+        //----------------------------------------------------------------------
+        matches!(self.kind, NodeKind::Export(_))
+    }
+
+    /// Is this Node a definition of any kind? Definitions are not data
+    /// and take no part in the packing of data. Imports and exports
+    /// are counted as definitions.
+    ///
+    pub fn is_def(&self) -> bool {
+        //----------------------------------------------------------------------
+        self.is_atom_def()
+            || self.is_macro_def()
+            || self.is_import()
+            || self.is_export()
     }
 
     /// Is this Node an Atom reference?
     ///
     pub fn is_atom(&self) -> bool {
         //----------------------------------------------------------------------
-        match self.kind {
-            NodeKind::Atom(_) => true,
-            _ => false,
-        }
+        matches!(self.kind, NodeKind::Atom(..))
     }
 
     /// Is this Node a Macro invocation?
     ///
     pub fn is_macro(&self) -> bool {
         //----------------------------------------------------------------------
-        match self.kind {
-            NodeKind::Macro(_) => true,
-            _ => false,
-        }
+        matches!(self.kind, NodeKind::Macro(..))
     }
 }
 
 //==============================================================================
 
+use crate::error::Location;
 use crate::token::{Token, TokenKind};
 use std::convert::From;
 
-impl<'token> From<Token<'token>> for Node<'token> {
+impl From<Token> for Node {
     //==========================================================================
-    fn from(token: Token<'token>) -> Self {
+    fn from(token: Token) -> Self {
         //----------------------------------------------------------------------
         Self {
             kind: match token.kind() {
@@ -217,8 +717,18 @@ impl<'token> From<Token<'token>> for Node<'token> {
                 TokenKind::Int(i) => NodeKind::Value(Value::Int(i)),
                 TokenKind::Hex(h) => NodeKind::Value(Value::UInt(h)),
                 TokenKind::Bin(b) => NodeKind::Value(Value::UInt(b)),
-                TokenKind::Atom(s) => NodeKind::Atom(s),
-                TokenKind::Macro(s) => NodeKind::Macro(s),
+                TokenKind::Atom(s) => {
+                    let (name, hint) = Hint::split(&s);
+                    NodeKind::Atom(name.to_string(), hint)
+                }
+                TokenKind::Label(s) => NodeKind::Label(s),
+                TokenKind::Bank(s) => NodeKind::Bank(s),
+                // a macro invocation without arguments;
+                // use `Node::new_macro` to provide them
+                TokenKind::Macro(s) => NodeKind::Macro(s, Box::default()),
+                TokenKind::Param(s) => NodeKind::Param(s),
+                TokenKind::Directive(d) => NodeKind::Directive(d),
+                TokenKind::String(s) => NodeKind::Str(s),
                 _ => {
                     panic!("Not a `Token` that can be converted into a `Node`.")
                 }
@@ -228,13 +738,15 @@ impl<'token> From<Token<'token>> for Node<'token> {
             // embed the original token with the source-code location.
             // this'll be used if we need to print an error message
             token: Some(token),
+            backtrace: Vec::new(),
         }
     }
 }
 
+use itertools::Itertools;
 use std::fmt::{self, *};
 
-impl Display for Node<'_> {
+impl Display for Node {
     //==========================================================================
     /// Pretty-prints a `Node` (and its descendants),
     /// essentially outputting normalised source code
@@ -243,32 +755,93 @@ impl Display for Node<'_> {
         //----------------------------------------------------------------------
         match self.kind {
             NodeKind::Void => write!(f, "<VOID>"),
-            NodeKind::DefAtom(ref a) => write!(f, "atom {}", a),
+            NodeKind::DefAtom(ref a, _) => write!(f, "atom {}", a),
+            NodeKind::DefMacro(ref m) => write!(f, "{}", m),
+            NodeKind::Import(ref m) => write!(f, "::{}", m),
+            NodeKind::Export(ref l) => {
+                write!(f, "export {{ {} }}", l.iter().join(", "))
+            }
+            NodeKind::DefLabel(ref l, _) => write!(f, ":{}", l),
+            NodeKind::Segment(ref s) => write!(f, "segment {}", s),
+            NodeKind::Align(a) => write!(f, "align {}", a),
             NodeKind::Primitive(ref p) => write!(f, "{}", p),
             NodeKind::Record(ref l) => write!(f, "{}", l),
-            NodeKind::List(ref l) => write!(f, "{}", l),
+            NodeKind::List(ref l) => write!(f, "({})", l.iter().join(", ")),
+            NodeKind::Indirect(Brackets::Round, ref l) => {
+                write!(f, "({})", l.iter().join(", "))
+            }
+            NodeKind::Indirect(Brackets::Square, ref l) => {
+                write!(f, "[{}]", l.iter().join(", "))
+            }
             NodeKind::Expr(ref x) => write!(f, "{}", x),
-            NodeKind::Atom(ref a) => write!(f, "{}", a),
-            NodeKind::Macro(ref m) => write!(f, "{}", m),
+            NodeKind::Atom(ref a, ref h) => write!(f, "{}{}", a, h),
+            NodeKind::Label(ref l) => write!(f, ":{}", l),
+            NodeKind::Bank(ref l) => write!(f, "bank(:{})", l),
+            NodeKind::Relative(ref v) => write!(f, "rel {}", v),
+            NodeKind::Macro(ref m, ref l) if l.is_empty() => write!(f, "{}", m),
+            NodeKind::Macro(ref m, ref l) => {
+                write!(f, "{} {}", m, l.iter().join(", "))
+            }
+            NodeKind::Param(ref p) => write!(f, "\\{}", p),
+            NodeKind::TypedParam(ref t, ref p) => write!(f, "{} \\{}", t, p),
+            NodeKind::Directive(ref d) => write!(f, "{}", d),
+            NodeKind::If(ref i) => write!(f, "{}", i),
+            NodeKind::Warn(ref w) => write!(f, "\\warn \"{}\"", w),
+            NodeKind::Error(ref e) => write!(f, "\\error \"{}\"", e),
+            NodeKind::Requires(ref o) => write!(f, "\\requires \"{}\"", o),
+            NodeKind::Enable(ref o) => write!(f, "\\enable \"{}\"", o),
+            NodeKind::Disable(ref o) => write!(f, "\\disable \"{}\"", o),
+            NodeKind::Relax(ref l) => {
+                write!(f, "\\relax {{ {} }}", l.iter().join(" "))
+            }
+            NodeKind::Joint(ref forms) => write!(
+                f,
+                "{}",
+                forms
+                    .iter()
+                    .map(|l| format!("{{ {} }}", l.iter().join(" ")))
+                    .join(" \\relax ")
+            ),
             NodeKind::Str(ref s) => write!(f, "{}", s),
             NodeKind::Value(ref v) => write!(f, "{}", v),
         }
     }
 }
 
-impl Debug for Node<'_> {
+impl Debug for Node {
     //==========================================================================
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         //----------------------------------------------------------------------
         match self.kind {
             NodeKind::Void => write!(f, "<VOID>"),
-            NodeKind::DefAtom(ref a) => write!(f, "atom {:?}", a),
+            NodeKind::DefAtom(ref a, _) => write!(f, "atom {:?}", a),
+            NodeKind::DefMacro(ref m) => write!(f, "{:?}", m),
+            NodeKind::Import(ref m) => write!(f, "::{}", m),
+            NodeKind::Export(ref l) => write!(f, "export {:?}", l),
+            NodeKind::DefLabel(ref l, _) => write!(f, ":{:?}", l),
+            NodeKind::Segment(ref s) => write!(f, "segment {:?}", s),
+            NodeKind::Align(a) => write!(f, "align {}", a),
             NodeKind::Primitive(ref p) => write!(f, "{:?}", p),
             NodeKind::Record(ref l) => write!(f, "{:?}", l),
             NodeKind::List(ref l) => write!(f, "{:?}", l),
+            NodeKind::Indirect(ref b, ref l) => write!(f, "{:?} {:?}", b, l),
             NodeKind::Expr(ref x) => write!(f, "{:?}", x),
-            NodeKind::Atom(ref a) => write!(f, "{:?}", a),
-            NodeKind::Macro(ref m) => write!(f, "{:?}", m),
+            NodeKind::Atom(ref a, ref h) => write!(f, "{:?}{}", a, h),
+            NodeKind::Label(ref l) => write!(f, ":{:?}", l),
+            NodeKind::Bank(ref l) => write!(f, "bank(:{:?})", l),
+            NodeKind::Relative(ref v) => write!(f, "rel {:?}", v),
+            NodeKind::Macro(ref m, ref l) => write!(f, "{:?} {:?}", m, l),
+            NodeKind::Param(ref p) => write!(f, "\\{}", p),
+            NodeKind::TypedParam(ref t, ref p) => write!(f, "{} \\{}", t, p),
+            NodeKind::Directive(ref d) => write!(f, "{}", d),
+            NodeKind::If(ref i) => write!(f, "{}", i),
+            NodeKind::Warn(ref w) => write!(f, "\\warn \"{}\"", w),
+            NodeKind::Error(ref e) => write!(f, "\\error \"{}\"", e),
+            NodeKind::Requires(ref o) => write!(f, "\\requires \"{}\"", o),
+            NodeKind::Enable(ref o) => write!(f, "\\enable \"{}\"", o),
+            NodeKind::Disable(ref o) => write!(f, "\\disable \"{}\"", o),
+            NodeKind::Relax(ref l) => write!(f, "\\relax {:?}", l),
+            NodeKind::Joint(ref forms) => write!(f, "{:?}", forms),
             NodeKind::Str(ref s) => write!(f, "{}", s),
             NodeKind::Value(ref v) => write!(f, "{}", v),
         }
@@ -4,28 +4,184 @@
 //! An Object file is a partially-compiled representation of a source code
 //! file. Each Object *always* maps to one source file. Symbols not defined
 //! in the file are "imports" to be linked against other Objects.
+//!
+//! A source file can import modules, e.g. `::z80`, each of which is
+//! assembled into an Object of its own. Only the symbols a module exports,
+//! e.g. `export { A, ld }`, can be used by the Objects that import it;
+//! either as they are, or, for Macros, qualified by the module's name, e.g.
+//! `z80.ld`. A module can export symbols it imports, and a Macro defined
+//! with the name of one imported extends it; see `find_macro`.
+//!
+//! The documentation comments of the Object's definitions are kept with the
+//! symbols, for tooling, e.g. `Atom::doc` and `Form::doc`.
+//!
+//! The data is packed into segments, e.g. `segment code`, each placed in
+//! memory separately when the Objects are linked. Data before the first
+//! `segment` statement is packed into the "default" segment.
 
 use crate::atom::Atom;
+use crate::r#macro::Macro;
 use crate::r#struct::Struct;
-use crate::table::Table;
 use std::collections::HashMap;
 
-pub type Structs<'token> = HashMap<String, Struct<'token>>;
-pub type Atoms<'token> = HashMap<String, Atom<'token>>;
-pub type Tables<'token> = Vec<Table<'token>>;
+pub type Structs = HashMap<String, Struct>;
+pub type Atoms = HashMap<String, Atom>;
+pub type Macros = HashMap<String, Macro>;
 
 #[derive(Default)]
-pub struct Object<'token> {
-    pub atoms: Atoms<'token>,
-    _tables: Tables<'token>,
-    _structs: Structs<'token>,
+pub struct Object {
+    /// The name of the module the Object was assembled from, e.g. "z80",
+    /// or empty for source code that was not imported.
+    name: String,
+    /// The modules the Object imports, by name, in the order imported.
+    imports: Vec<String>,
+    /// The symbols the Object exports, by name,
+    /// and where in the source code they were exported.
+    exports: HashMap<String, Location>,
+    pub atoms: Atoms,
+    pub macros: Macros,
+    _structs: Structs,
+    /// The segments data is packed into, in the order first used.
+    segments: Vec<Segment>,
+    /// The index of the segment data is currently packed into.
+    segment: usize,
 }
 
 use crate::error::*;
-use crate::node::Node;
+use crate::node::{Node, NodeKind};
+use crate::segment::{Label, Segment};
+use crate::table::Table;
 
-impl<'token> Object<'token> {
+impl Object {
     //==========================================================================
+    /// Create an empty Object for the module of the given name.
+    ///
+    pub fn new(name: &str) -> Self {
+        //----------------------------------------------------------------------
+        Self {
+            name: name.to_string(),
+            segments: vec![Segment::new(Segment::DEFAULT)],
+            ..Self::default()
+        }
+    }
+
+    /// The name of the module the Object was assembled from,
+    /// or empty for source code that was not imported.
+    ///
+    pub fn name(&self) -> &str {
+        //----------------------------------------------------------------------
+        &self.name
+    }
+
+    /// Does the Object export the symbol of the given name?
+    ///
+    pub fn is_exported(&self, name: &str) -> bool {
+        //----------------------------------------------------------------------
+        self.exports.contains_key(name)
+    }
+
+    /// The segments of the Object, in the order first used.
+    ///
+    pub fn segments(&self) -> &[Segment] {
+        //----------------------------------------------------------------------
+        &self.segments
+    }
+
+    /// The packed binary data of the Object; each segment in the order
+    /// first used, one after the other, padded to its alignment. Values not
+    /// known until the Object is linked, such as the addresses of Labels,
+    /// are left as zero.
+    ///
+    pub fn data(&self) -> Vec<u8> {
+        //----------------------------------------------------------------------
+        let mut data = Vec::new();
+        for segment in self.segments.iter() {
+            let len = data.len().next_multiple_of(segment.align());
+            data.resize(len, 0);
+            data.extend(segment.to_bytes());
+        }
+        data
+    }
+
+    /// Pack a Table into the current segment.
+    ///
+    pub fn pack(&mut self, table: &Table<'_>) -> ParseResult<()> {
+        //----------------------------------------------------------------------
+        table.pack(&mut self.segments[self.segment])
+    }
+
+    /// Append a site that can take any of the forms given, each packed as a
+    /// segment, to the current segment; the natural form first.
+    ///
+    pub fn joint(&mut self, forms: Vec<Segment>, location: Option<Location>) {
+        //----------------------------------------------------------------------
+        self.segments[self.segment].write_joint(forms, location);
+    }
+
+    /// Take the segments of the Object, in the order first used.
+    ///
+    pub fn into_segments(self) -> Vec<Segment> {
+        //----------------------------------------------------------------------
+        self.segments
+    }
+
+    /// Place the data that follows a placement Node; that is, define a
+    /// Label, change to another segment, or align the current segment.
+    ///
+    /// Returns `None` if successful, otherwise if attempting to define
+    /// a Label that already exists, returns a `ParseError`.
+    ///
+    pub fn place(&mut self, node: &Node) -> MaybeError {
+        //----------------------------------------------------------------------
+        match node.kind {
+            NodeKind::DefLabel(ref name, ref doc) => {
+                if self.find_label(name).is_some() {
+                    //TODO: include the location of the original definition
+                    return Some(
+                        parse_error(ParseErrorKind::DuplicateLabel(
+                            name.clone(),
+                        ))
+                        .at(node.location()),
+                    );
+                }
+                self.segments[self.segment].define(
+                    name,
+                    node.location(),
+                    doc.clone(),
+                );
+            }
+            NodeKind::Segment(ref name) => {
+                self.segment =
+                    match self.segments.iter().position(|s| s.name() == name) {
+                        Some(i) => i,
+                        None => {
+                            self.segments.push(Segment::new(name));
+                            self.segments.len() - 1
+                        }
+                    };
+            }
+            NodeKind::Align(boundary) => {
+                self.segments[self.segment].align_to(boundary);
+            }
+            _ => panic!("Not a placement `Node`."),
+        }
+        None
+    }
+
+    /// Find a Label defined by the Object, by name;
+    /// returns the segment it is defined in along with it.
+    ///
+    pub fn find_label(&self, name: &str) -> Option<(&Segment, &Label)> {
+        //----------------------------------------------------------------------
+        self.segments.iter().find_map(|segment| {
+            segment
+                .labels()
+                .iter()
+                .find(|l| l.name == name)
+                .map(|l| (segment, l))
+        })
+    }
+
     /// Define a new Atom, storing it in the Object's symbol pool.
     ///
     /// Takes a reference to an AST `Node`, since the node will remain in the
@@ -34,10 +190,270 @@ impl<'token> Object<'token> {
     /// Returns `None` if successful, otherwise if attempting to define an
     /// Atom that already exists, returns a `ParseError`.
     ///
-    pub fn new_atom(&mut self, node: &'token Node<'token>) -> MaybeError {
-        self.atoms
-            .insert(node.to_string(), Atom::from(node))
-            //TODO: include error details from the `Ok(V)`
-            .and_then(|_v| Some(ParseError::duplicate()))
+    pub fn new_atom(&mut self, node: &Node) -> MaybeError {
+        //----------------------------------------------------------------------
+        let atom = Atom::from(node);
+        let name = atom.to_string();
+
+        if self.atoms.contains_key(&name) {
+            //TODO: include the location of the original definition
+            return Some(ParseError::duplicate().at(Some(atom.location())));
+        }
+        self.atoms.insert(name, atom);
+        None
+    }
+
+    /// Define a new Macro, storing it in the Object's symbol pool. If the
+    /// Macro already exists, the definition adds another form to it.
+    ///
+    /// Returns `None` if successful, otherwise if attempting to define a
+    /// form of a Macro that already exists, returns a `ParseError`.
+    ///
+    pub fn new_macro(&mut self, node: &Node) -> MaybeError {
+        //----------------------------------------------------------------------
+        let r#macro = match node.kind {
+            NodeKind::DefMacro(ref m) => m.as_ref(),
+            _ => panic!("Not a Macro definition `Node`."),
+        };
+
+        if let Some(existing) = self.macros.get_mut(r#macro.name()) {
+            return existing.add_forms(r#macro).err();
+        }
+        self.macros
+            .insert(r#macro.name().to_string(), r#macro.clone());
+        None
+    }
+
+    /// Import a module into the Object, by name. The module's Object
+    /// is assembled by the `Assembler`.
+    ///
+    pub fn new_import(&mut self, name: &str) {
+        //----------------------------------------------------------------------
+        if !self.imports.iter().any(|i| i == name) {
+            self.imports.push(name.to_string());
+        }
+    }
+
+    /// Export the symbols listed by an export Node. The symbols must be
+    /// defined by the Object; see `check_exports`.
+    ///
+    /// Returns `None` if successful, otherwise if a symbol is
+    /// exported twice, returns a `ParseError`.
+    ///
+    pub fn new_export(&mut self, node: &Node) -> MaybeError {
+        //----------------------------------------------------------------------
+        let list = match node.kind {
+            NodeKind::Export(ref l) => l,
+            _ => panic!("Not an export `Node`."),
+        };
+
+        for symbol in list.iter() {
+            // an Atom is exported by its name as defined, e.g. `AF'`
+            let name = match symbol.kind {
+                NodeKind::Atom(ref a, ref h) => format!("{}{}", a, h),
+                NodeKind::Macro(ref m, _) => m.clone(),
+                // Labels are exported with their sigil, e.g. `:main`,
+                // as they do not share a name-space with Atoms & Macros
+                NodeKind::Label(ref l) => format!(":{}", l),
+                _ => panic!("Not an Atom, Macro or Label `Node`."),
+            };
+            let location = match symbol.location() {
+                Some(l) => l,
+                None => panic!("Exported symbol has no `Token`."),
+            };
+            if self.exports.insert(name, location).is_some() {
+                return Some(ParseError::duplicate().at(Some(location)));
+            }
+        }
+        None
+    }
+
+    /// Check that the Object defines every symbol it exports, or imports it
+    /// from a module that does; the modules are those it can import. As
+    /// Labels are defined as the data is packed, this is checked afterwards.
+    ///
+    pub fn check_exports(&self, modules: &[Object]) -> MaybeError {
+        //----------------------------------------------------------------------
+        self.exports
+            .iter()
+            .filter(|(name, _)| match name.strip_prefix(':') {
+                Some(label) => self.find_label(label).is_none(),
+                None => {
+                    !self.atoms.contains_key(*name)
+                        && !self.macros.contains_key(*name)
+                        && !self.imported(modules).any(|module| {
+                            module.find_export(modules, name).is_some()
+                                || module.exports_atom(modules, name)
+                        })
+                }
+            })
+            // report the first in the source code
+            .min_by_key(|(_, location)| (location.line, location.col))
+            .map(|(name, location)| {
+                parse_error(ParseErrorKind::UndefinedExport(name.clone()))
+                    .at(Some(*location))
+            })
+    }
+
+    /// Find a Macro by name, as seen from within the Object: those it
+    /// defines, then those exported by the modules it imports. The name may
+    /// be qualified by module, e.g. `z80.ld`. Returns a `ParseError` if more
+    /// than one module imported exports the name, unqualified.
+    ///
+    /// A Macro that has the name of one the Object imports extends it; the
+    /// forms of the first are tried before those of the second. The Macros
+    /// are returned in that order, empty if none is found, each with the
+    /// Object that defines it, as the body of a Macro is expanded as seen
+    /// from within that Object.
+    ///
+    pub fn find_macro<'a>(
+        &'a self,
+        modules: &'a [Object],
+        name: &str,
+    ) -> ParseResult<Vec<(&'a Object, &'a Macro)>> {
+        //----------------------------------------------------------------------
+        let first = match self.find_first(modules, name)? {
+            Some(found) => found,
+            None => return Ok(Vec::new()),
+        };
+        let mut chain = vec![first];
+        while let Some(next) = {
+            let (object, r#macro) = chain[chain.len() - 1];
+            object.extends(modules, r#macro.name())
+        } {
+            chain.push(next);
+        }
+        Ok(chain)
+    }
+
+    /// Find the first Macro of a name, as with `find_macro`.
+    ///
+    fn find_first<'a>(
+        &'a self,
+        modules: &'a [Object],
+        name: &str,
+    ) -> ParseResult<Option<(&'a Object, &'a Macro)>> {
+        //----------------------------------------------------------------------
+        // a width suffix is not qualification, e.g. `lda.w`,
+        // so the name is only qualified if the module is imported
+        if let Some((module, name)) = name.split_once('.') {
+            if let Some(module) =
+                self.imported(modules).find(|o| o.name == module)
+            {
+                return Ok(module.find_export(modules, name));
+            }
+        }
+        if let Some(m) = self.macros.get(name) {
+            return Ok(Some((self, m)));
+        }
+        // two modules may export the same Macro,
+        // where one exports what it imports from the other
+        let mut found: Vec<(&Object, &Macro)> = Vec::new();
+        let mut exporters = Vec::new();
+        for module in self.imported(modules) {
+            if let Some((object, r#macro)) = module.find_export(modules, name) {
+                if !found.iter().any(|(_, m)| std::ptr::eq(*m, r#macro)) {
+                    found.push((object, r#macro));
+                    exporters.push(module.name.clone());
+                }
+            }
+        }
+        if found.len() > 1 {
+            return Err(parse_error(ParseErrorKind::AmbiguousMacro {
+                name: name.to_string(),
+                modules: exporters,
+            }));
+        }
+        Ok(found.into_iter().next())
+    }
+
+    /// The Macro of the given name that a Macro the Object defines extends;
+    /// the first exported by a module the Object imports.
+    ///
+    fn extends<'a>(
+        &'a self,
+        modules: &'a [Object],
+        name: &str,
+    ) -> Option<(&'a Object, &'a Macro)> {
+        //----------------------------------------------------------------------
+        self.imported(modules)
+            .find_map(|module| module.find_export(modules, name))
+    }
+
+    /// The Macro of the given name that the Object exports, and the Object
+    /// that defines it; either the Object itself, or a module it imports.
+    ///
+    pub fn find_export<'a>(
+        &'a self,
+        modules: &'a [Object],
+        name: &str,
+    ) -> Option<(&'a Object, &'a Macro)> {
+        //----------------------------------------------------------------------
+        if !self.is_exported(name) {
+            return None;
+        }
+        match self.macros.get(name) {
+            Some(m) => Some((self, m)),
+            None => self.extends(modules, name),
+        }
+    }
+
+    /// The Atom of the given name that the Object exports, and the Object
+    /// that defines it; either the Object itself, or a module it imports.
+    ///
+    pub fn find_atom<'a>(
+        &'a self,
+        modules: &'a [Object],
+        name: &str,
+    ) -> Option<(&'a Object, &'a Atom)> {
+        //----------------------------------------------------------------------
+        if !self.is_exported(name) {
+            return None;
+        }
+        match self.atoms.get(name) {
+            Some(atom) => Some((self, atom)),
+            None => self
+                .imported(modules)
+                .find_map(|module| module.find_atom(modules, name)),
+        }
+    }
+
+    /// Does the Object export an Atom of the given name?
+    ///
+    fn exports_atom(&self, modules: &[Object], name: &str) -> bool {
+        //----------------------------------------------------------------------
+        self.find_atom(modules, name).is_some()
+    }
+
+    /// Is the Atom of the given name defined, as seen from within the
+    /// Object? Either by the Object, or by a module it imports.
+    ///
+    pub fn has_atom(&self, modules: &[Object], name: &str) -> bool {
+        //----------------------------------------------------------------------
+        self.atoms.contains_key(name)
+            || self
+                .imported(modules)
+                .any(|module| module.exports_atom(modules, name))
+    }
+
+    /// The names of the symbols the Object exports, in the order exported.
+    ///
+    pub fn exports(&self) -> Vec<&str> {
+        //----------------------------------------------------------------------
+        let mut exports: Vec<_> = self.exports.iter().collect();
+        exports.sort_by_key(|(_, location)| (location.line, location.col));
+        exports.into_iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    /// The Objects of the modules imported, in the order imported.
+    ///
+    fn imported<'a>(
+        &'a self,
+        modules: &'a [Object],
+    ) -> impl Iterator<Item = &'a Object> {
+        //----------------------------------------------------------------------
+        self.imports
+            .iter()
+            .filter_map(move |name| modules.iter().find(|o| &o.name == name))
     }
 }
@@ -3,7 +3,7 @@
 
 //! **Operators**.
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Operator {
     /// Addition operator "+"
     Add,
@@ -29,6 +29,30 @@ pub enum Operator {
     Shr,
 }
 
+impl Operator {
+    //==========================================================================
+    /// Apply the operator to a left-hand and right-hand value. Returns `None`
+    /// if the result cannot be calculated, e.g. division by zero, overflow.
+    ///
+    pub fn apply(self, left: i64, right: i64) -> Option<i64> {
+        //----------------------------------------------------------------------
+        match self {
+            Operator::Add => left.checked_add(right),
+            Operator::Sub => left.checked_sub(right),
+            Operator::Mul => left.checked_mul(right),
+            Operator::Div => left.checked_div(right),
+            Operator::Mod => left.checked_rem(right),
+            Operator::Pow => left.checked_pow(right.try_into().ok()?),
+            Operator::Xor => Some(left ^ right),
+            Operator::And => Some(left & right),
+            Operator::Bor => Some(left | right),
+            Operator::Shl => left.checked_shl(right.try_into().ok()?),
+            Operator::Shr => left.checked_shr(right.try_into().ok()?),
+        }
+    }
+}
+
+use std::convert::TryInto;
 use std::fmt::{self, *};
 
 impl Display for Operator {
@@ -60,11 +84,11 @@ impl Display for Operator {
 
 use crate::token::{Token, TokenKind};
 
-impl From<&Token<'_>> for Operator {
+impl From<&Token> for Operator {
     //==========================================================================
     /// Convert a token into an `Operator` enum.
     /// Panics if using a token that is not an operator!
-    fn from(token: &Token<'_>) -> Self {
+    fn from(token: &Token) -> Self {
         //----------------------------------------------------------------------
         match token.kind() {
             TokenKind::Operator(o) => o,
@@ -37,9 +37,9 @@ use crate::node::MaybeNode;
 
 /// During building of the `AST`, the methods return either a new `Node` to
 /// attach to the `AST`, or a `ParseError`.
-pub type ASTResult<'token> = ParseResult<MaybeNode<'token>>;
+pub type ASTResult = ParseResult<MaybeNode>;
 
-impl From<ParseError> for ASTResult<'_> {
+impl From<ParseError> for ASTResult {
     //==========================================================================
     /// For brevity, allow conversion of a `ParseError` to an `ASTResult`,
     /// i.e. `Result<Err(ParseError)>`.
@@ -50,17 +50,17 @@ impl From<ParseError> for ASTResult<'_> {
     }
 }
 
-impl<'token> From<Node<'token>> for ASTResult<'token> {
+impl From<Node> for ASTResult {
     //==========================================================================
-    fn from(node: Node<'token>) -> Self {
+    fn from(node: Node) -> Self {
         //----------------------------------------------------------------------
         Ok(Some(node))
     }
 }
 
-impl<'token> From<Token<'token>> for ASTResult<'token> {
+impl From<Token> for ASTResult {
     //==========================================================================
-    fn from(token: Token<'token>) -> Self {
+    fn from(token: Token) -> Self {
         //----------------------------------------------------------------------
         Self::from(Node::from(token))
     }
@@ -69,34 +69,88 @@ impl<'token> From<Token<'token>> for ASTResult<'token> {
 use crate::tokenizer::Tokenizer;
 use std::iter::Peekable;
 
-pub struct Parser<'token> {
-    tokens: Peekable<Tokenizer<'token>>,
+pub struct Parser {
+    tokens: Peekable<Tokenizer>,
+    /// Are we within the body of a macro definition?
+    /// Macro directives are only valid there.
+    in_macro: bool,
+    /// Are we within the pattern of a macro definition?
+    /// Parameters may be given a type there, e.g. `byte \zp`.
+    in_pattern: bool,
+    /// Documentation comments awaiting the definition that follows them.
+    doc: Option<String>,
 }
 
+use crate::atom::Hint;
 use crate::error::*;
 use crate::list::List;
 use crate::node::Node;
-use crate::token::Token;
+use crate::r#macro::{If, Macro};
+use crate::source::SourceFile;
+use crate::token::{Token, TokenKind};
+use std::rc::Rc;
 
-impl<'token> Parser<'token> {
+impl Parser {
     //==========================================================================
-    // note that we cannot implement `FromStr` due to the lifetime requirement?
-    #[allow(clippy::should_implement_trait)]
-    /// NB: the string reference must live as long as the `RymParser`;
-    /// that is, the source string you pass it will not deallocate until
-    /// the RymParser does as well.
+    /// Create a Parser for a file's source code. Returns a `ParseError`
+    /// if the source code does not follow the grammar.
     ///
-    pub fn from_str(source: &'token str) -> Self {
+    pub fn new(file: &Rc<SourceFile>) -> ParseResult<Self> {
         //----------------------------------------------------------------------
-        Self {
-            tokens: Tokenizer::from_str(source).unwrap().peekable(),
-        }
+        Ok(Self {
+            tokens: Tokenizer::new(file)?.peekable(),
+            in_macro: false,
+            in_pattern: false,
+            doc: None,
+        })
     }
 
     /// A statement is one computable action.
     ///
-    fn parse_statement(&mut self, token: Token<'token>) -> ASTResult<'token> {
+    fn parse_statement(&mut self, token: Token) -> ASTResult {
         //----------------------------------------------------------------------
+        // documentation is held onto until the next statement,
+        // which takes it if it is a definition
+        if token.is_doc() {
+            self.push_doc(&token);
+            return match self.tokens.next() {
+                Some(t) => self.parse_statement(t),
+                None => Ok(None),
+            };
+        }
+        let is_label_def = token.is_label() && token.location().col == 1;
+        if !token.is_keyword_atom()
+            && !token.is_keyword_macro()
+            && !is_label_def
+        {
+            self.doc = None;
+        }
+        // a block can only appear where expected,
+        // such as the body of a macro definition
+        if token.is_block_open()
+            || token.is_block_close()
+            || token.is_paren_close()
+            || token.is_bracket_open()
+            || token.is_bracket_close()
+        {
+            return ASTResult::from(
+                ParseError::unexpected().at(Some(token.location())),
+            );
+        }
+        if token.is_paren_open() {
+            let list = self.parse_brackets(&token)?;
+            // the node refers back to the opening parenthesis
+            return ASTResult::from(Node {
+                token: Some(token),
+                ..Node::new_list(list)
+            });
+        }
+        if token.is_directive() {
+            return self.parse_directive(token);
+        }
+        if token.is_import() {
+            return self.parse_import(token);
+        }
         if token.is_keyword() {
             return self.parse_keyword(token);
         }
@@ -106,15 +160,24 @@ impl<'token> Parser<'token> {
         if token.is_macro() {
             return self.parse_macro(token);
         }
+        // a label without indent is a definition
+        if token.is_label() && token.location().col == 1 {
+            return self.parse_label(token);
+        }
         if token.is_expr() {
             return self.parse_expr(token);
         }
+        // strings are self-contained lists
+        // and cannot form part of an expression
+        if token.is_string() {
+            return ASTResult::from(token);
+        }
         Ok(None)
     }
 
     /// Parse a keyword; for defining new Atoms and Macros.
     ///
-    fn parse_keyword(&mut self, token: Token<'token>) -> ASTResult<'token> {
+    fn parse_keyword(&mut self, token: Token) -> ASTResult {
         //----------------------------------------------------------------------
         if !token.is_keyword() {
             return Ok(None);
@@ -125,22 +188,197 @@ impl<'token> Parser<'token> {
             return self.parse_keyword_atom(token);
         }
         if token.is_keyword_macro() {
-            unimplemented!();
+            return self.parse_keyword_macro(token);
+        }
+        if token.is_keyword_export() {
+            return self.parse_keyword_export(token);
+        }
+        if token.is_keyword_segment() {
+            return self.parse_keyword_segment(token);
+        }
+        if token.is_keyword_align() {
+            return self.parse_keyword_align(token);
+        }
+        if token.is_keyword_rel() {
+            return self.parse_keyword_rel(token);
         }
 
         Ok(None)
     }
 
+    /// Parse a Label definition, e.g. `:loop`, taking any documentation
+    /// comment that precedes it. Labels can only be defined at the root of
+    /// a source file, not within the body of a macro.
+    ///
+    fn parse_label(&mut self, token: Token) -> ASTResult {
+        //----------------------------------------------------------------------
+        if self.in_macro {
+            return ASTResult::from(
+                ParseError::unexpected().at(Some(token.location())),
+            );
+        }
+        let doc = self.doc.take();
+        ASTResult::from(Node::new_def_label(token, doc))
+    }
+
+    /// Parse the beginning of a segment, e.g. `segment code`.
+    /// Data that follows is placed into the segment of that name.
+    ///
+    fn parse_keyword_segment(&mut self, token: Token) -> ASTResult {
+        //----------------------------------------------------------------------
+        match self.tokens.next() {
+            // the name of the segment is written as a macro name would be
+            Some(t) if t.is_macro() && !t.is_macro_qualified() => {
+                ASTResult::from(Node::new_segment(token, t.to_string()))
+            }
+            Some(t) => {
+                ASTResult::from(ParseError::unexpected().at(Some(t.location())))
+            }
+            None => ASTResult::from(ParseError::end_of_file()),
+        }
+    }
+
+    /// Parse the alignment of a segment, e.g. `align $100`. The boundary
+    /// must be a number, and a power of two.
+    ///
+    fn parse_keyword_align(&mut self, token: Token) -> ASTResult {
+        //----------------------------------------------------------------------
+        // `align -4` reads as the keyword less four, so the sign
+        // is taken here, for the boundary to be rejected below
+        let location = self.tokens.peek().map(Token::location);
+        let sign = match self.tokens.peek() {
+            Some(t) if t.as_str() == "-" => {
+                self.tokens.next();
+                -1
+            }
+            _ => 1,
+        };
+        let t = match self.tokens.next() {
+            Some(t) if t.is_number() => t,
+            Some(t) => {
+                return ASTResult::from(
+                    ParseError::unexpected().at(Some(t.location())),
+                )
+            }
+            None => return ASTResult::from(ParseError::end_of_file()),
+        };
+        let boundary = sign * Node::from(t.clone()).eval().unwrap_or(0);
+        if boundary <= 0 || boundary & (boundary - 1) != 0 {
+            return ASTResult::from(
+                parse_error(ParseErrorKind::Alignment(boundary)).at(location),
+            );
+        }
+        ASTResult::from(Node::new_align(token, boundary as usize))
+    }
+
+    /// Parse a value relative to the address that follows it,
+    /// e.g. `rel :loop`.
+    ///
+    fn parse_keyword_rel(&mut self, token: Token) -> ASTResult {
+        //----------------------------------------------------------------------
+        let value = match self.tokens.next() {
+            Some(t) if t.is_expr() => match self.parse_expr(t)? {
+                Some(node) => node,
+                None => return ASTResult::from(ParseError::unexpected()),
+            },
+            Some(t) => {
+                return ASTResult::from(
+                    ParseError::unexpected().at(Some(t.location())),
+                )
+            }
+            None => return ASTResult::from(ParseError::end_of_file()),
+        };
+        ASTResult::from(Node::new_relative(token, value))
+    }
+
+    /// Parse a module import, e.g. `::z80`.
+    ///
+    fn parse_import(&mut self, token: Token) -> ASTResult {
+        //----------------------------------------------------------------------
+        if !token.is_import() {
+            return Ok(None);
+        }
+        // modules can only be imported at the root of a source file
+        if self.in_macro {
+            return ASTResult::from(
+                ParseError::unexpected().at(Some(token.location())),
+            );
+        }
+        ASTResult::from(Node::new_import(token))
+    }
+
+    /// Parse the list of symbols a module exports, e.g.
+    ///
+    /// ```
+    /// export  { A, B, ld, jp }
+    /// ```
+    ///
+    /// Atoms, Macros and Labels can be exported; Atoms may be shadows,
+    /// e.g. `AF'`. A Label exported can be used by every file linked.
+    ///
+    fn parse_keyword_export(&mut self, token: Token) -> ASTResult {
+        //----------------------------------------------------------------------
+        if !token.is_keyword_export() {
+            return Ok(None);
+        }
+        // exports can only be given at the root of a source file
+        if self.in_macro {
+            return ASTResult::from(
+                ParseError::unexpected().at(Some(token.location())),
+            );
+        }
+        match self.tokens.next() {
+            Some(t) if t.is_block_open() => {}
+            Some(t) => {
+                return ASTResult::from(
+                    ParseError::unexpected().at(Some(t.location())),
+                )
+            }
+            None => return ASTResult::from(ParseError::end_of_file()),
+        }
+
+        let mut list = List::default();
+        loop {
+            let t = match self.tokens.next() {
+                Some(t) => t,
+                None => return ASTResult::from(ParseError::end_of_file()),
+            };
+            if t.is_block_close() {
+                break;
+            }
+            // the names are as they are defined, without hints
+            // or qualification by module
+            let is_name = if t.is_atom() {
+                match Hint::split(t.as_str()).1 {
+                    Hint::None => true,
+                    Hint::Shadow(ref h) => h.is_empty(),
+                    _ => false,
+                }
+            } else {
+                t.is_label() || (t.is_macro() && !t.is_macro_qualified())
+            };
+            if !is_name {
+                return ASTResult::from(
+                    ParseError::unexpected().at(Some(t.location())),
+                );
+            }
+            list.push(if t.is_atom() || t.is_label() {
+                Node::from(t)
+            } else {
+                Node::new_macro(t, List::default())
+            });
+        }
+
+        ASTResult::from(Node::new_export(token, list))
+    }
+
     /// A record type is an ad-hoc structure, setting the layout of
     /// data-packing for whatever data follows. When building an AST, nested
     /// structure names (e.g. "%vector", cannot be resovled yet (they might
     /// be defined in other modules) so we build a `List`. The assembly
     /// process transforms this list into a true struct, ensuring that any
     /// nested structs are resolved.
-    fn parse_record_type(
-        &mut self,
-        mut token: Token<'token>,
-    ) -> ASTResult<'token> {
+    fn parse_record_type(&mut self, mut token: Token) -> ASTResult {
         //----------------------------------------------------------------------
         // if the current token is not a type,
         // this is not our concern.
@@ -153,6 +391,16 @@ impl<'token> Parser<'token> {
 
         // add contiguous types to the struct:
         loop {
+            // user-defined types cannot be defined yet,
+            // so there are none to name
+            if token.is_type_struct() {
+                return ASTResult::from(
+                    parse_error(ParseErrorKind::StructType(
+                        token.as_str().to_string(),
+                    ))
+                    .at(Some(token.location())),
+                );
+            }
             // add the type to the record structure
             list.push(Node::from(token));
             // peek at the next token
@@ -172,17 +420,16 @@ impl<'token> Parser<'token> {
         )
     }
 
-    /// Parse an Atom definition.
+    /// Parse an Atom definition, taking any documentation comment
+    /// that precedes it.
     ///
     #[allow(clippy::needless_pass_by_value)]
-    fn parse_keyword_atom(
-        &mut self,
-        token: Token<'token>,
-    ) -> ASTResult<'token> {
+    fn parse_keyword_atom(&mut self, token: Token) -> ASTResult {
         //----------------------------------------------------------------------
         if !token.is_keyword_atom() {
             return Ok(None);
         }
+        let doc = self.doc.take();
 
         // "atom" keyword is present, skip over it
         let token = self.tokens.next().unwrap();
@@ -192,14 +439,271 @@ impl<'token> Parser<'token> {
         if !token.is_atom() {
             return ASTResult::from(ParseError::unexpected());
         }
+        // a shadow may be defined, e.g. `atom AF'`, but hints are only
+        // given where an Atom is used
+        match Hint::split(token.as_str()).1 {
+            Hint::None => {}
+            Hint::Shadow(ref h) if h.is_empty() => {}
+            _ => {
+                return ASTResult::from(
+                    ParseError::unexpected().at(Some(token.location())),
+                )
+            }
+        }
 
         // build an atom definition node
-        ASTResult::from(Node::new_atom(token))
+        ASTResult::from(Node::new_atom(token, doc))
+    }
+
+    /// Parse a Macro definition, e.g.
+    ///
+    /// ```
+    /// macro   name    \1, \2 { ... }
+    /// ```
+    ///
+    /// In place of parameters, a definition may give a pattern of Atoms,
+    /// numbers and parenthesised lists to match, e.g. `ld A, (\nn)`.
+    /// A parameter may be given a type, e.g. `byte \zp`.
+    ///
+    #[allow(clippy::needless_pass_by_value)]
+    fn parse_keyword_macro(&mut self, token: Token) -> ASTResult {
+        //----------------------------------------------------------------------
+        if !token.is_keyword_macro() {
+            return Ok(None);
+        }
+        let doc = self.doc.take();
+        // macro definitions cannot be nested
+        if self.in_macro {
+            return ASTResult::from(
+                ParseError::unexpected().at(Some(token.location())),
+            );
+        }
+
+        // "macro" keyword is present, skip over it
+        let name = match self.tokens.next() {
+            // the next token *must* be the macro name,
+            // and a definition cannot be qualified by module
+            Some(t) if t.is_macro() && !t.is_macro_qualified() => t,
+            Some(t) => {
+                return ASTResult::from(
+                    ParseError::unexpected().at(Some(t.location())),
+                )
+            }
+            None => return ASTResult::from(ParseError::end_of_file()),
+        };
+
+        // the parameters / pattern follow, up until the beginning of the
+        // body; what is allowed in a pattern is checked by the Macro
+        self.in_pattern = true;
+        let pattern = self.parse_args();
+        self.in_pattern = false;
+        let pattern = pattern?;
+        match self.tokens.next() {
+            Some(t) if t.is_block_open() => {}
+            Some(t) => {
+                return ASTResult::from(
+                    ParseError::unexpected().at(Some(t.location())),
+                )
+            }
+            None => return ASTResult::from(ParseError::end_of_file()),
+        }
+
+        self.in_macro = true;
+        let body = self.parse_block();
+        self.in_macro = false;
+        let body = body?;
+
+        // build a macro definition node
+        ASTResult::from(Node::new_def_macro(Macro::new(
+            name, pattern, body, doc,
+        )?))
+    }
+
+    /// Parse a macro directive. Directives are only valid within the body
+    /// of a macro definition.
+    ///
+    fn parse_directive(&mut self, token: Token) -> ASTResult {
+        //----------------------------------------------------------------------
+        if !token.is_directive() {
+            return Ok(None);
+        }
+        // an `\else` must follow the block of an `\if`
+        if !self.in_macro || token.is_directive_else() {
+            return ASTResult::from(
+                ParseError::unexpected().at(Some(token.location())),
+            );
+        }
+        if token.is_directive_if() {
+            return self.parse_directive_if(token);
+        }
+        if token.is_directive_message() {
+            return self.parse_directive_message(token);
+        }
+        if token.is_directive_relax() {
+            let list = self.parse_block_open()?;
+            return ASTResult::from(Node::new_relax(token, list));
+        }
+        // directives that test the arguments can be used as values
+        if token.is_expr() {
+            return self.parse_expr(token);
+        }
+
+        ASTResult::from(token)
+    }
+
+    /// Parse a conditional block, e.g.
+    ///
+    /// ```
+    /// \if \atom { ... } \else { ... }
+    /// ```
+    ///
+    /// An `\else` may be followed by another `\if` instead of a block.
+    ///
+    fn parse_directive_if(&mut self, token: Token) -> ASTResult {
+        //----------------------------------------------------------------------
+        // the test follows the `\if`
+        let test = match self.tokens.next() {
+            Some(t) if t.is_expr() => match self.parse_expr(t)? {
+                Some(node) => node,
+                None => return ASTResult::from(ParseError::unexpected()),
+            },
+            Some(t) => {
+                return ASTResult::from(
+                    ParseError::unexpected().at(Some(t.location())),
+                )
+            }
+            None => return ASTResult::from(ParseError::end_of_file()),
+        };
+
+        let then = self.parse_block_open()?;
+
+        // is there an alternative?
+        let other = match self.tokens.peek() {
+            Some(t) if t.is_directive_else() => {
+                self.tokens.next();
+                match self.tokens.peek() {
+                    // `\else \if ...`
+                    Some(t) if t.is_directive_if() => {
+                        let t = self.tokens.next().unwrap();
+                        let mut list = List::default();
+                        if let Some(node) = self.parse_directive_if(t)? {
+                            list.push(node);
+                        }
+                        list
+                    }
+                    _ => self.parse_block_open()?,
+                }
+            }
+            None | Some(_) => List::default(),
+        };
+
+        ASTResult::from(Node::new_if(token, If { test, then, other }))
+    }
+
+    /// Parse a warning, error, requirement or option change, e.g.
+    ///
+    /// ```
+    /// \warn "message"
+    /// \error "message"
+    /// \requires "option"
+    /// \enable "option"
+    /// \disable "option"
+    /// ```
+    ///
+    fn parse_directive_message(&mut self, token: Token) -> ASTResult {
+        //----------------------------------------------------------------------
+        // the message follows the directive
+        let message = match self.tokens.next() {
+            Some(t) if t.is_string() => t.to_string(),
+            Some(t) => {
+                return ASTResult::from(
+                    ParseError::unexpected().at(Some(t.location())),
+                )
+            }
+            None => return ASTResult::from(ParseError::end_of_file()),
+        };
+
+        // drop the speech-marks
+        let message = message[1..message.len() - 1].to_string();
+        if token.is_directive_warn() {
+            ASTResult::from(Node::new_warn(token, message))
+        } else if token.is_directive_error() {
+            ASTResult::from(Node::new_error(token, message))
+        } else if token.is_directive_requires() {
+            ASTResult::from(Node::new_requires(token, message))
+        } else if token.is_directive_enable() {
+            ASTResult::from(Node::new_enable(token, message))
+        } else {
+            ASTResult::from(Node::new_disable(token, message))
+        }
+    }
+
+    /// Hold onto a documentation comment for the definition that follows.
+    /// Consecutive comments are joined, a line apart.
+    ///
+    fn push_doc(&mut self, token: &Token) {
+        //----------------------------------------------------------------------
+        if let TokenKind::Doc(text) = token.kind() {
+            self.doc = Some(match self.doc.take() {
+                Some(doc) => format!("{}\n{}", doc, text),
+                None => text,
+            });
+        }
+    }
+
+    /// Parse a block, beginning with the opening brace.
+    ///
+    fn parse_block_open(&mut self) -> ParseResult<List> {
+        //----------------------------------------------------------------------
+        match self.tokens.next() {
+            Some(t) if t.is_block_open() => self.parse_block(),
+            Some(t) => Err(ParseError::unexpected().at(Some(t.location()))),
+            None => Err(ParseError::end_of_file()),
+        }
+    }
+
+    /// Parse the statements of a block, up to and including the closing
+    /// brace. The opening brace should have already been consumed.
+    ///
+    fn parse_block(&mut self) -> ParseResult<List> {
+        //----------------------------------------------------------------------
+        let mut list = List::default();
+
+        loop {
+            let token = match self.tokens.next() {
+                Some(t) => t,
+                None => return Err(ParseError::end_of_file()),
+            };
+            // the end of the block? documentation
+            // at the end has nothing to document
+            if token.is_block_close() {
+                self.doc = None;
+                return Ok(list);
+            }
+            if token.is_doc() {
+                self.push_doc(&token);
+                continue;
+            }
+            // parse the statement and add it to the block
+            match self.parse_statement(token.clone())? {
+                Some(node) => list.push(node),
+                // the source code ended before the block did
+                None => {
+                    return Err(
+                        ParseError::end_of_file().at(Some(token.location()))
+                    )
+                }
+            }
+        }
     }
 
     /// Parse a macro invocation.
     ///
-    fn parse_macro(&mut self, token: Token<'token>) -> ASTResult<'token> {
+    /// The arguments to the macro are the list elements that follow it, up
+    /// until the next macro (or any other statement that is not a value),
+    /// e.g. `ld HL, $1234`.
+    ///
+    fn parse_macro(&mut self, token: Token) -> ASTResult {
         //----------------------------------------------------------------------
         // if the current token is not a macro,
         // this is not our concern.
@@ -207,8 +711,127 @@ impl<'token> Parser<'token> {
             return Ok(None);
         }
 
+        let args = self.parse_args()?;
+
         // build a `Node` for a macro invocation
-        ASTResult::from(Node::from(token))
+        ASTResult::from(Node::new_macro(token, args))
+    }
+
+    /// Parse the arguments to a macro; the values that follow, up until
+    /// anything that is not a value.
+    ///
+    fn parse_args(&mut self) -> ParseResult<List> {
+        //----------------------------------------------------------------------
+        let mut args = List::default();
+
+        loop {
+            match self.tokens.peek() {
+                // gather the following values as arguments
+                Some(t) if Self::is_arg(t, self.in_pattern) => {
+                    let t = self.tokens.next().unwrap();
+                    match self.parse_arg(t)? {
+                        Some(node) => args.push(node),
+                        None => break,
+                    }
+                }
+                None | Some(_) => break,
+            }
+        }
+
+        Ok(args)
+    }
+
+    /// Can the token begin an argument to a macro invocation?
+    /// Within the pattern of a macro definition, this includes a type.
+    /// A Label without indent begins the next line, defining the Label.
+    ///
+    fn is_arg(token: &Token, in_pattern: bool) -> bool {
+        //----------------------------------------------------------------------
+        (token.is_expr() && !(token.is_label() && token.location().col == 1))
+            || token.is_string()
+            || token.is_paren_open()
+            || token.is_bracket_open()
+            || (in_pattern && token.is_type_primitive())
+    }
+
+    /// Parse a single argument to a macro invocation.
+    ///
+    fn parse_arg(&mut self, token: Token) -> ASTResult {
+        //----------------------------------------------------------------------
+        if self.in_pattern && token.is_type_primitive() {
+            return self.parse_typed_param(token);
+        }
+        // strings are self-contained lists
+        // and cannot form part of an expression
+        if token.is_string() {
+            return ASTResult::from(token);
+        }
+        // indirection, e.g. `(HL)` or `[HL]`
+        if token.is_paren_open() || token.is_bracket_open() {
+            let list = self.parse_brackets(&token)?;
+            return ASTResult::from(Node::new_indirect(token, list));
+        }
+        self.parse_expr(token)
+    }
+
+    /// Parse a typed parameter within the pattern of a macro definition,
+    /// e.g. `byte \zp`. The type should have already been consumed.
+    ///
+    #[allow(clippy::needless_pass_by_value)]
+    fn parse_typed_param(&mut self, token: Token) -> ASTResult {
+        //----------------------------------------------------------------------
+        let primitive = match token.kind() {
+            TokenKind::Primitive(p) => p,
+            _ => return Ok(None),
+        };
+        // the type must be followed by a parameter
+        match self.tokens.next() {
+            Some(t) if t.is_param() => {
+                ASTResult::from(Node::new_typed_param(primitive, t))
+            }
+            Some(t) => {
+                ASTResult::from(ParseError::unexpected().at(Some(t.location())))
+            }
+            None => ASTResult::from(ParseError::end_of_file()),
+        }
+    }
+
+    /// Parse a parenthesised list, e.g. `(1, 2, 3)`, or the indirection
+    /// `(HL)` / `[HL]` given to a macro, up to the bracket that closes the
+    /// opening one given. The opening bracket should have already been
+    /// consumed.
+    ///
+    fn parse_brackets(&mut self, token: &Token) -> ParseResult<List> {
+        //----------------------------------------------------------------------
+        let mut list = List::default();
+
+        loop {
+            match self.tokens.next() {
+                Some(ref t)
+                    if (token.is_paren_open() && t.is_paren_close())
+                        || (token.is_bracket_open()
+                            && t.is_bracket_close()) =>
+                {
+                    break
+                }
+                Some(t) if Self::is_arg(&t, self.in_pattern) => {
+                    if let Some(node) = self.parse_arg(t)? {
+                        list.push(node);
+                    }
+                }
+                Some(t) => {
+                    return Err(ParseError::unexpected().at(Some(t.location())))
+                }
+                // the source code ended before the list did
+                None => {
+                    return Err(
+                        ParseError::end_of_file().at(Some(token.location()))
+                    )
+                }
+            }
+        }
+
+        Ok(list)
     }
 
     /// Parse an expression, returning an AST node
@@ -219,7 +842,7 @@ impl<'token> Parser<'token> {
     /// returns an `ASTResult` of either a `Node` of the expression, or the
     /// error encountered.
     ///
-    fn parse_expr(&mut self, token: Token<'token>) -> ASTResult<'token> {
+    fn parse_expr(&mut self, token: Token) -> ASTResult {
         //----------------------------------------------------------------------
         // if the current token is not a valid opening for an expression
         // (including if we've reached end-of-file), then return an
@@ -246,7 +869,7 @@ impl<'token> Parser<'token> {
         }
     }
 
-    fn parse_expr_inner(&mut self, left: Node<'token>) -> ASTResult<'token> {
+    fn parse_expr_inner(&mut self, left: Node) -> ASTResult {
         //----------------------------------------------------------------------
         // save the operator, move to the next token
         let oper = self.tokens.next().unwrap();
@@ -286,14 +909,14 @@ impl<'token> Parser<'token> {
     }
 }
 
-impl<'token> Iterator for Parser<'token> {
+impl Iterator for Parser {
     //==========================================================================
-    type Item = ASTResult<'token>;
+    type Item = ASTResult;
 
     /// When you turn the crank on the parser,
     /// it spits out AST nodes.
     ///
-    fn next(&mut self) -> Option<ASTResult<'token>> {
+    fn next(&mut self) -> Option<ASTResult> {
         //----------------------------------------------------------------------
         // pull a token from the source code
         match self.tokens.next() {
@@ -303,12 +926,9 @@ impl<'token> Iterator for Parser<'token> {
             Some(token) => match self.parse_statement(token) {
                 // pass errors through
                 Err(e) => Some(Err(e)),
-                Ok(option) => match option {
-                    Some(node) => Some(ASTResult::from(node)),
-                    // if the token was consumed but produced
-                    // no node, then return no more nodes
-                    None => None,
-                },
+                // if the token was consumed but produced
+                // no node, then return no more nodes
+                Ok(option) => option.map(ASTResult::from),
             },
         }
     }
@@ -10,6 +10,7 @@
 //! * `nybl` = 4 bits (a nybble)
 //! * `byte` = 8 bits (1 byte)
 //! * `word` = 16 bits (2 bytes)
+//! * `trib` = 24 bits (3 bytes), e.g. the 65816's long addresses
 //! * `long` = 32 bits (4 bytes)
 //!
 //! Note that these are specified for the target-system (6502, Z80 etc.) and
@@ -21,7 +22,7 @@
 
 /// Native, target-system, primitive types;
 /// described in number of bits.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Primitive {
     /// A single bit. Cannot be named `bit` due to conflict with the `bit`
     /// instruction on Z80 cpus.
@@ -33,12 +34,40 @@ pub enum Primitive {
     /// A word. 16 bits on retro systems! Not to be confused with the modern
     /// meaning of the term which is the native CPU stride (32/64-bits)
     WORD = 16,
+    /// A "tribyte", 24 bits. Used for the long addresses of CPUs with
+    /// a 24-bit address space, such as the 65816.
+    TRIB = 24,
     /// A "long"; 32-bits on retro systems. Also known as a "double-word"
     /// or "double" in the past, not to be confused with the double-precision
     /// float.
     LONG = 32,
 }
 
+impl Primitive {
+    //==========================================================================
+    /// The number of bits the type occupies.
+    ///
+    pub fn bits(self) -> usize {
+        //----------------------------------------------------------------------
+        self as usize
+    }
+
+    /// Does a value fit within the type?
+    ///
+    pub fn fits(self, value: i64) -> bool {
+        //----------------------------------------------------------------------
+        fits(value, self.bits())
+    }
+}
+
+/// Does a value fit within the given number of bits? A value may be given
+/// signed or unsigned, e.g. a byte accepts -128 to 255.
+///
+pub fn fits(value: i64, bits: usize) -> bool {
+    //--------------------------------------------------------------------------
+    value >= -(1 << (bits - 1)) && value < (1 << bits)
+}
+
 use std::fmt::{self, *};
 
 impl Display for Primitive {
@@ -51,6 +80,7 @@ impl Display for Primitive {
             Primitive::NYBL => "nybl",
             Primitive::BYTE => "byte",
             Primitive::WORD => "word",
+            Primitive::TRIB => "trib",
             Primitive::LONG => "long",
         })
     }
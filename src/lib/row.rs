@@ -11,13 +11,13 @@ use crate::r#struct::Struct;
 /// AST Nodes and place them into Table Cells until the Row is full
 /// ("satisfied").
 ///
-pub struct RowBuilder<'token> {
+pub struct RowBuilder<'a> {
     /// The Cells for the Row being built are held here until you've finished
     /// with the RowBuilder; we cannot return a Row instance unless we create
     /// it the final method call.
-    cells: Vec<Cell<'token>>,
+    cells: Vec<Cell<'a>>,
     /// An Iterator over a record Struct to map the cells in the row to.
-    fields: std::iter::Peekable<std::slice::Iter<'token, Field<'token>>>,
+    fields: std::iter::Peekable<std::slice::Iter<'a, Field>>,
     /// If the Row is satisfied (full) or not. Once the Row is filled,
     /// this will be flipped on and the RowBuilder will accept no more data.
     is_satisfied: bool,
@@ -31,13 +31,13 @@ pub struct RowBuilder<'token> {
 
 use crate::node::Node;
 
-impl<'token> RowBuilder<'token> {
+impl<'a> RowBuilder<'a> {
     //==========================================================================
     /// Table Rows are tightly bound to the Record-struct that defines the type
     /// of each column. You cannot create a Row without a Record-struct as this
     /// would allow swapping the Record-struct whilst the Row is using it.
     ///  
-    pub fn new(record: &'token Struct<'token>, row: usize) -> Self {
+    pub fn new(record: &'a Struct, row: usize) -> Self {
         //----------------------------------------------------------------------
         Self {
             // take an Iterator over the Fields in the Struct;
@@ -59,6 +59,13 @@ impl<'token> RowBuilder<'token> {
         self.is_satisfied
     }
 
+    /// Has no data been added to the Row yet?
+    ///
+    pub fn is_empty(&self) -> bool {
+        //----------------------------------------------------------------------
+        self.cells.is_empty()
+    }
+
     /// Push data into the `Row`, assigning a new table `Cell` for it.
     ///
     /// Returns a Result:
@@ -67,10 +74,7 @@ impl<'token> RowBuilder<'token> {
     /// - An Error if the Row is full and no more data can be added
     ///   (you can't call `add_data` again once the RowBuilder has yielded)
     ///
-    pub fn add_data(
-        &mut self,
-        node: &'token Node<'token>,
-    ) -> ParseResult<Option<Row<'token>>> {
+    pub fn add_data(&mut self, node: Node) -> ParseResult<Option<Row<'a>>> {
         //----------------------------------------------------------------------
         // get the next record field
         match self.fields.next() {
@@ -98,14 +102,28 @@ impl<'token> RowBuilder<'token> {
 }
 
 use crate::cell::Cell;
+use crate::segment::Segment;
 
 /// One row of a data Table.
-pub struct Row<'token>(Vec<Cell<'token>>);
+pub struct Row<'a>(Vec<Cell<'a>>);
+
+impl Row<'_> {
+    //==========================================================================
+    /// Pack the Cells of the Row into a segment.
+    ///
+    pub fn pack(&self, segment: &mut Segment) -> ParseResult<()> {
+        //----------------------------------------------------------------------
+        for cell in self.0.iter() {
+            cell.pack(segment)?;
+        }
+        Ok(())
+    }
+}
 
 use itertools::Itertools;
 use std::fmt::{self, *};
 
-impl<'token> Display for Row<'token> {
+impl<'a> Display for Row<'a> {
     //==========================================================================
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         //----------------------------------------------------------------------
@@ -114,7 +132,7 @@ impl<'token> Display for Row<'token> {
     }
 }
 
-impl<'token> Debug for Row<'token> {
+impl<'a> Debug for Row<'a> {
     //==========================================================================
     /// Debug printing a Row places each Cell on its own line.
     ///
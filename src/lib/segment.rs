@@ -4,20 +4,389 @@
 //! A "segment" is a chunk of re-locatable code/data; each segment will have an
 //! intended region of memory, for example you may wish to place a game's code
 //! in the lower half of memory and the game data in the upper half.
-//! 
+//!
 //! A single source code file can contain code / data intended for different
 //! segments; e.g. the source file might define both the AI code and the
 //! graphic data for a sprite, with the final location of these assets being
 //! separate from each other.
+//!
+//! ```
+//!         segment code
+//! :main
+//!         jp      :main
+//!
+//!         segment data
+//!         align   $100
+//! :sine
+//!         byte
+//!         0, 3, 6, 9
+//! ```
+//!
+//! A segment holds its packed bytes, the Labels defined within it, and the
+//! "fixups"; the values that can't be packed until the Object is linked,
+//! such as the address of a Label. Addresses are not known here; Labels
+//! and fixups are given as offsets from the beginning of the segment.
+//!
+//! A segment may also hold "joints"; sites that can take more than one
+//! form, e.g. an absolute or a relative jump, each packed as a segment of
+//! its own. Which form is used can only be decided once the Object is
+//! linked, so the Linker chooses the form of each joint and lays out the
+//! segment anew, see `relax`, moving the Labels and fixups that follow.
 
+use crate::error::*;
+use crate::node::Node;
+
+/// The statement the data last packed belongs to: the outer-most Macro
+/// invocation it was expanded from, e.g. an instruction, and the offset
+/// at which the statement's data begins.
+type Statement = Option<(Expansion, usize)>;
+
+#[derive(Debug, Clone)]
 pub struct Segment {
-    /*TODO: segment properties (load address, run address, alignment)
-            are determined by the linker script, so only a reference
-            to that segment data will be needed here */
-    //TODO: imports and exports
-    _spans: Vec<Span>,
+    /// The name of the segment, e.g. "code".
+    name: String,
+    /// The boundary the beginning of the segment must be placed on,
+    /// a power of two; at least that of any alignment within it.
+    align: usize,
+    /// The contents of the segment, in order.
+    spans: Vec<Span>,
+    /// The length of the segment, in bytes.
+    len: usize,
+    /// The Labels defined within the segment, in the order defined.
+    labels: Vec<Label>,
+    /// The values to patch once the Object is linked, in order.
+    fixups: Vec<Fixup>,
+    /// The sites that can take more than one form, in order.
+    joints: Vec<Joint>,
+    /// The statement the data last packed belongs to.
+    statement: Statement,
 }
 
+/// A run of the contents of a segment.
+#[derive(Debug, Clone)]
 pub enum Span {
-    Data,
+    /// Packed bytes, including those left for fixups to patch.
+    Data(Vec<u8>),
+    /// Padding to a boundary; the boundary and the number of bytes.
+    Align(usize, usize),
+    /// A site that can take more than one form; the index of the joint.
+    Joint(usize),
+}
+
+/// A Label defined within a segment, e.g. `:loop`.
+#[derive(Debug, Clone)]
+pub struct Label {
+    /// The name of the Label, without the colon.
+    pub name: String,
+    /// The offset of the Label from the beginning of the segment.
+    pub offset: usize,
+    /// Where in the source code the Label was defined.
+    pub location: Option<Location>,
+    /// The documentation comment preceding the definition, if any.
+    pub doc: Option<String>,
+    /// The index of the span the Label begins.
+    span: usize,
+}
+
+/// A value that is packed once the Object is linked, e.g. `jp :loop`;
+/// until then, the bytes are left as zero.
+#[derive(Debug, Clone)]
+pub struct Fixup {
+    /// The offset of the value from the beginning of the segment.
+    pub offset: usize,
+    /// The width of the value, in bits; a whole number of bytes.
+    pub bits: usize,
+    /// The expression giving the value, e.g. `:loop + 1`.
+    pub value: Node,
+    /// The offset of the statement the value belongs to, e.g. the first
+    /// byte of the instruction `jr :loop`; the value's own offset if it
+    /// was not expanded from a Macro.
+    pub start: usize,
+    /// The index of the span the value lies within.
+    span: usize,
+}
+
+/// A site that can take more than one form, e.g. `jp :loop`, which could
+/// be `jr :loop` if near enough; the form is chosen when linking.
+#[derive(Debug, Clone)]
+pub struct Joint {
+    /// The offset of the site from the beginning of the segment.
+    pub offset: usize,
+    /// The forms the site can take, each packed as a segment;
+    /// the natural form first.
+    pub forms: Vec<Segment>,
+    /// The index of the form used.
+    pub form: usize,
+    /// Where in the source code the site is, e.g. a Macro invocation.
+    pub location: Option<Location>,
+}
+
+impl Segment {
+    /// The name of the segment used where none is given.
+    pub const DEFAULT: &'static str = "default";
+
+    //==========================================================================
+    /// Create an empty segment of the given name.
+    ///
+    pub fn new(name: &str) -> Self {
+        //----------------------------------------------------------------------
+        Self {
+            name: name.to_string(),
+            align: 1,
+            spans: Vec::new(),
+            len: 0,
+            labels: Vec::new(),
+            fixups: Vec::new(),
+            joints: Vec::new(),
+            statement: None,
+        }
+    }
+
+    /// The name of the segment, e.g. "code".
+    ///
+    pub fn name(&self) -> &str {
+        //----------------------------------------------------------------------
+        &self.name
+    }
+
+    /// The boundary the beginning of the segment must be placed on.
+    ///
+    pub fn align(&self) -> usize {
+        //----------------------------------------------------------------------
+        self.align
+    }
+
+    /// The length of the segment, in bytes.
+    ///
+    pub fn len(&self) -> usize {
+        //----------------------------------------------------------------------
+        self.len
+    }
+
+    /// Is the segment empty? An empty segment may still define Labels.
+    ///
+    pub fn is_empty(&self) -> bool {
+        //----------------------------------------------------------------------
+        self.len == 0
+    }
+
+    /// The contents of the segment, in order.
+    ///
+    pub fn spans(&self) -> &[Span] {
+        //----------------------------------------------------------------------
+        &self.spans
+    }
+
+    /// The Labels defined within the segment, in the order defined.
+    ///
+    pub fn labels(&self) -> &[Label] {
+        //----------------------------------------------------------------------
+        &self.labels
+    }
+
+    /// The values to patch once the Object is linked.
+    ///
+    pub fn fixups(&self) -> &[Fixup] {
+        //----------------------------------------------------------------------
+        &self.fixups
+    }
+
+    /// The sites that can take more than one form, in order.
+    ///
+    pub fn joints(&self) -> &[Joint] {
+        //----------------------------------------------------------------------
+        &self.joints
+    }
+
+    /// Begin the data of a statement expanded from the Macro invocation
+    /// given, if not already begun; i.e. the outer-most step of a value's
+    /// backtrace. A value not expanded from a Macro is a statement of its
+    /// own.
+    ///
+    pub fn begin(&mut self, invocation: Option<&Expansion>) {
+        //----------------------------------------------------------------------
+        self.statement = match (invocation, self.statement.take()) {
+            (Some(step), Some((last, start))) if *step == last => {
+                Some((last, start))
+            }
+            (Some(step), _) => Some((step.clone(), self.len)),
+            (None, _) => None,
+        };
+    }
+
+    /// Append packed bytes to the segment.
+    ///
+    pub fn write(&mut self, bytes: &[u8]) {
+        //----------------------------------------------------------------------
+        match self.spans.last_mut() {
+            Some(Span::Data(data)) => data.extend_from_slice(bytes),
+            _ => self.spans.push(Span::Data(bytes.to_vec())),
+        }
+        self.len += bytes.len();
+    }
+
+    /// Append a value to be packed once the Object is linked,
+    /// leaving zeroes in its place until then.
+    ///
+    pub fn write_fixup(&mut self, value: Node, bits: usize) {
+        //----------------------------------------------------------------------
+        let offset = self.len;
+        let start = self.statement.as_ref().map_or(offset, |s| s.1);
+        self.write(&vec![0; bits / 8]);
+        self.fixups.push(Fixup {
+            offset,
+            bits,
+            value,
+            start,
+            span: self.spans.len() - 1,
+        });
+    }
+
+    /// Append a site that can take any of the forms given, the natural
+    /// form first; the natural form is used until the segment is relaxed.
+    ///
+    pub fn write_joint(
+        &mut self,
+        forms: Vec<Segment>,
+        location: Option<Location>,
+    ) {
+        //----------------------------------------------------------------------
+        self.push_joint(forms, 0, location);
+    }
+
+    fn push_joint(
+        &mut self,
+        forms: Vec<Segment>,
+        form: usize,
+        location: Option<Location>,
+    ) {
+        //----------------------------------------------------------------------
+        self.align = forms.iter().fold(self.align, |a, f| a.max(f.align));
+        self.statement = None;
+        self.spans.push(Span::Joint(self.joints.len()));
+        let len = forms[form].len;
+        self.joints.push(Joint {
+            offset: self.len,
+            forms,
+            form,
+            location,
+        });
+        self.len += len;
+    }
+
+    /// Define a Label at the end of the segment,
+    /// i.e. naming the data that follows.
+    ///
+    pub fn define(
+        &mut self,
+        name: &str,
+        location: Option<Location>,
+        doc: Option<String>,
+    ) {
+        //----------------------------------------------------------------------
+        // the Label begins a span of its own, so that it
+        // moves with the data that follows when relaxed
+        self.statement = None;
+        self.spans.push(Span::Data(Vec::new()));
+        self.labels.push(Label {
+            name: name.to_string(),
+            offset: self.len,
+            location,
+            doc,
+            span: self.spans.len() - 1,
+        });
+    }
+
+    /// Pad the segment to the given boundary, a power of two. So that the
+    /// padding holds wherever the segment is placed, the segment itself
+    /// must be placed on (at least) the same boundary.
+    ///
+    pub fn align_to(&mut self, boundary: usize) {
+        //----------------------------------------------------------------------
+        self.align = self.align.max(boundary);
+        self.statement = None;
+        let len = (boundary - self.len % boundary) % boundary;
+        self.spans.push(Span::Align(boundary, len));
+        self.len += len;
+    }
+
+    /// The packed bytes of the segment; padding is zero, as are the bytes
+    /// left for fixups, unless patched.
+    ///
+    pub fn to_bytes(&self) -> Vec<u8> {
+        //----------------------------------------------------------------------
+        let mut bytes = Vec::with_capacity(self.len);
+        for span in self.spans.iter() {
+            match span {
+                Span::Data(data) => bytes.extend_from_slice(data),
+                Span::Align(_, len) => bytes.resize(bytes.len() + len, 0),
+                Span::Joint(i) => {
+                    let joint = &self.joints[*i];
+                    bytes.extend(joint.forms[joint.form].to_bytes());
+                }
+            }
+        }
+        bytes
+    }
+
+    /// Lay out the segment anew with the form given for each joint, in
+    /// order. The Labels and fixups that follow a joint move with it, and
+    /// the padding to each boundary is recalculated.
+    ///
+    pub fn relax(&self, forms: &[usize]) -> Segment {
+        //----------------------------------------------------------------------
+        let mut relaxed = Segment::new(&self.name);
+        relaxed.align = self.align;
+
+        // where each span began, before and after
+        let mut starts = Vec::with_capacity(self.spans.len());
+        let mut offset = 0;
+        for span in self.spans.iter() {
+            starts.push((offset, relaxed.len));
+            match span {
+                Span::Data(data) => {
+                    relaxed.spans.push(Span::Data(data.clone()));
+                    relaxed.len += data.len();
+                    offset += data.len();
+                }
+                Span::Align(boundary, len) => {
+                    relaxed.align_to(*boundary);
+                    offset += len;
+                }
+                Span::Joint(i) => {
+                    let joint = &self.joints[*i];
+                    relaxed.push_joint(
+                        joint.forms.clone(),
+                        forms[*i],
+                        joint.location,
+                    );
+                    offset += joint.forms[joint.form].len;
+                }
+            }
+        }
+        relaxed.labels = self
+            .labels
+            .iter()
+            .map(|l| {
+                let (before, after) = starts[l.span];
+                Label {
+                    offset: l.offset - before + after,
+                    ..l.clone()
+                }
+            })
+            .collect();
+        relaxed.fixups = self
+            .fixups
+            .iter()
+            .map(|f| {
+                let (before, after) = starts[f.span];
+                Fixup {
+                    offset: f.offset - before + after,
+                    start: f.start - before + after,
+                    ..f.clone()
+                }
+            })
+            .collect();
+        relaxed
+    }
 }
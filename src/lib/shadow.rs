@@ -0,0 +1,125 @@
+// retronym (C) copyright Kroc Camen 2017, 2018
+// BSD 2-clause licence; see LICENSE.TXT
+
+//! **Shadow-register tracking** for the Z80. An optional check, enabled with
+//! the `z80-shadow-check` option (by the z80 module's `z80_shadow_check`, or
+//! `-e z80-shadow-check`), that follows `ex AF, AF'` and `exx`
+//! through the instructions in the order they are written and warns where
+//! a hint disagrees with which registers are in play, e.g.
+//!
+//! ```
+//! ex      AF, AF'
+//! ld      A, B        ; fine, no hints are given
+//! ld      A`count, B  ; warning, the shadow `A` is in play
+//! ex      AF', AF
+//! ld      A'count, B  ; warning, the shadow `A` is not in play
+//! ```
+//!
+//! Registers without a hint are not checked. Only straight-line code is
+//! followed; jumps, calls and returns are not, so a swap in a subroutine
+//! is not seen by its caller.
+//!
+
+use crate::atom::Hint;
+use crate::error::*;
+use crate::list::List;
+use crate::node::{Node, NodeKind};
+
+/// The name of the option that enables the check.
+pub const OPTION: &str = "z80-shadow-check";
+
+/// The registers swapped by `ex AF, AF'`.
+const AF: &[&str] = &["A", "F", "AF"];
+/// The registers swapped by `exx`.
+const BC_DE_HL: &[&str] = &["B", "C", "D", "E", "H", "L", "BC", "DE", "HL"];
+
+/// Which set of registers is in play, the primary or the shadow?
+#[derive(Debug, Default)]
+pub struct Shadows {
+    /// Has `ex AF, AF'` swapped in the shadow AF?
+    is_af: bool,
+    /// Has `exx` swapped in the shadow BC, DE & HL?
+    is_bc_de_hl: bool,
+}
+
+impl Shadows {
+    //==========================================================================
+    /// Follow a Macro invocation (an instruction), returning a warning for
+    /// each register whose hint disagrees with the set of registers in play.
+    ///
+    pub fn step(&mut self, name: &str, args: &List) -> Vec<ParseError> {
+        //----------------------------------------------------------------------
+        match (name, args.len()) {
+            ("ex", 2) if args.iter().all(|a| Self::is_register(a, "AF")) => {
+                self.is_af = !self.is_af;
+                return Vec::new();
+            }
+            ("exx", 0) => {
+                self.is_bc_de_hl = !self.is_bc_de_hl;
+                return Vec::new();
+            }
+            _ => {}
+        }
+
+        let mut warnings = Vec::new();
+        for (i, arg) in args.iter().enumerate() {
+            // `C` is also the carry condition, e.g. `jr C, -2`
+            let is_condition = i == 0
+                && Self::is_register(arg, "C")
+                && ["jr", "jp", "call", "ret"].contains(&name);
+            if !is_condition {
+                self.check(arg, &mut warnings);
+            }
+        }
+        warnings
+    }
+
+    /// Check the hints of the registers within an argument.
+    ///
+    fn check(&self, node: &Node, warnings: &mut Vec<ParseError>) {
+        //----------------------------------------------------------------------
+        match node.kind {
+            NodeKind::Atom(ref name, ref hint) if *hint != Hint::None => {
+                let is_shadow = if AF.contains(&name.as_str()) {
+                    self.is_af
+                } else if BC_DE_HL.contains(&name.as_str()) {
+                    self.is_bc_de_hl
+                } else {
+                    return;
+                };
+                if hint.is_shadow() != is_shadow {
+                    warnings.push(
+                        parse_error(ParseErrorKind::Warning(format!(
+                            "`{}{}` is used, but the {} `{}` is in play",
+                            name,
+                            hint,
+                            if is_shadow { "shadow" } else { "primary" },
+                            name
+                        )))
+                        .at(node.location()),
+                    );
+                }
+            }
+            NodeKind::Indirect(_, ref list) => {
+                for node in list.iter() {
+                    self.check(node, warnings);
+                }
+            }
+            NodeKind::Expr(ref expr) => {
+                self.check(&expr.left, warnings);
+                self.check(&expr.right, warnings);
+            }
+            _ => {}
+        }
+    }
+
+    /// Is the Node the given register, regardless of any hint?
+    ///
+    fn is_register(node: &Node, register: &str) -> bool {
+        //----------------------------------------------------------------------
+        match node.kind {
+            NodeKind::Atom(ref name, _) => name == register,
+            _ => false,
+        }
+    }
+}
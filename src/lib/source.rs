@@ -0,0 +1,151 @@
+// retronym (C) copyright Kroc Camen 2017, 2018
+// BSD 2-clause licence; see LICENSE.TXT
+
+//! The **SourceMap** owns the source code of every file loaded -- source
+//! files, modules imported, text typed at the REPL -- and hands out a
+//! `FileId` for each. The ID is stable for as long as the SourceMap lives,
+//! so tokens, AST nodes, errors &c. refer back to their source code with a
+//! `Span`, the file's ID and a range of bytes within, instead of borrowing
+//! the text; the Assembler can then hold Objects from many files at once.
+//!
+//! Each file's source code is shared by reference counting, so a `Token`
+//! keeps the file alive without a lifetime tying it to the SourceMap.
+
+use crate::error::Location;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+
+/// Identifies a file within a `SourceMap`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FileId(usize);
+
+/// A range of bytes within a file's source code.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Span {
+    /// The file the source code belongs to.
+    pub file: FileId,
+    /// Byte offset of the start of the span.
+    pub start: usize,
+    /// Byte offset just past the end of the span.
+    pub end: usize,
+}
+
+/// The source code of a file, with its name for error messages.
+#[derive(Debug)]
+pub struct SourceFile {
+    /// The file's ID within the SourceMap it was added to.
+    id: FileId,
+    /// The name of the file, e.g. "game.rym" or "z80".
+    name: String,
+    /// The source code.
+    text: String,
+    /// The byte offset at which each line begins.
+    lines: Vec<usize>,
+}
+
+impl SourceFile {
+    //==========================================================================
+    /// The file's ID within the SourceMap it was added to.
+    ///
+    pub fn id(&self) -> FileId {
+        //----------------------------------------------------------------------
+        self.id
+    }
+
+    /// The name of the file, e.g. "game.rym" or "z80".
+    ///
+    pub fn name(&self) -> &str {
+        //----------------------------------------------------------------------
+        &self.name
+    }
+
+    /// The source code of the file.
+    ///
+    pub fn text(&self) -> &str {
+        //----------------------------------------------------------------------
+        &self.text
+    }
+
+    /// The line & column at which a span begins.
+    ///
+    pub fn location(&self, span: Span) -> Location {
+        //----------------------------------------------------------------------
+        let line = match self.lines.binary_search(&span.start) {
+            Ok(i) => i,
+            Err(i) => i - 1,
+        };
+        let col = self.text[self.lines[line]..span.start].chars().count();
+        Location {
+            span,
+            line: line + 1,
+            col: col + 1,
+        }
+    }
+}
+
+/// The files loaded, in the order loaded.
+#[derive(Default)]
+pub struct SourceMap {
+    files: Vec<Rc<SourceFile>>,
+}
+
+impl SourceMap {
+    //==========================================================================
+    /// Add source code to the SourceMap under the given name,
+    /// returning the ID given to it.
+    ///
+    pub fn add(&mut self, name: &str, text: String) -> FileId {
+        //----------------------------------------------------------------------
+        let id = FileId(self.files.len());
+        let lines = Some(0)
+            .into_iter()
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        self.files.push(Rc::new(SourceFile {
+            id,
+            name: name.to_string(),
+            text,
+            lines,
+        }));
+        id
+    }
+
+    /// Read a source file into the SourceMap, named after its path,
+    /// returning the ID given to it.
+    ///
+    pub fn load<P: AsRef<Path>>(&mut self, path: P) -> io::Result<FileId> {
+        //----------------------------------------------------------------------
+        let text = fs::read_to_string(&path)?;
+        Ok(self.add(&path.as_ref().to_string_lossy(), text))
+    }
+
+    /// The file of the given ID.
+    ///
+    pub fn file(&self, id: FileId) -> &Rc<SourceFile> {
+        //----------------------------------------------------------------------
+        &self.files[id.0]
+    }
+
+    /// The name of the file of the given ID.
+    ///
+    pub fn name(&self, id: FileId) -> &str {
+        //----------------------------------------------------------------------
+        self.files[id.0].name()
+    }
+
+    /// The source code of the file of the given ID.
+    ///
+    pub fn source(&self, id: FileId) -> &str {
+        //----------------------------------------------------------------------
+        self.files[id.0].text()
+    }
+
+    /// The source code within a span.
+    ///
+    pub fn snippet(&self, span: Span) -> &str {
+        //----------------------------------------------------------------------
+        &self.source(span.file)[span.start..span.end]
+    }
+}
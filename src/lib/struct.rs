@@ -20,9 +20,9 @@ use crate::field::Field;
 // Rust structs.
 //
 #[derive(Default)]
-pub struct Struct<'token> {
+pub struct Struct {
     /// The list of Fields in the Struct.
-    fields: Vec<Field<'token>>,
+    fields: Vec<Field>,
     /// Width, in bytes, of the structure. Not public as this value is
     /// calculated according to the bit-packing rules.
     stride: usize,
@@ -34,7 +34,7 @@ pub struct Struct<'token> {
 use itertools::Itertools;
 use std::fmt::{self, *};
 
-impl Display for Struct<'_> {
+impl Display for Struct {
     //==========================================================================
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         //----------------------------------------------------------------------
@@ -43,7 +43,7 @@ impl Display for Struct<'_> {
     }
 }
 
-impl Debug for Struct<'_> {
+impl Debug for Struct {
     //==========================================================================
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         //----------------------------------------------------------------------
@@ -54,20 +54,20 @@ impl Debug for Struct<'_> {
 
 use std::slice;
 
-impl<'token> IntoIterator for &'token Struct<'token> {
+impl<'a> IntoIterator for &'a Struct {
     //==========================================================================
-    type Item = &'token Field<'token>;
-    type IntoIter = slice::Iter<'token, Field<'token>>;
+    type Item = &'a Field;
+    type IntoIter = slice::Iter<'a, Field>;
 
     /// We only ever return references from iterating a Struct.
     ///
-    fn into_iter(self) -> slice::Iter<'token, Field<'token>> {
+    fn into_iter(self) -> slice::Iter<'a, Field> {
         //----------------------------------------------------------------------
         self.fields.iter()
     }
 }
 
-impl<'token> Struct<'token> {
+impl Struct {
     //==========================================================================
     /// The width of the struct, in bytes.
     ///
@@ -86,7 +86,7 @@ impl<'token> Struct<'token> {
 
     /// Add a `Field` to the `Struct`. [Chainable]
     ///
-    pub fn add_field(mut self, field: Field<'token>) -> Self {
+    pub fn add_field(mut self, field: Field) -> Self {
         //----------------------------------------------------------------------
         // how many bytes does this add to the stride?
         self.stride += match field.bits() {
@@ -101,7 +101,7 @@ impl<'token> Struct<'token> {
 
     /// Get a Field from the Struct.
     ///
-    pub fn field(&'token self, index: usize) -> &'token Field<'token> {
+    pub fn field(&self, index: usize) -> &Field {
         //----------------------------------------------------------------------
         &self.fields[index]
     }
@@ -109,11 +109,11 @@ impl<'token> Struct<'token> {
 
 use crate::list::List;
 
-impl<'token> From<&'token List<'token>> for Struct<'token> {
+impl From<&List> for Struct {
     //==========================================================================
     /// "Resolve" a List of Types into a Record Struct.
     ///
-    fn from(list: &'token List<'token>) -> Self {
+    fn from(list: &List) -> Self {
         //----------------------------------------------------------------------
         // take the List of Nodes,
         list.into_iter()
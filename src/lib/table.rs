@@ -7,24 +7,24 @@
 use crate::error::*;
 use crate::row::{Row, RowBuilder};
 
-pub struct TableBuilder<'token> {
+pub struct TableBuilder<'a> {
     /// Reference to the Record-struct used to define the columns.
-    record: &'token Struct<'token>,
+    record: &'a Struct,
     /// The Rows built for the Table.
-    rows: Vec<Row<'token>>,
+    rows: Vec<Row<'a>>,
     /// RowBuilder employed to populate Rows one-by-one.
-    builder: RowBuilder<'token>,
+    builder: RowBuilder<'a>,
     /// Current Row in the Table during building.
     row: usize,
 }
 
-impl<'token> TableBuilder<'token> {
+impl<'a> TableBuilder<'a> {
     //==========================================================================
     /// Tables are tightly bound to the Record-struct that defines the type of
     /// each column. You cannot create a Table without a Record-struct as this
     /// would allow swapping the Record-struct whilst the Table is using it.
     ///
-    pub fn new(record: &'token Struct<'token>) -> Self {
+    pub fn new(record: &'a Struct) -> Self {
         //----------------------------------------------------------------------
         Self {
             record,
@@ -37,14 +37,42 @@ impl<'token> TableBuilder<'token> {
     /// Add data to the Table by assigning an AST Node to the next Cell in the
     /// current Row. When the record is satsisfied, another row will be started.
     ///
+    /// Lists, and strings (which are self-contained lists of bytes), are
+    /// flattened; each element takes the next Cell.
+    ///
     /// TODO: return satisfied state, exports?
-    /// TODO: handle being passed a list (flatten it out?)
-    /// TODO: errors for non-data types of nodes
     ///
-    pub fn add_data(
-        &mut self,
-        node: &'token Node<'token>,
-    ) -> Option<ParseError> {
+    pub fn add_data(&mut self, node: &Node) -> MaybeError {
+        //----------------------------------------------------------------------
+        match node.kind {
+            NodeKind::List(ref list) => {
+                for node in list.iter() {
+                    if let Some(e) = self.add_data(node) {
+                        return Some(e);
+                    }
+                }
+                None
+            }
+            NodeKind::Str(ref s) => {
+                // drop the speech-marks
+                for b in s[1..s.len() - 1].bytes() {
+                    let node = Node::new_value(
+                        Value::Int(i32::from(b)),
+                        node.token.clone(),
+                    );
+                    if let Some(e) = self.add_cell(node) {
+                        return Some(e);
+                    }
+                }
+                None
+            }
+            _ => self.add_cell(node.clone()),
+        }
+    }
+
+    /// Assign a single Node to the next Cell in the current Row.
+    ///
+    fn add_cell(&mut self, node: Node) -> MaybeError {
         //----------------------------------------------------------------------
         // has the Row already yielded?
         if self.builder.is_satisfied() {
@@ -53,19 +81,23 @@ impl<'token> TableBuilder<'token> {
         }
         // add the data to the Row builder;
         // it'll return a Row if it has been satisfied
-        let row = self.builder.add_data(node).unwrap()?;
-        self.rows.push(row);
-        self.row += 1;
-        // no probalo
-        None
+        match self.builder.add_data(node) {
+            Err(e) => Some(e),
+            Ok(Some(row)) => {
+                self.rows.push(row);
+                self.row += 1;
+                None
+            }
+            // no probalo
+            Ok(None) => None,
+        }
     }
 
-    pub fn finish(self) -> ParseResult<Table<'token>> {
+    pub fn finish(self) -> ParseResult<Table<'a>> {
         //----------------------------------------------------------------------
-        // if finishing the table and the Row is
-        // not yet satisified, this is an error!
-        if !self.builder.is_satisfied() {
-            // TODO: convert to error
+        // if finishing the table and the Row has been
+        // started but not yet satisified, this is an error!
+        if !self.builder.is_satisfied() && !self.builder.is_empty() {
             return Err(parse_error(ParseErrorKind::Unsatisfied));
         }
 
@@ -77,19 +109,33 @@ impl<'token> TableBuilder<'token> {
     }
 }
 
-use crate::node::Node;
+use crate::node::{Node, NodeKind, Value};
 use crate::r#struct::Struct;
+use crate::segment::Segment;
 
-pub struct Table<'token> {
+pub struct Table<'a> {
     /// Reference to the Record-struct used to define the columns.
-    record: &'token Struct<'token>,
+    record: &'a Struct,
     /// The collection of Rows containing the data.
-    rows: Vec<Row<'token>>,
+    rows: Vec<Row<'a>>,
+}
+
+impl Table<'_> {
+    //==========================================================================
+    /// Pack the Rows of the Table into a segment.
+    ///
+    pub fn pack(&self, segment: &mut Segment) -> ParseResult<()> {
+        //----------------------------------------------------------------------
+        for row in self.rows.iter() {
+            row.pack(segment)?;
+        }
+        Ok(())
+    }
 }
 
 use std::fmt::{self, *};
 
-impl<'token> Display for Table<'token> {
+impl<'a> Display for Table<'a> {
     //==========================================================================
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         //----------------------------------------------------------------------
@@ -104,7 +150,7 @@ impl<'token> Display for Table<'token> {
     }
 }
 
-impl<'token> Debug for Table<'token> {
+impl<'a> Debug for Table<'a> {
     //==========================================================================
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         //----------------------------------------------------------------------
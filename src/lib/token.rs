@@ -6,36 +6,52 @@
 //! of Pest, hiding the macro-generated `Rule`s from token consumers.
 
 use crate::parser::pest::Rule;
+use crate::source::{SourceFile, Span};
 use pest::iterators::Pair;
-use pest::Span;
+use std::rc::Rc;
 
 /// A `Token` is a single 'word' ("lexeme") of the source code. It's what's
 /// known as a "new type" -- a single tuple struct -- because we cannot
 /// implement our own functions on top of Pest's `Pair` as it's from an
 /// external crate. We wrap the minimum amount of the interface for our
 /// purposes.
+///
+/// Rather than borrow the text of the source code, as Pest does, the Token
+/// keeps the span of the source code it was read from and a reference to
+/// the file it belongs to, which owns the text.
 #[derive(Clone)]
-pub struct Token<'token>(Rule, Span<'token>);
+pub struct Token(Rule, Span, Rc<SourceFile>);
 
 /// A list of tokens.
-pub type Tokens<'token> = Vec<Token<'token>>;
+pub type Tokens = Vec<Token>;
 
 /// An optional Token.
-pub type MaybeToken<'token> = Option<Token<'token>>;
+pub type MaybeToken = Option<Token>;
 
-impl<'t> From<Pair<'t, Rule>> for Token<'t> {
+impl Token {
     //==========================================================================
-    // Wrap a Pest `Pair` into our `Token`.
-    fn from(pair: Pair<'t, Rule>) -> Self {
+    /// Wrap a Pest `Pair` into our `Token`,
+    /// for the file the Pair was parsed from.
+    ///
+    pub(crate) fn new(pair: Pair<'_, Rule>, file: &Rc<SourceFile>) -> Self {
         //----------------------------------------------------------------------
-        // awating shipping of tuple struct constructors:
-        // https://github.com/rust-lang/rust/issues/51994
-        Token(pair.as_rule(), pair.as_span())
+        let span = pair.as_span();
+        Token(
+            pair.as_rule(),
+            Span {
+                file: file.id(),
+                start: span.start(),
+                end: span.end(),
+            },
+            Rc::clone(file),
+        )
     }
 }
 
+use crate::error::Location;
 use crate::ops::Operator;
 use crate::primitive::Primitive;
+use crate::r#macro::Directive;
 
 /// Describes the type of the token (and a parsed value, if possible),
 /// without having to expose the internal Pest Rule.
@@ -44,6 +60,17 @@ pub enum TokenKind {
     KeywordAtom,
     /// Token is the "macro" keyword.
     KeywordMacro,
+    /// Token is the "export" keyword.
+    KeywordExport,
+    /// Token is the "segment" keyword.
+    KeywordSegment,
+    /// Token is the "align" keyword.
+    KeywordAlign,
+    /// Token is the "rel" keyword.
+    KeywordRel,
+    /// Token is a module import, e.g. `::z80`.
+    /// The name is given without the leading colons.
+    Import(String),
     /// Token is a primitive type.
     Primitive(Primitive),
     /// Token is a struct name.
@@ -58,15 +85,41 @@ pub enum TokenKind {
     Float(f32),
     /// Token is an atom symbol.
     Atom(String),
+    /// Token is a label, e.g. `:loop`.
+    /// The name is given without the leading colon.
+    Label(String),
+    /// Token is the bank of a label, e.g. `bank(:level)`.
+    /// The name of the label is given without the leading colon.
+    Bank(String),
     /// Token is a macro symbol.
     Macro(String),
     /// Token is a string literal.
     String(String),
+    /// Token is a macro parameter, e.g. `\1` or `\name`.
+    /// The name is given without the leading backslash.
+    Param(String),
+    /// Token is a macro directive, e.g. `\shift`.
+    Directive(Directive),
+    /// Token is the opening brace of a block.
+    BlockOpen,
+    /// Token is the closing brace of a block.
+    BlockClose,
+    /// Token is an opening parenthesis.
+    ParenOpen,
+    /// Token is a closing parenthesis.
+    ParenClose,
+    /// Token is an opening square bracket.
+    BracketOpen,
+    /// Token is a closing square bracket.
+    BracketClose,
     /// Token is an `Operator`.
     Operator(Operator),
+    /// Token is a documentation comment. The text is given without
+    /// the back-ticks or surrounding white-space.
+    Doc(String),
 }
 
-impl<'token> Token<'token> {
+impl Token {
     //==========================================================================
     /// Our lexer/parser, Pest, generates an enum, `Rule`, from the original
     /// grammar file. This method returns the `Rule` discriminant for the
@@ -81,9 +134,30 @@ impl<'token> Token<'token> {
     /// The string representation directly from the original source code
     /// -- i.e. *not* normalised.
     ///
-    pub fn as_str(&self) -> &'token str {
+    pub fn as_str(&self) -> &str {
+        //----------------------------------------------------------------------
+        &self.2.text()[self.1.start..self.1.end]
+    }
+
+    /// The file the token was read from.
+    ///
+    pub fn file(&self) -> &SourceFile {
+        //----------------------------------------------------------------------
+        &self.2
+    }
+
+    /// The span of the source code the token was read from.
+    ///
+    pub fn span(&self) -> Span {
         //----------------------------------------------------------------------
-        self.1.as_str()
+        self.1
+    }
+
+    /// The line & column in the source code where the token begins.
+    ///
+    pub fn location(&self) -> Location {
+        //----------------------------------------------------------------------
+        self.2.location(self.1)
     }
 
     pub fn kind(&self) -> TokenKind {
@@ -92,18 +166,22 @@ impl<'token> Token<'token> {
             // keywords:
             Rule::keyword_atom => TokenKind::KeywordAtom,
             Rule::keyword_macro => TokenKind::KeywordMacro,
+            Rule::keyword_export => TokenKind::KeywordExport,
+            Rule::keyword_segment => TokenKind::KeywordSegment,
+            Rule::keyword_align => TokenKind::KeywordAlign,
+            Rule::keyword_rel => TokenKind::KeywordRel,
+            Rule::import => TokenKind::Import(self.as_str()[2..].to_string()),
             // primitive types:
             Rule::type_bool => TokenKind::Primitive(Primitive::BOOL),
             Rule::type_nybl => TokenKind::Primitive(Primitive::NYBL),
             Rule::type_byte => TokenKind::Primitive(Primitive::BYTE),
             Rule::type_word => TokenKind::Primitive(Primitive::WORD),
+            Rule::type_trib => TokenKind::Primitive(Primitive::TRIB),
             Rule::type_long => TokenKind::Primitive(Primitive::LONG),
             // Struct type:
             Rule::type_struct => TokenKind::Struct(self.to_string()),
             // literals:
-            Rule::int_number => {
-                TokenKind::Int(i32::from_str_radix(&self.as_str(), 16).unwrap())
-            }
+            Rule::int_number => TokenKind::Int(self.as_str().parse().unwrap()),
             Rule::hex_number => TokenKind::Hex(
                 // note that we have to drop the sigil. limitations in
                 // Pest make this difficult to do at the grammar level
@@ -116,7 +194,38 @@ impl<'token> Token<'token> {
             ),
             Rule::string => TokenKind::String(self.to_string()),
             Rule::atom => TokenKind::Atom(self.to_string()),
-            Rule::macro_ => TokenKind::Macro(self.to_string()),
+            // drop the colon from the label name
+            Rule::label => TokenKind::Label(self.as_str()[1..].to_string()),
+            Rule::bank => {
+                let s = self.as_str();
+                let label = &s[s.find(':').unwrap() + 1..s.len() - 1];
+                TokenKind::Bank(label.trim_end().to_string())
+            }
+            Rule::macro_ | Rule::macro_qualified => {
+                TokenKind::Macro(self.to_string())
+            }
+            // drop the backslash from the parameter name
+            Rule::param => TokenKind::Param(self.as_str()[1..].to_string()),
+            // directives:
+            Rule::dir_shift => TokenKind::Directive(Directive::Shift),
+            Rule::dir_end => TokenKind::Directive(Directive::End),
+            Rule::dir_if => TokenKind::Directive(Directive::If),
+            Rule::dir_else => TokenKind::Directive(Directive::Else),
+            Rule::dir_atom => TokenKind::Directive(Directive::Atom),
+            Rule::dir_number => TokenKind::Directive(Directive::Number),
+            Rule::dir_string => TokenKind::Directive(Directive::String),
+            Rule::dir_warn => TokenKind::Directive(Directive::Warn),
+            Rule::dir_error => TokenKind::Directive(Directive::Error),
+            Rule::dir_requires => TokenKind::Directive(Directive::Requires),
+            Rule::dir_enable => TokenKind::Directive(Directive::Enable),
+            Rule::dir_disable => TokenKind::Directive(Directive::Disable),
+            Rule::dir_relax => TokenKind::Directive(Directive::Relax),
+            Rule::block_open => TokenKind::BlockOpen,
+            Rule::block_close => TokenKind::BlockClose,
+            Rule::paren_open => TokenKind::ParenOpen,
+            Rule::paren_close => TokenKind::ParenClose,
+            Rule::bracket_open => TokenKind::BracketOpen,
+            Rule::bracket_close => TokenKind::BracketClose,
             // operators:
             Rule::op_add => TokenKind::Operator(Operator::Add),
             Rule::op_sub => TokenKind::Operator(Operator::Sub),
@@ -129,6 +238,14 @@ impl<'token> Token<'token> {
             Rule::op_bor => TokenKind::Operator(Operator::Bor),
             Rule::op_shl => TokenKind::Operator(Operator::Shl),
             Rule::op_shr => TokenKind::Operator(Operator::Shr),
+            // documentation:
+            Rule::doc_line => {
+                TokenKind::Doc(self.as_str()[2..].trim().to_string())
+            }
+            Rule::doc_block => {
+                let text = self.as_str();
+                TokenKind::Doc(text[3..text.len() - 3].trim().to_string())
+            }
             // Pest rules that do not translate to tokens, e.g. `EOI`
             _ => panic!(
                 "Token not of a type that could be translated to a TokenKind!"
@@ -139,7 +256,7 @@ impl<'token> Token<'token> {
 
 use std::fmt::{self, *};
 
-impl<'token> Display for Token<'token> {
+impl Display for Token {
     //==========================================================================
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         //----------------------------------------------------------------------
@@ -147,7 +264,7 @@ impl<'token> Display for Token<'token> {
     }
 }
 
-impl<'token> Debug for Token<'token> {
+impl Debug for Token {
     //==========================================================================
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         //----------------------------------------------------------------------
@@ -155,34 +272,61 @@ impl<'token> Debug for Token<'token> {
     }
 }
 
-impl<'t> Token<'t> {
+impl Token {
     //==========================================================================
-    /// Is this token a keyword? ("atom", "macro"). This doesn't include the
-    /// type-names ("byte", "word", "long" &c.) because those are recognised
-    /// separately and not bundled in with keywords.
+    /// Is this token a keyword? ("atom", "macro", "export", "segment",
+    /// "align", "rel"). This doesn't
+    /// include the type-names ("byte", "word", "long" &c.) because those are
+    /// recognised separately and not bundled in with keywords.
     ///
     pub fn is_keyword(&self) -> bool {
         //----------------------------------------------------------------------
-        match self.as_rule() {
-            Rule::keyword_atom | Rule::keyword_macro => true,
-            _ => false,
-        }
+        matches!(
+            self.as_rule(),
+            Rule::keyword_atom
+                | Rule::keyword_macro
+                | Rule::keyword_export
+                | Rule::keyword_segment
+                | Rule::keyword_align
+                | Rule::keyword_rel
+        )
     }
 
     pub fn is_keyword_atom(&self) -> bool {
         //----------------------------------------------------------------------
-        match self.as_rule() {
-            Rule::keyword_atom => true,
-            _ => false,
-        }
+        matches!(self.as_rule(), Rule::keyword_atom)
     }
 
     pub fn is_keyword_macro(&self) -> bool {
         //----------------------------------------------------------------------
-        match self.as_rule() {
-            Rule::keyword_macro => true,
-            _ => false,
-        }
+        matches!(self.as_rule(), Rule::keyword_macro)
+    }
+
+    pub fn is_keyword_export(&self) -> bool {
+        //----------------------------------------------------------------------
+        matches!(self.as_rule(), Rule::keyword_export)
+    }
+
+    pub fn is_keyword_segment(&self) -> bool {
+        //----------------------------------------------------------------------
+        matches!(self.as_rule(), Rule::keyword_segment)
+    }
+
+    pub fn is_keyword_align(&self) -> bool {
+        //----------------------------------------------------------------------
+        matches!(self.as_rule(), Rule::keyword_align)
+    }
+
+    pub fn is_keyword_rel(&self) -> bool {
+        //----------------------------------------------------------------------
+        matches!(self.as_rule(), Rule::keyword_rel)
+    }
+
+    /// Is this a module import, e.g. `::z80`?
+    ///
+    pub fn is_import(&self) -> bool {
+        //----------------------------------------------------------------------
+        matches!(self.as_rule(), Rule::import)
     }
 
     /// Is this a built-in (primitive type),
@@ -190,22 +334,20 @@ impl<'t> Token<'t> {
     ///
     pub fn is_type_primitive(&self) -> bool {
         //----------------------------------------------------------------------
-        match self.as_rule() {
+        matches!(
+            self.as_rule(),
             Rule::type_bool
-            | Rule::type_nybl
-            | Rule::type_byte
-            | Rule::type_word
-            | Rule::type_long => true,
-            _ => false,
-        }
+                | Rule::type_nybl
+                | Rule::type_byte
+                | Rule::type_word
+                | Rule::type_trib
+                | Rule::type_long
+        )
     }
 
     pub fn is_type_struct(&self) -> bool {
         //----------------------------------------------------------------------
-        match self.as_rule() {
-            Rule::type_struct => true,
-            _ => false,
-        }
+        matches!(self.as_rule(), Rule::type_struct)
     }
 
     /// Is this a built-in (primitive) or user-defined (struct) type?
@@ -221,40 +363,175 @@ impl<'t> Token<'t> {
     ///
     pub fn is_atom(&self) -> bool {
         //----------------------------------------------------------------------
-        match self.as_rule() {
-            Rule::atom => true,
-            _ => false,
-        }
+        matches!(self.as_rule(), Rule::atom)
     }
 
-    /// Is this a macro name?
+    /// Is this a macro name? This includes names
+    /// qualified by their module, e.g. `z80.ld`.
     ///
     pub fn is_macro(&self) -> bool {
         //----------------------------------------------------------------------
-        match self.as_rule() {
-            Rule::macro_ => true,
-            _ => false,
-        }
+        matches!(self.as_rule(), Rule::macro_ | Rule::macro_qualified)
+    }
+
+    /// Is this a macro name qualified by its module, e.g. `z80.ld`?
+    ///
+    pub fn is_macro_qualified(&self) -> bool {
+        //----------------------------------------------------------------------
+        matches!(self.as_rule(), Rule::macro_qualified)
+    }
+
+    /// Is this a macro parameter?
+    ///
+    pub fn is_param(&self) -> bool {
+        //----------------------------------------------------------------------
+        matches!(self.as_rule(), Rule::param)
+    }
+
+    /// Is this a macro directive? (`\shift`, `\if` &c.)
+    ///
+    pub fn is_directive(&self) -> bool {
+        //----------------------------------------------------------------------
+        matches!(
+            self.as_rule(),
+            Rule::dir_shift
+                | Rule::dir_end
+                | Rule::dir_if
+                | Rule::dir_else
+                | Rule::dir_atom
+                | Rule::dir_number
+                | Rule::dir_string
+                | Rule::dir_warn
+                | Rule::dir_error
+                | Rule::dir_requires
+                | Rule::dir_enable
+                | Rule::dir_disable
+                | Rule::dir_relax
+        )
+    }
+
+    pub fn is_directive_if(&self) -> bool {
+        //----------------------------------------------------------------------
+        matches!(self.as_rule(), Rule::dir_if)
+    }
+
+    pub fn is_directive_else(&self) -> bool {
+        //----------------------------------------------------------------------
+        matches!(self.as_rule(), Rule::dir_else)
+    }
+
+    pub fn is_directive_warn(&self) -> bool {
+        //----------------------------------------------------------------------
+        matches!(self.as_rule(), Rule::dir_warn)
+    }
+
+    pub fn is_directive_error(&self) -> bool {
+        //----------------------------------------------------------------------
+        matches!(self.as_rule(), Rule::dir_error)
+    }
+
+    pub fn is_directive_requires(&self) -> bool {
+        //----------------------------------------------------------------------
+        matches!(self.as_rule(), Rule::dir_requires)
+    }
+
+    pub fn is_directive_enable(&self) -> bool {
+        //----------------------------------------------------------------------
+        matches!(self.as_rule(), Rule::dir_enable)
+    }
+
+    pub fn is_directive_relax(&self) -> bool {
+        //----------------------------------------------------------------------
+        matches!(self.as_rule(), Rule::dir_relax)
+    }
+
+    /// Is this a directive that is followed by a string?
+    /// (`\warn`, `\error`, `\requires`, `\enable`, `\disable`)
+    ///
+    pub fn is_directive_message(&self) -> bool {
+        //----------------------------------------------------------------------
+        matches!(
+            self.as_rule(),
+            Rule::dir_warn
+                | Rule::dir_error
+                | Rule::dir_requires
+                | Rule::dir_enable
+                | Rule::dir_disable
+        )
+    }
+
+    /// Is this the opening brace of a block?
+    ///
+    pub fn is_block_open(&self) -> bool {
+        //----------------------------------------------------------------------
+        matches!(self.as_rule(), Rule::block_open)
+    }
+
+    /// Is this the closing brace of a block?
+    ///
+    pub fn is_block_close(&self) -> bool {
+        //----------------------------------------------------------------------
+        matches!(self.as_rule(), Rule::block_close)
+    }
+
+    /// Is this an opening parenthesis?
+    ///
+    pub fn is_paren_open(&self) -> bool {
+        //----------------------------------------------------------------------
+        matches!(self.as_rule(), Rule::paren_open)
+    }
+
+    /// Is this a closing parenthesis?
+    ///
+    pub fn is_paren_close(&self) -> bool {
+        //----------------------------------------------------------------------
+        matches!(self.as_rule(), Rule::paren_close)
+    }
+
+    /// Is this an opening square bracket?
+    ///
+    pub fn is_bracket_open(&self) -> bool {
+        //----------------------------------------------------------------------
+        matches!(self.as_rule(), Rule::bracket_open)
+    }
+
+    /// Is this a closing square bracket?
+    ///
+    pub fn is_bracket_close(&self) -> bool {
+        //----------------------------------------------------------------------
+        matches!(self.as_rule(), Rule::bracket_close)
+    }
+
+    /// Is this a documentation comment, either a line or a block?
+    ///
+    pub fn is_doc(&self) -> bool {
+        //----------------------------------------------------------------------
+        matches!(self.as_rule(), Rule::doc_line | Rule::doc_block)
+    }
+
+    /// Is this a label, e.g. `:loop`? Whether it is a definition or
+    /// a recall depends upon where it is found.
+    ///
+    pub fn is_label(&self) -> bool {
+        //----------------------------------------------------------------------
+        matches!(self.as_rule(), Rule::label)
     }
 
     /// Is this a number literal?
     ///
     pub fn is_number(&self) -> bool {
         //----------------------------------------------------------------------
-        match self.as_rule() {
-            Rule::int_number | Rule::hex_number | Rule::bin_number => true,
-            _ => false,
-        }
+        matches!(
+            self.as_rule(),
+            Rule::int_number | Rule::hex_number | Rule::bin_number
+        )
     }
 
     /// Is this a string literal?
     ///
     pub fn is_string(&self) -> bool {
         //----------------------------------------------------------------------
-        match self.as_rule() {
-            Rule::string => true,
-            _ => false,
-        }
+        matches!(self.as_rule(), Rule::string)
     }
 
     /// Is this a valid opening token for an expression? This wouldn't include
@@ -262,13 +539,19 @@ impl<'t> Token<'t> {
     ///
     pub fn is_expr(&self) -> bool {
         //----------------------------------------------------------------------
-        match self.as_rule() {
+        matches!(
+            self.as_rule(),
             Rule::atom
-            | Rule::int_number
-            | Rule::hex_number
-            | Rule::bin_number => true,
-            _ => false,
-        }
+                | Rule::label
+                | Rule::bank
+                | Rule::param
+                | Rule::dir_atom
+                | Rule::dir_number
+                | Rule::dir_string
+                | Rule::int_number
+                | Rule::hex_number
+                | Rule::bin_number
+        )
     }
 
     /// Is this a 'value' -- i.e. a token that can return a value. This would
@@ -277,41 +560,41 @@ impl<'t> Token<'t> {
     ///
     pub fn is_value(&self) -> bool {
         //----------------------------------------------------------------------
-        match self.as_rule() {
-            Rule::int_number | Rule::hex_number | Rule::bin_number => true,
-            _ => false,
-        }
+        matches!(
+            self.as_rule(),
+            Rule::int_number | Rule::hex_number | Rule::bin_number
+        )
     }
 
     pub fn is_literal(&self) -> bool {
         //----------------------------------------------------------------------
-        match self.as_rule() {
+        matches!(
+            self.as_rule(),
             Rule::int_number
-            | Rule::hex_number
-            | Rule::bin_number
-            | Rule::string => true,
-            _ => false,
-        }
+                | Rule::hex_number
+                | Rule::bin_number
+                | Rule::string
+        )
     }
 
     /// Is this an operator?
     ///
     pub fn is_oper(&self) -> bool {
         //----------------------------------------------------------------------
-        match self.as_rule() {
+        matches!(
+            self.as_rule(),
             Rule::op_pow
-            | Rule::op_add
-            | Rule::op_sub
-            | Rule::op_mul
-            | Rule::op_div
-            | Rule::op_mod
-            | Rule::op_xor
-            | Rule::op_and
-            | Rule::op_bor
-            | Rule::op_shl
-            | Rule::op_shr => true,
-            _ => false,
-        }
+                | Rule::op_add
+                | Rule::op_sub
+                | Rule::op_mul
+                | Rule::op_div
+                | Rule::op_mod
+                | Rule::op_xor
+                | Rule::op_and
+                | Rule::op_bor
+                | Rule::op_shl
+                | Rule::op_shr
+        )
     }
 
     /// If a token does not require any external information to resolve,
@@ -320,17 +603,18 @@ impl<'t> Token<'t> {
     /// considered static as they require other tokens to calculate a value.
     ///
     pub fn is_static(&self) -> bool {
-        match self.as_rule() {
+        matches!(
+            self.as_rule(),
             Rule::int_number
-            | Rule::hex_number
-            | Rule::bin_number
-            | Rule::string
-            | Rule::type_bool
-            | Rule::type_nybl
-            | Rule::type_byte
-            | Rule::type_word
-            | Rule::type_long => true,
-            _ => false,
-        }
+                | Rule::hex_number
+                | Rule::bin_number
+                | Rule::string
+                | Rule::type_bool
+                | Rule::type_nybl
+                | Rule::type_byte
+                | Rule::type_word
+                | Rule::type_trib
+                | Rule::type_long
+        )
     }
 }
@@ -3,55 +3,66 @@
 
 use crate::parser::pest::Rule;
 use crate::parser::pest::RymParser;
-use crate::token::Token;
-use pest::iterators::Pairs;
+use crate::source::SourceFile;
+use crate::token::{Token, Tokens};
+use std::rc::Rc;
 
-pub struct Tokenizer<'token> {
-    /// A Pest Pairs iterator which will yield each token from the source code.
-    pairs: Pairs<'token, Rule>,
+pub struct Tokenizer {
+    /// The tokens read from the source code, in order. Pest borrows the
+    /// source code as it parses, so the tokens are read all at once.
+    tokens: std::vec::IntoIter<Token>,
 }
 
-use std::convert::From;
-
-impl<'token> From<Pairs<'token, Rule>> for Tokenizer<'token> {
-    //==========================================================================
-    /// Build a new token iterator from Pest's Pairs iterator.
-    /// (the `parse` method will give you one of these)
-    fn from(pairs: Pairs<'token, Rule>) -> Self {
-        //----------------------------------------------------------------------
-        Self { pairs }
-    }
-}
-
-use crate::error::ParseError;
+use crate::error::*;
+use crate::source::Span;
+use pest::error::InputLocation;
 // required for the `parse` method of `RymParser` to be visible here.
 use pest::Parser;
 
-impl<'token> Tokenizer<'token> {
+impl Tokenizer {
     //==========================================================================
-    // note that we cannot implement `FromStr` due to the lifetime requirement?
-    #[allow(clippy::should_implement_trait)]
-    pub fn from_str(s: &'token str) -> Result<Self, ParseError> {
+    /// Read the tokens of a file's source code. Returns a `ParseError`
+    /// at the first text that does not follow the grammar.
+    ///
+    pub fn new(file: &Rc<SourceFile>) -> ParseResult<Self> {
         //----------------------------------------------------------------------
         // create a parser from the given source code
-        let parser = RymParser::parse(Rule::rym, s).expect(
-            // TODO: cast `PestError` to `ParseError`
-            "Fail to parse source text using Pest.",
-        );
+        let pairs = match RymParser::parse(Rule::rym, file.text()) {
+            Ok(pairs) => pairs,
+            Err(e) => {
+                let start = match e.location {
+                    InputLocation::Pos(pos) => pos,
+                    InputLocation::Span((start, _)) => start,
+                };
+                // the text that could not be read, to the end of its line
+                let text = file.text()[start..].lines().next().unwrap_or("");
+                let span = Span {
+                    file: file.id(),
+                    start,
+                    end: start + text.len(),
+                };
+                return Err(parse_error(ParseErrorKind::Syntax(
+                    text.trim_end().to_string(),
+                ))
+                .at(Some(file.location(span))));
+            }
+        };
 
-        // convert the parse result into an wrapped iterator
-        Ok(Self::from(parser))
+        // wrap each Pest Pair as a Token
+        // (we don't want to expose the Pair internals)
+        let tokens: Tokens = pairs.map(|pair| Token::new(pair, file)).collect();
+        Ok(Self {
+            tokens: tokens.into_iter(),
+        })
     }
 }
 
-impl<'token> Iterator for Tokenizer<'token> {
+impl Iterator for Tokenizer {
     //==========================================================================
-    type Item = Token<'token>;
+    type Item = Token;
 
     fn next(&mut self) -> Option<Self::Item> {
         //----------------------------------------------------------------------
-        // get the next Pest Pair and return it as a Token
-        // (we don't want to expose the Pair internals)
-        self.pairs.next().map(Token::from)
+        self.tokens.next()
     }
 }
@@ -6,14 +6,25 @@
 
 //==============================================================================
 
+pub mod cli;
 pub mod repl;
 
 //==============================================================================
 
+use std::env;
 use std::io;
 use std::io::Write;
 
 fn main() {
+    // `retronym asm` assembles a file and `retronym doc`
+    // documents a module, otherwise we start the REPL
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("asm") => return cli::asm(&args[1..]),
+        Some("doc") => return cli::doc(&args[1..]),
+        _ => {}
+    }
+
     println!();
     println!("Retronym (C) copryright Kroc Camen 2017, 2018");
     println!("BSD 2-clause licence; see LICENSE.TXT");
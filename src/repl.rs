@@ -22,6 +22,14 @@ pub fn repl() {
         // get user input
         io::stdin().read_line(&mut line).unwrap();
 
-        retronym::assemble_str(&line);
+        // report what went wrong, if anything
+        match retronym::assemble_str(&line) {
+            Ok(asm) => {
+                for w in asm.warnings().iter() {
+                    println!("! WARNING: {}", w);
+                }
+            }
+            Err(e) => println!("! ERROR: {}", e),
+        }
     }
 }
@@ -0,0 +1,148 @@
+// retronym (C) copyright Kroc Camen 2017, 2018
+// BSD 2-clause licence; see LICENSE.TXT
+
+//! Helpers shared by the integration tests. Each test file is built as a
+//! crate of its own and uses only some of these, hence `dead_code`.
+
+#![allow(dead_code)]
+
+use retronym::assembler::Assembler;
+use retronym::error::{ParseError, ParseResult};
+
+/// The address of a site relaxed, and its length before and after.
+///
+pub type Relaxed = (usize, usize, usize);
+
+/// Source code to assemble for a test, named `test`, along with the
+/// modules, options and memory map to assemble and link it with. The
+/// modules bundled with Retronym can always be imported.
+///
+pub struct Build<'a> {
+    source: &'a str,
+    modules: &'a [(&'a str, &'a str)],
+    options: &'a [&'a str],
+    map: Option<&'a str>,
+}
+
+impl<'a> Build<'a> {
+    //==========================================================================
+    /// Build the source code given, with no other modules or options.
+    ///
+    pub fn new(source: &'a str) -> Self {
+        //----------------------------------------------------------------------
+        Self {
+            source,
+            modules: &[],
+            options: &[],
+            map: None,
+        }
+    }
+
+    /// Add modules that can be imported, as pairs of name and source code;
+    /// these take precedence over the bundled modules.
+    ///
+    pub fn modules(self, modules: &'a [(&'a str, &'a str)]) -> Self {
+        //----------------------------------------------------------------------
+        Self { modules, ..self }
+    }
+
+    /// Enable the options given, e.g. "undocumented".
+    ///
+    pub fn options(self, options: &'a [&'a str]) -> Self {
+        //----------------------------------------------------------------------
+        Self { options, ..self }
+    }
+
+    /// Link by the memory map given, in Retronym syntax.
+    ///
+    pub fn map(self, map: &'a str) -> Self {
+        //----------------------------------------------------------------------
+        Self {
+            map: Some(map),
+            ..self
+        }
+    }
+
+    /// Assemble the source code and read the memory map, if any, returning
+    /// the Assembler or the error message.
+    ///
+    pub fn assembler(&self) -> Result<Assembler, String> {
+        //----------------------------------------------------------------------
+        self.assembler_with(&[]).map_err(|e| e.to_string())
+    }
+
+    /// Assemble the source code, which must fail, returning the error.
+    ///
+    pub fn error(&self) -> ParseError {
+        //----------------------------------------------------------------------
+        match self.assembler_with(&[]) {
+            Ok(_) => panic!("`{}` assembled without error", self.source),
+            Err(e) => e,
+        }
+    }
+
+    /// Assemble the source code, returning the packed bytes of its Object,
+    /// unlinked, or the error message.
+    ///
+    pub fn assemble(&self) -> Result<Vec<u8>, String> {
+        //----------------------------------------------------------------------
+        // the modules imported come first
+        let asm = self.assembler()?;
+        Ok(asm.objects().last().unwrap().data().to_vec())
+    }
+
+    /// Assemble and link the source code, returning the linked bytes or the
+    /// error message.
+    ///
+    pub fn link(&self) -> Result<Vec<u8>, String> {
+        //----------------------------------------------------------------------
+        let asm = self.assembler()?;
+        match asm.link() {
+            Ok(image) => Ok(image.data().to_vec()),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    /// Assemble and link the source code with relaxation enabled, returning
+    /// the linked bytes and each site relaxed, or the error message.
+    ///
+    pub fn relax(&self) -> Result<(Vec<u8>, Vec<Relaxed>), String> {
+        //----------------------------------------------------------------------
+        let asm = self
+            .assembler_with(&[retronym::linker::OPTION])
+            .map_err(|e| e.to_string())?;
+        let image = asm.link().map_err(|e| e.to_string())?;
+        let relaxations = image
+            .relaxations()
+            .iter()
+            .map(|r| (r.address, r.from, r.to))
+            .collect();
+        Ok((image.data().to_vec(), relaxations))
+    }
+
+    /// Assemble the source code with the options given enabled, as well as
+    /// those of the build, and read the memory map, if any.
+    ///
+    fn assembler_with(&self, options: &[&str]) -> ParseResult<Assembler> {
+        //----------------------------------------------------------------------
+        let mut asm = Assembler::default();
+        for (name, module) in self.modules.iter() {
+            asm.add_module(name, module);
+        }
+        asm.add_bundled();
+        for option in self.options.iter().chain(options) {
+            asm.enable(option);
+        }
+        let file = asm.add_source("test", self.source);
+        if let Some(e) = asm.assemble_file(file) {
+            return Err(e);
+        }
+        if let Some(map) = self.map {
+            let file = asm.add_source("map", map);
+            if let Some(e) = asm.add_map(file) {
+                return Err(e);
+            }
+        }
+        Ok(asm)
+    }
+}
@@ -0,0 +1,173 @@
+// retronym (C) copyright Kroc Camen 2017, 2018
+// BSD 2-clause licence; see LICENSE.TXT
+
+//! Tests of documentation comments, and of the Markdown reference written
+//! of the symbols a module exports.
+
+mod common;
+
+use common::Build;
+
+#[test]
+fn doc_comments() {
+    //--------------------------------------------------------------------------
+    // documentation comments are kept with the definition that follows
+    let source = "``the accumulator
+atom    A
+
+```
+load the accumulator
+```
+``from memory
+macro   lda     \\1 {
+        byte    $AD
+        word    \\1
+}
+``the entry point
+:start
+``documents nothing
+        lda     $1234
+atom    X
+:end
+";
+    let asm = Build::new(source).assembler().unwrap();
+    let object = &asm.objects()[0];
+    assert_eq!(object.data(), [0xAD, 0x34, 0x12]);
+    assert_eq!(object.atoms["A"].doc(), Some("the accumulator"));
+    assert_eq!(object.atoms["X"].doc(), None);
+    assert_eq!(
+        object.macros["lda"].forms()[0].doc(),
+        Some("load the accumulator\nfrom memory")
+    );
+    let doc = |name| object.find_label(name).and_then(|(_, l)| l.doc.clone());
+    assert_eq!(doc("start"), Some("the entry point".to_string()));
+    assert_eq!(doc("end"), None);
+
+    // a record is not a definition, so documents nothing,
+    // not even the Label that follows
+    let asm = Build::new("``a point\nbyte, byte\n:origin\n")
+        .assembler()
+        .unwrap();
+    let object = &asm.objects()[0];
+    assert_eq!(object.find_label("origin").unwrap().1.doc, None);
+    // user-defined types cannot be defined yet, documented or not
+    assert_eq!(
+        Build::new("``a point\n%point  byte, byte\n").assemble(),
+        Err("User-defined type `%point` cannot be used yet at 2:1".into())
+    );
+}
+
+#[test]
+fn doc_markdown() {
+    //--------------------------------------------------------------------------
+    // a module's exports are documented in the order they were defined
+    let source = "export  { A, B, ld, :reset, :main }
+
+``the accumulator
+atom    A
+atom    B
+atom    C
+
+``load the accumulator
+macro   ld      A, \\1 {
+        byte    $3E, \\1
+}
+macro   ld      B, \\1 {
+        \\requires \"example\"
+        byte    $06, \\1
+}
+
+``restart the machine
+:reset
+        byte    0
+:main
+:hidden
+";
+    let asm = Build::new(source).assembler().unwrap();
+    assert_eq!(
+        retronym::doc::markdown("example", &asm.objects()[0], &[]),
+        "# example
+
+## Atoms
+
+* `A`
+
+  the accumulator
+
+* `B`
+
+## Labels
+
+* `:reset`
+
+  restart the machine
+
+* `:main`
+
+## Macros
+
+### `ld`
+
+* `ld A, \\1`
+
+  load the accumulator
+
+* `ld B, \\1` (requires `example`)
+"
+    );
+}
+
+#[test]
+fn doc_extends() {
+    //--------------------------------------------------------------------------
+    // a module's reference includes what it exports from those it imports,
+    // and the forms of the macros it extends, after its own
+    let asm = Build::new("::gb80\n").assembler().unwrap();
+    let (gb80, modules) = asm.objects()[..2].split_last().unwrap();
+    let doc = retronym::doc::markdown("gb80", gb80, modules);
+
+    // the Z80's registers are not exported by the LR35902
+    assert!(!doc.contains("IX"), "{}", doc);
+    let atoms = ["`HL+`", "`HL-`", "`A`"]
+        .iter()
+        .map(|a| doc.find(a).unwrap());
+    assert!(atoms.collect::<Vec<_>>().windows(2).all(|w| w[0] < w[1]));
+    let own = doc.find("* `ld A, (\\nn)`").unwrap();
+    let shared = doc.find("* `ld B, B`").unwrap();
+    assert!(own < shared, "{}", doc);
+    assert!(
+        doc.contains("### `nop`\n\n* `nop`\n\n  no operation\n"),
+        "{}",
+        doc
+    );
+    assert!(doc.contains(
+        "### `djnz`\n\n* `djnz \\1`\n\n  not available on the LR35902; an error\n"
+    ));
+}
+
+#[test]
+fn doc_bundled() {
+    //--------------------------------------------------------------------------
+    // every register & mnemonic of the bundled CPU modules is documented,
+    // on the first form of each macro
+    for name in ["c6502", "z80", "gb80"].iter() {
+        let asm = Build::new(&format!("::{}\n", name)).assembler().unwrap();
+        let objects = asm.objects();
+        let module = objects.iter().find(|o| o.name() == *name).unwrap();
+        for export in module.exports() {
+            let doc = match module.find_atom(objects, export) {
+                Some((_, atom)) => atom.doc(),
+                None => {
+                    let chain = module.find_macro(objects, export).unwrap();
+                    chain[0].1.forms()[0].doc()
+                }
+            };
+            assert!(
+                doc.is_some(),
+                "`{}` of `{}` is undocumented",
+                export,
+                name
+            );
+        }
+    }
+}
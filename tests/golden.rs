@@ -0,0 +1,461 @@
+// retronym (C) copyright Kroc Camen 2017, 2018
+// BSD 2-clause licence; see LICENSE.TXT
+
+//! Golden-file tests for the CPU modules. Each golden file lists every
+//! instruction form, one per line, followed by a comment giving its known
+//! encoding in hexadecimal, e.g. `ld A, B ; 78`.
+
+mod common;
+
+use common::Build;
+
+/// Stop reporting after this many failing instructions.
+const MAX_FAILURES: usize = 20;
+/// How many instructions to assemble at a time, when narrowing down failures.
+const CHUNK: usize = 32;
+
+/// Read the instructions and their expected bytes from a golden file.
+///
+fn cases(golden: &str) -> Vec<(&str, Vec<u8>)> {
+    //--------------------------------------------------------------------------
+    golden
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with(';'))
+        .map(|line| {
+            let (source, hex) = line.split_at(line.find(';').unwrap());
+            let bytes = hex[1..]
+                .split_whitespace()
+                .map(|b| u8::from_str_radix(b, 16).unwrap())
+                .collect();
+            (source.trim(), bytes)
+        })
+        .collect()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    //--------------------------------------------------------------------------
+    bytes
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Assemble the golden file using the module, with the given options
+/// enabled, and compare the output.
+///
+fn check(module: &str, golden: &str, options: &[&str]) {
+    //--------------------------------------------------------------------------
+    // assembling everything at once is quick; only if that fails do
+    // we narrow it down to find the culprits, as each assembly has to
+    // include the whole module
+    let cases = cases(golden);
+    if is_pass(module, &cases, options) {
+        return;
+    }
+
+    let failures: Vec<String> = cases
+        .chunks(CHUNK)
+        .filter(|chunk| !is_pass(module, chunk, options))
+        .flat_map(|chunk| chunk.iter())
+        .filter_map(|(source, bytes)| {
+            match Build::new(&format!("{}\n        {}\n", module, source))
+                .options(options)
+                .assemble()
+            {
+                Ok(ref b) if b == bytes => None,
+                Ok(b) => Some(format!(
+                    "{}: expected {}, got {}",
+                    source,
+                    hex(bytes),
+                    hex(&b)
+                )),
+                Err(e) => Some(format!("{}: {}", source, e)),
+            }
+        })
+        .take(MAX_FAILURES)
+        .collect();
+
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+/// Do the instructions assemble, together, into the expected bytes?
+///
+fn is_pass(module: &str, cases: &[(&str, Vec<u8>)], options: &[&str]) -> bool {
+    //--------------------------------------------------------------------------
+    let source = cases.iter().fold(String::from(module), |acc, (source, _)| {
+        format!("{}\n        {}", acc, source)
+    });
+    let expected: Vec<u8> =
+        cases.iter().flat_map(|(_, bytes)| bytes.clone()).collect();
+
+    Build::new(&format!("{}\n", source))
+        .options(options)
+        .assemble()
+        == Ok(expected)
+}
+
+#[test]
+fn z80() {
+    //--------------------------------------------------------------------------
+    check(
+        include_str!("../modules/z80.rym"),
+        include_str!("golden/z80.rym"),
+        &[],
+    );
+}
+
+#[test]
+fn z80_undocumented() {
+    //--------------------------------------------------------------------------
+    check(
+        include_str!("../modules/z80.rym"),
+        include_str!("golden/z80_undocumented.rym"),
+        &["z80-undocumented"],
+    );
+}
+
+#[test]
+fn z80_undocumented_requires_option() {
+    //--------------------------------------------------------------------------
+    // without opting in, an undocumented instruction is an error
+    for source in ["sll B", "ld A, IXH", "inc IYL"].iter() {
+        let error = Build::new(&format!(
+            "{}\n        {}\n",
+            include_str!("../modules/z80.rym"),
+            source
+        ))
+        .assemble()
+        .unwrap_err();
+        assert!(
+            error.starts_with(&format!(
+                "`{}` requires the option `z80-undocumented`",
+                source
+            )),
+            "{}",
+            error
+        );
+    }
+
+    // which `z80_undocumented` does, for the statements that follow
+    assert_eq!(
+        Build::new("::z80\n        z80_undocumented\n        sll B\n")
+            .assemble(),
+        Ok(vec![0xCB, 0x30])
+    );
+}
+
+#[test]
+fn z80_hints() {
+    //--------------------------------------------------------------------------
+    // register & shadow hints document a register's use,
+    // but do not change the assembly
+    let source = format!(
+        "{}\n{}\n",
+        include_str!("../modules/z80.rym"),
+        "        ld HL`width, $1234
+        ex AF, AF'
+        ex AF', AF
+        ld A'flags, B`count
+        exx
+        ld H'pointer, D'
+        ret NC`gun_loaded"
+    );
+    assert_eq!(
+        Build::new(&source).assemble(),
+        Ok(vec![0x21, 0x34, 0x12, 0x08, 0x08, 0x78, 0xD9, 0x62, 0xD0])
+    );
+}
+
+#[test]
+fn z80_displacement() {
+    //--------------------------------------------------------------------------
+    // a displacement of IX / IY is signed, so cannot be 128 or more, which
+    // would fit the byte, but address the memory before IX / IY instead
+    for source in [
+        "ld A, (IX + 128)",
+        "ld (IX + 255), A",
+        "ld A, (IX - 129)",
+        "bit 0, (IY + 128)",
+    ]
+    .iter()
+    {
+        let error = Build::new(&format!(
+            "{}\n        {}\n",
+            include_str!("../modules/z80.rym"),
+            source
+        ))
+        .assemble()
+        .unwrap_err();
+        assert!(
+            error.starts_with(
+                "an index displacement must be within -128 to +127"
+            ),
+            "{}",
+            error
+        );
+    }
+}
+
+#[test]
+fn shadow_hints_require_shadow() {
+    //--------------------------------------------------------------------------
+    // only registers with a shadow can be given a shadow hint
+    for (module, source, register) in [
+        (include_str!("../modules/z80.rym"), "ld IX', $1234", "IX"),
+        (include_str!("../modules/z80.rym"), "ld SP'stack, HL", "SP"),
+        (include_str!("../modules/gb80.rym"), "ld A', B", "A"),
+    ]
+    .iter()
+    {
+        let error = Build::new(&format!("{}\n        {}\n", module, source))
+            .assemble()
+            .unwrap_err();
+        assert!(
+            error.starts_with(&format!(
+                "`{}` does not have a shadow register",
+                register
+            )),
+            "{}",
+            error
+        );
+    }
+}
+
+#[test]
+fn z80_square_brackets() {
+    //--------------------------------------------------------------------------
+    // square brackets can be used for indirection, in place of parentheses
+    let source = format!(
+        "{}\n{}\n",
+        include_str!("../modules/z80.rym"),
+        "        ld A, [HL]
+        ld [IX + 5], $12
+        jp [HL]
+        ld HL, [$1234]"
+    );
+    assert_eq!(
+        Build::new(&source).assemble(),
+        Ok(vec![0x7E, 0xDD, 0x36, 0x05, 0x12, 0xE9, 0x2A, 0x34, 0x12])
+    );
+
+    // but are still distinct from no indirection
+    let error = Build::new(&format!("{}        ld A, HL\n", source))
+        .assemble()
+        .unwrap_err();
+    assert!(
+        error.starts_with("No matching form for `ld A, HL`"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn z80_shadow_check() {
+    //--------------------------------------------------------------------------
+    // hints that disagree with the swaps of `ex AF, AF'` / `exx` are warned
+    // of, when enabled; registers without a hint are not checked
+    let source = format!(
+        "{}\n{}\n",
+        include_str!("../modules/z80.rym"),
+        "        ld A'count, B
+        ex AF, AF'
+        ld A'count, B
+        ld A`count, B
+        ld A, B
+        exx
+        ld HL'pointer, $1234
+        ld HL`pointer, $1234
+        jr C, -2
+        exx
+        ex AF', AF
+        ld A, (HL'pointer)"
+    );
+    let options = ["z80-shadow-check"];
+    let asm = Build::new(&source).options(&options).assembler().unwrap();
+    let warnings: Vec<String> = asm
+        .warnings()
+        .iter()
+        .map(|w| w.to_string().split(" at ").next().unwrap().to_string())
+        .collect();
+    assert_eq!(
+        warnings,
+        [
+            "`A'count` is used, but the primary `A` is in play",
+            "`A`count` is used, but the shadow `A` is in play",
+            "`HL`pointer` is used, but the shadow `HL` is in play",
+            "`HL'pointer` is used, but the primary `HL` is in play",
+        ]
+    );
+
+    // without the option, hints are not checked
+    let asm = Build::new(&source).assembler().unwrap();
+    assert!(asm.warnings().is_empty());
+
+    // which `z80_shadow_check` enables, for the statements that follow
+    let source = "::z80
+        ld A'count, B
+        z80_shadow_check
+        ld A'count, B
+";
+    let asm = Build::new(source).assembler().unwrap();
+    let warnings: Vec<String> =
+        asm.warnings().iter().map(|w| w.to_string()).collect();
+    assert_eq!(
+        warnings,
+        ["`A'count` is used, but the primary `A` is in play at 4:12"]
+    );
+}
+
+#[test]
+fn c6502() {
+    //--------------------------------------------------------------------------
+    check(
+        include_str!("../modules/c6502.rym"),
+        include_str!("golden/c6502.rym"),
+        &[],
+    );
+}
+
+#[test]
+fn c6502_jmp_page_boundary() {
+    //--------------------------------------------------------------------------
+    // `jmp ($xxFF)` assembles, but with a warning
+    let source = format!(
+        "{}\n        jmp ($12FF)\n        jmp ($1234)\n",
+        include_str!("../modules/c6502.rym")
+    );
+    let asm = Build::new(&source).assembler().unwrap();
+    assert_eq!(
+        asm.objects()[0].data(),
+        [0x6C, 0xFF, 0x12, 0x6C, 0x34, 0x12]
+    );
+    assert_eq!(asm.warnings().len(), 1);
+}
+
+#[test]
+fn gb80() {
+    //--------------------------------------------------------------------------
+    check(
+        include_str!("../modules/gb80.rym"),
+        include_str!("golden/gb80.rym"),
+        &[],
+    );
+}
+
+#[test]
+fn gb80_z80_only() {
+    //--------------------------------------------------------------------------
+    // the Z80's instructions that the LR35902 lacks are reported as such
+    let module = include_str!("../modules/gb80.rym");
+    for source in ["djnz -2", "exx", "ex DE, HL", "im 1"].iter() {
+        let error = Build::new(&format!("{}\n        {}\n", module, source))
+            .assemble()
+            .unwrap_err();
+        assert!(
+            error.contains("is not available on the LR35902"),
+            "{}: {}",
+            source,
+            error
+        );
+    }
+    // as are its registers, which the LR35902 does not define
+    for (source, invocation) in [
+        ("ld A, (IX + 5)", "ld A, (IX + 5)"),
+        ("ld HL, ($1234)", "ld HL, (4660)"),
+        ("jp PO, $1234", "jp PO, 4660"),
+    ]
+    .iter()
+    {
+        let error = Build::new(&format!("{}\n        {}\n", module, source))
+            .assemble()
+            .unwrap_err();
+        assert!(
+            error
+                .starts_with(&format!("No matching form for `{}`", invocation)),
+            "{}: {}",
+            source,
+            error
+        );
+    }
+}
+
+#[test]
+fn c65c02() {
+    //--------------------------------------------------------------------------
+    check(
+        include_str!("../modules/c6502.rym"),
+        include_str!("golden/c65c02.rym"),
+        &["65c02"],
+    );
+}
+
+#[test]
+fn c65c02_requires_option() {
+    //--------------------------------------------------------------------------
+    // the 65C02's instructions are not available on the 6502
+    for source in ["phx", "bra -2", "inc A"].iter() {
+        let error = Build::new(&format!(
+            "{}\n        {}\n",
+            include_str!("../modules/c6502.rym"),
+            source
+        ))
+        .assemble()
+        .unwrap_err();
+        assert!(
+            error.starts_with(&format!(
+                "`{}` requires the option `65c02`",
+                source
+            )),
+            "{}",
+            error
+        );
+    }
+}
+
+#[test]
+fn c65816() {
+    //--------------------------------------------------------------------------
+    check(
+        include_str!("../modules/c6502.rym"),
+        include_str!("golden/c65816.rym"),
+        &["65c02", "65816"],
+    );
+}
+
+#[test]
+fn c65816_register_width() {
+    //--------------------------------------------------------------------------
+    // immediate values follow the width of the registers
+    // as given by `rep#` / `sep#`, or `a8`, `a16` &c.
+    let source = format!(
+        "{}\n{}\n",
+        include_str!("../modules/c6502.rym"),
+        "        cpu_65816
+        lda# $12
+        rep# $20
+        lda# $1234
+        ldx# $12
+        rep# $10
+        ldx# $1234
+        cpy# $1234
+        sep# $30
+        lda# $12
+        ldy# $12
+        a16
+        bit# $1234
+        i16
+        ldy# $1234
+        a8
+        and# $12"
+    );
+    assert_eq!(
+        Build::new(&source).assemble(),
+        Ok(vec![
+            0xA9, 0x12, 0xC2, 0x20, 0xA9, 0x34, 0x12, 0xA2, 0x12, 0xC2, 0x10,
+            0xA2, 0x34, 0x12, 0xC0, 0x34, 0x12, 0xE2, 0x30, 0xA9, 0x12, 0xA0,
+            0x12, 0x89, 0x34, 0x12, 0xA0, 0x34, 0x12, 0x29, 0x12
+        ])
+    );
+}
@@ -0,0 +1,291 @@
+// retronym (C) copyright Kroc Camen 2017, 2018
+// BSD 2-clause licence; see LICENSE.TXT
+
+//! Tests of segments and the linker: placing data, patching fixups, and
+//! relaxing branches to the form that reaches.
+
+mod common;
+
+use common::Build;
+
+#[test]
+fn segments() {
+    //--------------------------------------------------------------------------
+    let source = "::z80
+        segment code
+:main
+        jp      :main
+        ld      HL, :data + 1
+        segment data
+        align   $10
+:data
+        byte
+        1, 2
+:more
+        3
+";
+    let asm = Build::new(source).assembler().unwrap();
+    let object = asm.objects().last().unwrap();
+
+    let names: Vec<&str> = object.segments().iter().map(|s| s.name()).collect();
+    assert_eq!(names, ["default", "code", "data"]);
+
+    // addresses are left as zero until linked
+    let code = &object.segments()[1];
+    assert_eq!(code.to_bytes(), [0xC3, 0x00, 0x00, 0x21, 0x00, 0x00]);
+    let fixups: Vec<_> = code
+        .fixups()
+        .iter()
+        .map(|f| (f.offset, f.bits, f.value.to_string()))
+        .collect();
+    assert_eq!(
+        fixups,
+        [
+            (1, 16, ":main".to_string()),
+            (4, 16, ":data + 1".to_string())
+        ]
+    );
+
+    // a Label within a table names the data that follows
+    let data = &object.segments()[2];
+    assert_eq!(data.align(), 0x10);
+    assert_eq!(data.to_bytes(), [0x01, 0x02, 0x03]);
+    let labels: Vec<_> = data
+        .labels()
+        .iter()
+        .map(|l| (l.name.as_str(), l.offset))
+        .collect();
+    assert_eq!(labels, [("data", 0), ("more", 2)]);
+
+    assert_eq!(
+        Build::new(":a\n        byte\n:a\n").assemble().unwrap_err(),
+        "Label `:a` is already defined at 3:1"
+    );
+    // alignment must be a power of two
+    for (boundary, error) in [
+        ("0", "Alignment must be a power of two, but 0 given at 3:17"),
+        ("3", "Alignment must be a power of two, but 3 given at 3:17"),
+        (
+            "-4",
+            "Alignment must be a power of two, but -4 given at 3:17",
+        ),
+    ]
+    .iter()
+    {
+        let source =
+            format!("        byte\n        1\n        align   {}\n", boundary);
+        assert_eq!(Build::new(&source).assemble(), Err(error.to_string()));
+    }
+}
+
+#[test]
+fn linker() {
+    //--------------------------------------------------------------------------
+    let lib = "export  { :ret }
+:ret
+        byte
+        $C9
+";
+    let source = "::lib
+::z80
+        segment code
+:main
+        jp      :ret
+        jp      :main + 1
+        segment data
+        byte
+        :data
+:data
+";
+    // the segments of every file are placed together, by name
+    assert_eq!(
+        Build::new(source).modules(&[("lib", lib)]).link(),
+        Ok(vec![0xC9, 0xC3, 0x00, 0x00, 0xC3, 0x02, 0x00, 0x08])
+    );
+
+    assert_eq!(
+        Build::new("        byte\n        :nope\n").link(),
+        Err("Undefined label `:nope` at 2:9".to_string())
+    );
+    // a Label not exported cannot be used by other files
+    assert_eq!(
+        Build::new("::lib\n        byte\n        :ret\n")
+            .modules(&[("lib", ":ret\n")])
+            .link(),
+        Err("Undefined label `:ret` at 3:9".to_string())
+    );
+    assert_eq!(
+        Build::new("::lib\nexport  { :ret }\n:ret\n")
+            .modules(&[("lib", lib)])
+            .link(),
+        Err("Label `:ret` is exported by more than one file at 3:1".to_string())
+    );
+    assert_eq!(
+        Build::new(
+            "        byte\n        0\n        align $100\n:far\n        :far\n"
+        )
+        .link(),
+        Err("Value 256 does not fit in 8 bits at 5:9".to_string())
+    );
+    assert_eq!(
+        Build::new("export  { :nope }\n").assemble(),
+        Err("`:nope` is exported, but not defined at 1:11".to_string())
+    );
+}
+
+#[test]
+fn relaxation() {
+    //--------------------------------------------------------------------------
+    let zeroes = |n: usize| vec!["0"; n].join(", ");
+    let source = format!(
+        "::z80
+:start
+        jp      :near
+        jp      NZ, :far
+        djnz    :start
+:near
+        nop
+        byte
+        {}
+:far
+        nop
+",
+        zeroes(130)
+    );
+    // only the jump near enough takes the shorter form
+    let mut bytes = vec![0x18, 0x05, 0xC2, 0x8A, 0x00, 0x10, 0xF9, 0x00];
+    bytes.extend(vec![0; 131]);
+    assert_eq!(Build::new(&source).relax(), Ok((bytes, vec![(0, 3, 2)])));
+
+    // without relaxation, each jump keeps its natural form
+    let mut bytes = vec![0xC3, 0x08, 0x00, 0xC2, 0x8B, 0x00, 0x10, 0xF8, 0x00];
+    bytes.extend(vec![0; 131]);
+    assert_eq!(Build::new(&source).link(), Ok(bytes));
+
+    // lengthening the second jump puts the first out of reach
+    let source = format!(
+        "::z80
+        jp      :near
+        jp      :far
+        byte
+        {}
+:near
+        {}
+:far
+        nop
+",
+        zeroes(125),
+        zeroes(130)
+    );
+    let mut bytes = vec![0xC3, 0x83, 0x00, 0xC3, 0x05, 0x01];
+    bytes.extend(vec![0; 256]);
+    assert_eq!(Build::new(&source).relax(), Ok((bytes, vec![])));
+
+    // a 6502 branch out of reach branches over a jump instead
+    let source = format!(
+        "::c6502
+:top
+        bne     :top
+        beq     :end
+        byte
+        {}
+:end
+        bcc     :top
+",
+        zeroes(130)
+    );
+    let mut bytes = vec![0xD0, 0xFE, 0xD0, 0x03, 0x4C, 0x89, 0x00];
+    bytes.extend(vec![0; 130]);
+    bytes.extend(&[0xB0, 0x03, 0x4C, 0x00, 0x00]);
+    assert_eq!(
+        Build::new(&source).relax(),
+        Ok((bytes, vec![(0x02, 2, 5), (0x89, 2, 5)]))
+    );
+}
+
+#[test]
+fn branch_range() {
+    //--------------------------------------------------------------------------
+    let zeroes = |n: usize| vec!["0"; n].join(", ");
+    // the furthest a branch reaches, either way
+    let source = format!(
+        "::z80
+:back
+        byte
+        {}
+        jr      :back
+        jr      :ahead
+        byte
+        {}
+:ahead
+",
+        zeroes(126),
+        zeroes(127)
+    );
+    let mut bytes = vec![0; 126];
+    bytes.extend(&[0x18, 0x80, 0x18, 0x7F]);
+    bytes.extend(vec![0; 127]);
+    assert_eq!(Build::new(&source).link(), Ok(bytes));
+
+    // a byte further is an error, naming the branch and its target
+    let source = format!(
+        "::z80
+:start
+        djnz    :end
+        byte
+        {}
+:end
+",
+        zeroes(128)
+    );
+    assert_eq!(
+        Build::new(&source).link(),
+        Err("Branch at $0000 to `:end` ($0082) is 128 byte(s) away; \
+             it must be within -128 to +127 at 3:17
+    in expansion of macro `djnz`, invoked in `test` at 3:9"
+            .to_string())
+    );
+    let source = format!(
+        "::c6502
+:start
+        byte
+        {}
+        bcc     :start
+",
+        zeroes(127)
+    );
+    assert_eq!(
+        Build::new(&source).link(),
+        Err("Branch at $007F to `:start` ($0000) is -129 byte(s) away; \
+             it must be within -128 to +127 at 5:17
+    in expansion of macro `bcc`, invoked in `test` at 5:9"
+            .to_string())
+    );
+    // with relaxation, the branch takes a form that reaches instead
+    assert!(Build::new(&source).relax().is_ok());
+    // a distance given as a number is checked the same, even before zero
+    let source = "::z80
+        nop
+        jr      $0150
+        jr      0 - 200
+";
+    assert_eq!(
+        Build::new(source).link(),
+        Err("Branch at $0001 to `336` ($0153) is 336 byte(s) away; \
+             it must be within -128 to +127 at 3:17
+    in expansion of macro `jr`, invoked in `test` at 3:9"
+            .to_string())
+    );
+    let source = "::z80
+        jr      0 - 200
+";
+    assert_eq!(
+        Build::new(source).link(),
+        Err(
+            "Branch at $0000 to `0 - 200` (-$00C6) is -200 byte(s) away; \
+             it must be within -128 to +127 at 2:19
+    in expansion of macro `jr`, invoked in `test` at 2:9"
+                .to_string()
+        )
+    );
+}
@@ -0,0 +1,273 @@
+// retronym (C) copyright Kroc Camen 2017, 2018
+// BSD 2-clause licence; see LICENSE.TXT
+
+//! Tests of macros: expanding their forms, the directives that control
+//! expansion, and the errors given -- with the chain of invocations --
+//! when expansion fails.
+
+mod common;
+
+use common::Build;
+use retronym::error::ParseErrorKind;
+
+#[test]
+fn macro_shift() {
+    //--------------------------------------------------------------------------
+    // a macro that shifts takes any number of arguments,
+    // the body repeating until all are consumed
+    let source = "macro   bytes   \\1
+{
+        \\if \\string { \\end }
+        byte \\1
+        \\shift
+}
+        bytes   1, 2, 3
+        bytes   4
+        bytes   \"ab\", 5
+";
+    assert_eq!(Build::new(source).assemble(), Ok(vec![1, 2, 3, 4]));
+
+    // but at least as many as its parameters
+    let source = "macro   pairs   \\1, \\2
+{
+        byte \\2, \\1
+        \\shift
+        \\shift
+}
+        pairs   1, 2, 3, 4
+        pairs   5
+";
+    let error = Build::new(source).error();
+    assert!(matches!(
+        error.kind(),
+        ParseErrorKind::ArgumentCount {
+            expected: 2,
+            found: 1,
+            is_variadic: true,
+            ..
+        }
+    ));
+    assert_eq!(
+        error.to_string(),
+        "Macro `pairs` expects at least 2 argument(s), but 1 given at 8:9"
+    );
+
+    // a parameter is undefined once its argument is shifted away
+    let source =
+        "macro   rest    \\1 { \\shift byte \\1 }\n        rest    1\n";
+    let error = Build::new(source).error();
+    assert!(matches!(
+        error.kind(),
+        ParseErrorKind::UndefinedParam(ref p) if p == "1"
+    ));
+}
+
+#[test]
+fn macro_if_else() {
+    //--------------------------------------------------------------------------
+    // an `\else` can be followed by another `\if`
+    let source = "macro   sign    \\n
+{
+        \\if \\n >> 7 { byte $FF }
+        \\else \\if \\n { byte 1 }
+        \\else { byte 0 }
+}
+        sign    $80
+        sign    2
+        sign    0
+";
+    assert_eq!(Build::new(source).assemble(), Ok(vec![0xFF, 1, 0]));
+
+    // the test must be known when the macro is expanded
+    let source =
+        "macro   t       \\1 { \\if \\1 { byte 1 } }\n        t       :x\n";
+    let error = Build::new(source).error();
+    assert!(matches!(error.kind(), ParseErrorKind::NotStatic));
+    assert_eq!(
+        error.to_string(),
+        "Value cannot be calculated at this point at 1:26
+    in expansion of macro `t`, invoked in `test` at 2:9"
+    );
+
+    // an `\else` must follow the block of an `\if`
+    let source = "macro   t       \\1 { \\else { byte 1 } }\n";
+    let error = Build::new(source).error();
+    assert!(matches!(error.kind(), ParseErrorKind::Unexpected));
+}
+
+#[test]
+fn macro_argument_checks() {
+    //--------------------------------------------------------------------------
+    // `\atom`, `\number` and `\string` test the first argument
+    let source = "atom    A
+macro   kind    \\1
+{
+        \\if \\atom { byte 1 }
+        \\else \\if \\number { byte 2 }
+        \\else \\if \\string { byte 3 }
+        \\else { byte 4 }
+}
+        kind    A
+        kind    $10
+        kind    1 + 2
+        kind    \"x\"
+        kind    :x
+:x
+";
+    assert_eq!(Build::new(source).assemble(), Ok(vec![1, 2, 2, 3, 4]));
+}
+
+#[test]
+fn macro_recursion() {
+    //--------------------------------------------------------------------------
+    // a macro that invokes itself without end stops at the depth limit,
+    // the repeated steps of the backtrace given once
+    let source = "macro   loop    \\1 { loop \\1 }\n        loop    1\n";
+    let error = Build::new(source).error();
+    assert!(matches!(
+        error.kind(),
+        ParseErrorKind::Recursion(ref name) if name == "loop"
+    ));
+    assert_eq!(error.backtrace().len(), 64);
+    assert_eq!(
+        error.to_string(),
+        "Macro recursion too deep expanding `loop` at 1:22
+    in expansion of macro `loop`, invoked in `test` at 1:22 (and 62 more times)
+    in expansion of macro `loop`, invoked in `test` at 2:9"
+    );
+
+    // nesting within the limit is fine
+    let source = "macro   down    \\n
+{
+        \\if \\n { down \\n - 1 } \\else { byte $AA }
+}
+        down    62
+";
+    assert_eq!(Build::new(source).assemble(), Ok(vec![0xAA]));
+}
+
+#[test]
+fn backtraces() {
+    //--------------------------------------------------------------------------
+    // an error packing, or linking, a statement expanded from a macro gives
+    // the chain of invocations, each with the file it was invoked from
+    let lib = "export  { put, far }
+macro   put     \\1 { emit \\1 }
+macro   emit    \\1 { byte \\1 }
+macro   far     \\1 { byte rel \\1 }
+";
+    let modules = [("lib", lib)];
+    assert_eq!(
+        Build::new("::lib\n        put 300\n")
+            .modules(&modules)
+            .link(),
+        Err("Value 300 does not fit in 8 bits at 2:13
+    in expansion of macro `emit`, invoked in `lib.rym` at 2:22
+    in expansion of macro `put`, invoked in `test` at 2:9"
+            .to_string())
+    );
+    let source =
+        "::lib\n        far :end\n        byte\n        align $100\n:end\n";
+    assert_eq!(
+        Build::new(source).modules(&modules).link(),
+        Err("Branch at $0000 to `:end` ($0100) is 255 byte(s) away; \
+             it must be within -128 to +127 at 2:13
+    in expansion of macro `far`, invoked in `test` at 2:9"
+            .to_string())
+    );
+}
+
+#[test]
+fn macro_forms() {
+    //--------------------------------------------------------------------------
+    // a form with the same pattern as another could never be matched
+    let source = "macro   ld      A, \\1 { byte 1 }
+macro   ld      A, \\n { byte 2 }
+";
+    let error = Build::new(source).error();
+    assert!(matches!(error.kind(), ParseErrorKind::Duplicate));
+    assert_eq!(error.to_string(), "Duplicate at 2:9");
+
+    // nor can a parameter name be used twice
+    let error = Build::new("macro   put     \\a, \\a { byte 1 }\n").error();
+    assert!(matches!(error.kind(), ParseErrorKind::Duplicate));
+    assert_eq!(error.to_string(), "Duplicate at 1:21");
+
+    // the same pattern can depend upon an option,
+    // the first form whose option is enabled being used
+    let source = "macro   lda     \\1 { \\requires \"wide\" byte 2 }
+macro   lda     \\1 { byte 1 }
+        lda     0
+";
+    assert_eq!(Build::new(source).assemble(), Ok(vec![1]));
+    assert_eq!(
+        Build::new(source).options(&["wide"]).assemble(),
+        Ok(vec![2])
+    );
+
+    // square brackets match parentheses, unless the forms use both
+    let source = "macro   lda     (\\zp) { byte 1 }
+macro   lda     [\\zp] { byte 2 }
+macro   sta     (\\zp) { byte 3 }
+        lda     (0)
+        lda     [0]
+        sta     [0]
+";
+    assert_eq!(Build::new(source).assemble(), Ok(vec![1, 2, 3]));
+
+    // arguments that match no form name the invocation, listing the forms
+    // that begin the same way, or else take as many arguments
+    let source = "atom    A
+atom    B
+macro   ld      A, \\1 { byte 1 }
+macro   ld      A, B, \\1 { byte 2 }
+macro   ld      B, \\1, \\2 { byte 3 }
+        ld      B, 2
+";
+    let error = Build::new(source).error();
+    assert!(matches!(
+        error.kind(),
+        ParseErrorKind::NoMatchingForm { ref invocation, more: 0, .. }
+            if invocation == "ld B, 2"
+    ));
+    assert_eq!(
+        error.to_string(),
+        "No matching form for `ld B, 2` at 6:9
+    valid forms are:
+        ld B, \\1, \\2"
+    );
+    let error = Build::new("::z80\n        ld      (HL), SP\n").error();
+    assert_eq!(
+        error.to_string(),
+        "No matching form for `ld (HL), SP` at 2:9
+    valid forms are:
+        ld (HL), B
+        ld (HL), C
+        ld (HL), D
+        ld (HL), E
+        ld (HL), H
+        ld (HL), L
+        ld (HL), A
+        ld (HL), \\n"
+    );
+    // the list is cut short
+    let error = Build::new("::z80\n        ld      A, SP\n").error();
+    assert!(matches!(
+        error.kind(),
+        ParseErrorKind::NoMatchingForm { ref forms, more: 12, .. }
+            if forms.len() == 8
+    ));
+    assert!(error.to_string().ends_with("\n        ...and 12 more"));
+    // forms requiring an option not enabled are left out
+    let error = Build::new("::z80\n        sll     B, C\n").error();
+    assert_eq!(error.to_string(), "No matching form for `sll B, C` at 2:9");
+    let error = Build::new("::z80\n        sll     B, C\n")
+        .options(&["z80-undocumented"])
+        .error();
+    assert_eq!(
+        error.to_string(),
+        "No matching form for `sll B, C` at 2:9
+    valid forms are:
+        sll B"
+    );
+}
@@ -0,0 +1,533 @@
+// retronym (C) copyright Kroc Camen 2017, 2018
+// BSD 2-clause licence; see LICENSE.TXT
+
+//! Tests of memory maps: regions, banks, pins and presets, and packing
+//! segments into them.
+
+mod common;
+
+use common::Build;
+use retronym::assembler::Assembler;
+use retronym::map::PRESETS;
+
+#[test]
+fn memory_map() {
+    //--------------------------------------------------------------------------
+    let map = "region  ROM, $0000, $10
+ram     RAM, $C000, $10
+mirror  RAM, $E000
+slot    SLOT, $8000, 4
+banks   SLOT, 2
+fill    ROM, $FF
+fill    SLOT, $EE
+place   \"vars\", RAM
+";
+    let source = "::z80
+        jp      :count
+        segment vars
+:count
+        byte
+        0
+";
+    // RAM is not written to the output
+    let mut bytes = vec![0xC3, 0x00, 0xC0];
+    bytes.resize(0x10, 0xFF);
+    bytes.extend([0xEE; 8].iter());
+    assert_eq!(Build::new(source).map(map).link(), Ok(bytes));
+
+    // without a fill, the last region ends with its last byte placed
+    let map = "region  ROM, $0100, $100
+ram     RAM, $C000, 1
+place   \"vars\", RAM
+";
+    assert_eq!(
+        Build::new(source).map(map).link(),
+        Ok(vec![0xC3, 0x00, 0xC0])
+    );
+    let map = "region  ROM, $0000, 2
+region  RAM, $C000, 1
+place   \"vars\", RAM
+";
+    assert_eq!(
+        Build::new(source).map(map).link(),
+        Err("Segment `default` overflows `ROM` by 1 byte(s)".to_string())
+    );
+    assert_eq!(
+        Build::new(source)
+            .map("region  ROM, $0000, $10\nplace   \"vars\", RAM\n")
+            .link(),
+        Err("Undefined memory area `RAM` at 2:17".to_string())
+    );
+    // mistakes are reported as memory map statements, not as macros
+    let mistakes = [
+        (
+            "regoin  ROM, $0000, $10\n",
+            "Unknown memory map statement `regoin` at 1:1",
+        ),
+        (":start\n", "Unknown memory map statement `:start` at 1:1"),
+        (
+            "region  ROM, $0000\n",
+            "`region` is written `region <name>, <start>, <size>`, \
+             but 2 argument(s) given at 1:1",
+        ),
+        (
+            "region  \"ROM\", $0000, $10\n",
+            "`region` is written `region <name>, <start>, <size>` at 1:9",
+        ),
+        (
+            "region  ROM, $0000, $10\nplace   ROM, ROM\n",
+            "`place` is written `place \"<segment>\", <area>[, <bank>]` at 2:9",
+        ),
+        (
+            "region  ROM, $0000, $10\nbanks   ROM, 2\n",
+            "`ROM` is not a slot at 2:9",
+        ),
+        (
+            "region  ROM, $0000, $10\nplace   \"vars\", ROM, 1\n",
+            "`ROM` is not a slot at 2:17",
+        ),
+        (
+            "slot    S, $8000, 4\nmirror  S, $C000\n",
+            "`S` is not a region or RAM at 2:9",
+        ),
+        (
+            "region  ROM, $0000, $10\nram     ROM, $C000, $10\n",
+            "Area `ROM` is already defined at 2:9",
+        ),
+    ];
+    for &(map, error) in mistakes.iter() {
+        assert_eq!(Build::new(source).map(map).link(), Err(error.to_string()));
+    }
+
+    // memory cannot be given twice, but a slot can lie over other memory
+    let map = |map| Build::new("").map(map).link().err();
+    assert_eq!(
+        map("region  ROM, $0000, $100\nram     RAM, $00F0, $20\n"),
+        Some("Memory at $00F0 is given by both `ROM` and `RAM` at 2:1".into())
+    );
+    assert_eq!(
+        map("slot    A, $8000, $4000\nslot    B, $BFFF, 1\n"),
+        Some("Memory at $BFFF is given by both `A` and `B` at 2:1".into())
+    );
+    assert_eq!(
+        map("region  ROM, $0000, $100\nslot    A, $0000, $4000\n"),
+        None
+    );
+    // a mirror must not overlap other memory, nor be larger than its area
+    assert_eq!(
+        map("ram     RAM, $C000, $10\nmirror  RAM, $C008\n"),
+        Some(
+            "Memory at $C008 is given by both `RAM` and the mirror of `RAM` \
+             at 2:1"
+                .into()
+        )
+    );
+    assert_eq!(
+        map("ram     RAM, $C000, $10\nmirror  RAM, $E000\nram     X, $E00F, 1\n"),
+        Some(
+            "Memory at $E00F is given by both the mirror of `RAM` and `X` \
+             at 3:1"
+                .into()
+        )
+    );
+    assert_eq!(
+        map("ram     RAM, $C000, $10\nmirror  RAM, $E000, $11\n"),
+        Some(
+            "The mirror of `RAM` is larger than it by 1 byte(s) at 2:21".into()
+        )
+    );
+}
+
+#[test]
+fn banks() {
+    //--------------------------------------------------------------------------
+    let map = "region  ROM, $0000, 4
+slot    SLOT, $8000, 4
+banks   SLOT, 3
+fill    SLOT, $FF
+ram     RAM, $C000, 4
+place   \"level\", SLOT, 2
+place   \"a\", SLOT
+place   \"b\", SLOT
+place   \"vars\", RAM
+";
+    // after 4 bytes of ROM, the banks of 4 bytes are numbered from 1
+    let source = "::z80
+        ld      A, bank(:level)
+        segment level
+:level
+        byte
+        1, 2, bank(:level)
+";
+    assert_eq!(
+        Build::new(source).map(map).link(),
+        Ok(vec![
+            0x3E, 0x02, 0x00, 0x00, // ROM
+            0xFF, 0xFF, 0xFF, 0xFF, // bank 1
+            0x01, 0x02, 0x02, 0xFF, // bank 2
+            0xFF, 0xFF, 0xFF, 0xFF, // bank 3
+        ])
+    );
+    assert_eq!(
+        Build::new(source)
+            .map(&map.replace("SLOT, 2", "SLOT, 4"))
+            .link(),
+        Err("Bank 4 is not one of the banks of `SLOT` at 6:1".to_string())
+    );
+    // a slot without banks has nowhere to place a segment
+    assert_eq!(
+        Build::new(source)
+            .map(&map.replace("banks   SLOT, 3\n", ""))
+            .link(),
+        Err(
+            "Slot `SLOT` has no banks; give the number with `banks` at 5:1"
+                .to_string()
+        )
+    );
+
+    // a segment goes in the first bank with room for it
+    assert_eq!(
+        Build::new(
+            "::z80
+        segment a
+:a
+        jp      :a
+        segment b
+:b
+        jp      :b
+"
+        )
+        .map(map)
+        .link(),
+        Ok(vec![
+            0x00, 0x00, 0x00, 0x00, // ROM
+            0xC3, 0x00, 0x80, 0xFF, // bank 1
+            0xC3, 0x00, 0x80, 0xFF, // bank 2
+            0xFF, 0xFF, 0xFF, 0xFF, // bank 3
+        ])
+    );
+    assert_eq!(
+        Build::new(
+            "::z80
+        segment a
+        jp      :b
+        segment b
+:b
+        byte
+        1, 2
+"
+        )
+        .map(map)
+        .link(),
+        Err("Label `:b` lies in bank 2 but is used from bank 1; \
+             both are paged into `SLOT` at 3:17
+    in expansion of macro `jp`, invoked in `test` at 3:9"
+            .to_string())
+    );
+    assert_eq!(
+        Build::new(
+            "::z80
+        ld      A, bank(:count)
+        segment vars
+:count
+        byte
+        0
+"
+        )
+        .map(map)
+        .link(),
+        Err("Label `:count` does not lie in a bank of ROM at 2:20
+    in expansion of macro `ld`, invoked in `test` at 2:9"
+            .to_string())
+    );
+
+    // nothing is placed in a reserved bank, but it is still written
+    let reserved = map.replace("fill    SLOT", "reserve SLOT, 1\nfill    SLOT");
+    assert_eq!(
+        Build::new(
+            "::z80
+        segment a
+:a
+        jp      :a
+"
+        )
+        .map(&reserved)
+        .link(),
+        Ok(vec![
+            0x00, 0x00, 0x00, 0x00, // ROM
+            0xFF, 0xFF, 0xFF, 0xFF, // bank 1
+            0xC3, 0x00, 0x80, 0xFF, // bank 2
+            0xFF, 0xFF, 0xFF, 0xFF, // bank 3
+        ])
+    );
+    assert_eq!(
+        Build::new(source)
+            .map(&reserved.replace("SLOT, 2", "SLOT, 1"))
+            .link(),
+        Err("Bank 1 of `SLOT` is reserved at 7:1".to_string())
+    );
+    assert_eq!(
+        Build::new(source)
+            .map(&format!("{}pin     \"level\", 4\n", reserved))
+            .link(),
+        Err("Bank 1 of `SLOT` is reserved at 11:1".to_string())
+    );
+
+    // the banks of a slot begin on a whole bank, numbered from there
+    assert_eq!(
+        Build::new(source)
+            .map(&map.replace("ROM, $0000, 4", "ROM, $0000, 2"))
+            .link(),
+        Ok(vec![
+            0x3E, 0x02, 0xFF, 0xFF, // ROM, padded
+            0xFF, 0xFF, 0xFF, 0xFF, // bank 1
+            0x01, 0x02, 0x02, 0xFF, // bank 2
+            0xFF, 0xFF, 0xFF, 0xFF, // bank 3
+        ])
+    );
+}
+
+#[test]
+fn presets() {
+    //--------------------------------------------------------------------------
+    // every preset is a valid memory map
+    for (name, _) in PRESETS.iter() {
+        let mut asm = Assembler::default();
+        let file = asm.add_preset(name).unwrap();
+        assert_eq!(asm.add_map(file).map(|e| e.to_string()), None, "{}", name);
+    }
+    assert_eq!(Assembler::default().add_preset("c64"), None);
+
+    // a memory map read after a preset adds to it
+    let mut asm = Assembler::default();
+    asm.add_bundled();
+    let file = asm.add_source(
+        "test",
+        "::z80
+        ld      A, bank(:level)
+        segment level
+:level
+        byte
+        1
+",
+    );
+    assert_eq!(asm.assemble_file(file).map(|e| e.to_string()), None);
+    let file = asm.add_preset("sms").unwrap();
+    assert_eq!(asm.add_map(file).map(|e| e.to_string()), None);
+    let file =
+        asm.add_source("map", "banks   SLOT2, 2\nplace   \"level\", SLOT2\n");
+    assert_eq!(asm.add_map(file).map(|e| e.to_string()), None);
+
+    let image = asm.link().unwrap();
+    assert_eq!(image.data().len(), 0x10000);
+    assert_eq!(&image.data()[..3], &[0x3E, 0x02, 0xFF]);
+    assert_eq!(&image.data()[0x8000..0x8002], &[0x01, 0xFF]);
+    let level = image.symbols().iter().find(|s| s.name == "level").unwrap();
+    assert_eq!(
+        (level.address, level.load, level.bank),
+        (0x8000, Some(0x8000), Some(2))
+    );
+
+    // banks follow a program of any size on a whole bank
+    for (preset, slot, bank, load) in
+        [("cpc", "UPPER", 3, 0xC000), ("pcw", "SLOT2", 4, 0x10000)].iter()
+    {
+        let map = format!("banks   {0}, 1\nplace   \"level\", {0}\n", slot);
+        let mut asm = Assembler::default();
+        asm.add_bundled();
+        let file = asm.add_source(
+            "test",
+            "::z80
+        ld      A, bank(:level)
+        segment level
+:level
+        byte
+        1
+",
+        );
+        assert_eq!(asm.assemble_file(file).map(|e| e.to_string()), None);
+        let file = asm.add_preset(preset).unwrap();
+        assert_eq!(asm.add_map(file).map(|e| e.to_string()), None);
+        let file = asm.add_source("map", &map);
+        assert_eq!(asm.add_map(file).map(|e| e.to_string()), None);
+
+        let image = asm.link().unwrap();
+        assert_eq!(&image.data()[..2], &[0x3E, *bank as u8], "{}", preset);
+        assert_eq!(image.data()[*load], 0x01, "{}", preset);
+        let level = image.symbols().iter().find(|s| s.name == "level").unwrap();
+        assert_eq!((level.load, level.bank), (Some(*load), Some(*bank)));
+    }
+
+    // MBC1 cannot page in banks $20, $40 and $60, which MBC5 can
+    let source = "::z80\n        segment level\n        byte\n        1\n";
+    let map = "banks   ROMX, $21\nplace   \"level\", ROMX, $20\n";
+    for (preset, result) in [
+        ("gb-mbc5", None),
+        ("gb-mbc1", Some("Bank 32 of `ROMX` is reserved at 2:1")),
+    ]
+    .iter()
+    {
+        let mut asm = Assembler::default();
+        asm.add_bundled();
+        let file = asm.add_source("test", source);
+        assert_eq!(asm.assemble_file(file).map(|e| e.to_string()), None);
+        let file = asm.add_preset(preset).unwrap();
+        assert_eq!(asm.add_map(file).map(|e| e.to_string()), None);
+        let file = asm.add_source("map", map);
+        assert_eq!(asm.add_map(file).map(|e| e.to_string()), None);
+        assert_eq!(
+            asm.link().err().map(|e| e.to_string()),
+            result.map(str::to_string),
+            "{}",
+            preset
+        );
+    }
+}
+
+#[test]
+fn pins() {
+    //--------------------------------------------------------------------------
+    let map = "region  ROM, $0000, $10
+fill    ROM, $FF
+pin     \"b\", 8
+pin     :start, 2
+";
+    let source = "::z80
+:start
+        nop
+        segment b
+        byte
+        1, 2
+        segment c
+        byte
+        3, 4, 5, 6
+";
+    // the segment not pinned is placed around those pinned
+    assert_eq!(
+        Build::new(source).map(map).link(),
+        Ok(vec![
+            0xFF, 0xFF, 0x00, 0x03, 0x04, 0x05, 0x06, 0xFF, //
+            0x01, 0x02, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+        ])
+    );
+    let asm = Build::new(source).map(map).assembler().unwrap();
+    let gaps: Vec<(usize, usize)> = asm
+        .link()
+        .unwrap()
+        .gaps()
+        .iter()
+        .map(|g| (g.load, g.size))
+        .collect();
+    assert_eq!(gaps, vec![(0, 2), (7, 1), (10, 6)]);
+
+    assert_eq!(
+        Build::new(source)
+            .map(&map.replace("\"b\", 8", "\"b\", 2"))
+            .link(),
+        Err("Segment `default` overlaps `b` by 1 byte(s) at $2 at 4:1"
+            .to_string())
+    );
+    assert_eq!(
+        Build::new(source)
+            .map(&map.replace("\"b\", 8", "\"z\", 8"))
+            .link(),
+        Err("Undefined segment `z` at 3:1".to_string())
+    );
+    assert_eq!(
+        Build::new(source)
+            .map(&format!("{}pin     \"b\", 10\n", map))
+            .link(),
+        Err(
+            "Segment `b` is pinned at $A, but already pinned at $8 at 5:1"
+                .to_string()
+        )
+    );
+    assert_eq!(
+        Build::new(source)
+            .map(&map.replace("\"b\", 8", "\"b\", $20"))
+            .link(),
+        Err("Pinned location $20 lies outside of the memory map at 3:1"
+            .to_string())
+    );
+    assert_eq!(
+        Build::new(source)
+            .map(&map.replace("\"b\", 8", "\"default\", 0"))
+            .link(),
+        Err("Label `:start` is pinned at $2, but lies at $0 at 4:1".to_string())
+    );
+
+    // a pin must keep the segment on a multiple of its alignment
+    let aligned = source.replace("segment b\n", "segment b\n        align 4\n");
+    assert_eq!(
+        Build::new(&aligned)
+            .map(&map.replace("\"b\", 8", "\"b\", 10"))
+            .link(),
+        Err(
+            "Segment `b` must be aligned to 4 byte(s), but is pinned at $000A \
+             at 3:1"
+                .to_string()
+        )
+    );
+    let labelled = aligned.replace("1, 2\n", "1\n:two\n        2\n");
+    assert_eq!(
+        Build::new(&labelled)
+            .map(&map.replace("\"b\", 8", ":two, 8"))
+            .link(),
+        Err(
+            "Segment `b` must be aligned to 4 byte(s), but is pinned at $0007 \
+             at 3:1"
+                .to_string()
+        )
+    );
+    assert_eq!(
+        Build::new(&labelled)
+            .map(&map.replace("\"b\", 8", ":two, 9"))
+            .link()
+            .map(|data| data[8..10].to_vec()),
+        Ok(vec![0x01, 0x02])
+    );
+}
+
+#[test]
+fn packing() {
+    //--------------------------------------------------------------------------
+    let map = "region  ROM, $0000, $10
+pin     \"p\", 8
+";
+    let source = "::z80
+        segment a
+        byte
+        1, 1, 1, 1, 1, 1
+        segment b
+        byte
+        2, 2, 2, 2, 2, 2, 2, 2
+        segment p
+        byte
+        0, 0
+";
+    // each segment goes in the space it fits best,
+    // not the first space with room
+    let mut bytes = vec![2; 8];
+    bytes.extend(&[0, 0]);
+    bytes.extend(&[1; 6]);
+    assert_eq!(Build::new(source).map(map).link(), Ok(bytes));
+
+    let asm = Build::new(source).map(map).assembler().unwrap();
+    let image = asm.link().unwrap();
+    let fills: Vec<(&str, usize, usize)> = image
+        .fills()
+        .iter()
+        .map(|f| (f.area.as_str(), f.used, f.size))
+        .collect();
+    assert_eq!(fills, vec![("ROM", 16, 16)]);
+
+    assert_eq!(
+        Build::new(&format!(
+            "{}        segment c\n        byte\n        3\n",
+            source
+        ))
+        .map(map)
+        .link(),
+        Err("Segment `c` overflows `ROM` by 1 byte(s)".to_string())
+    );
+}
@@ -0,0 +1,284 @@
+// retronym (C) copyright Kroc Camen 2017, 2018
+// BSD 2-clause licence; see LICENSE.TXT
+
+//! Tests of modules: importing and exporting symbols, loading them from
+//! search paths, and reporting errors in the file they lie within.
+
+mod common;
+
+use common::Build;
+use retronym::assembler::Assembler;
+use retronym::error::ParseErrorKind;
+use retronym::module::SearchPath;
+use std::path::Path;
+
+#[test]
+fn modules() {
+    //--------------------------------------------------------------------------
+    // a module's exports can be used once imported,
+    // either as they are or qualified by the module's name
+    let mut path = SearchPath::default();
+    path.add_dir("modules").unwrap();
+    let source = "::z80
+        ld A, B
+        z80.ld A, C
+        ex AF, AF'
+";
+    let mut asm = Assembler::default();
+    let file = asm.add_source("test", source);
+    asm.add_search_path(&path);
+    assert!(asm.assemble_file(file).is_none());
+    // the module the `z80` module imports comes first
+    let names: Vec<_> = asm.objects().iter().map(|o| o.name()).collect();
+    assert_eq!(names, ["z80_base", "z80", ""]);
+    assert_eq!(asm.objects()[2].data(), [0x78, 0x79, 0x08]);
+}
+
+#[test]
+fn modules_export() {
+    //--------------------------------------------------------------------------
+    // only the symbols a module exports can be used, but its macros
+    // can use what the module does not export
+    let lib = "export  { put }
+macro   put     \\1 { emit \\1 }
+macro   emit    \\1 { byte \\1 }
+";
+    let modules = [("lib", lib)];
+    assert_eq!(
+        Build::new("::lib\n        put 1\n        lib.put 2\n")
+            .modules(&modules)
+            .assemble(),
+        Ok(vec![1, 2])
+    );
+    for (source, error) in [
+        ("        put 1", "Undefined macro `put`"),
+        ("::lib\n        emit 1", "Undefined macro `emit`"),
+        ("::lib\n        lib.emit 1", "Undefined macro `lib.emit`"),
+        ("::nope", "Undefined module `nope`"),
+        ("export  { emit }", "`emit` is exported, but not defined"),
+    ]
+    .iter()
+    {
+        let result = Build::new(&format!("{}\n", source))
+            .modules(&modules)
+            .assemble();
+        assert!(
+            result.as_ref().unwrap_err().starts_with(error),
+            "{:?}",
+            result
+        );
+    }
+
+    // a macro exported by two modules imported must be qualified
+    let other = "export  { put }\nmacro   put     \\1 { byte \\1, \\1 }\n";
+    let modules = [("lib", lib), ("other", other)];
+    let build = Build::new("::lib\n::other\n        put 1\n");
+    assert_eq!(
+        build.modules(&modules).assemble(),
+        Err(
+            "Macro `put` is exported by both `lib` and `other`; qualify it \
+             with the module's name, e.g. `lib.put` at 3:9"
+                .to_string()
+        )
+    );
+    assert_eq!(
+        Build::new("::lib\n::other\n        lib.put 1\n        other.put 2\n")
+            .modules(&modules)
+            .assemble(),
+        Ok(vec![1, 2, 2])
+    );
+    // whichever order they are imported in
+    let error = Build::new("::gb80\n::z80\n        ld ($1234), SP\n").error();
+    assert!(matches!(
+        error.kind(),
+        ParseErrorKind::AmbiguousMacro { name, modules }
+            if name == "ld" && modules == &["gb80", "z80"]
+    ));
+    assert_eq!(
+        Build::new("::z80\n::gb80\n        gb80.ld ($1234), SP\n").assemble(),
+        Ok(vec![0x08, 0x34, 0x12])
+    );
+}
+
+#[test]
+fn modules_extend() {
+    //--------------------------------------------------------------------------
+    // a module can export what it imports, and a macro defined with the name
+    // of one imported extends it, its own forms tried first; each form's
+    // body is expanded as seen from within the module that defines it
+    let base = "export  { A, put, emit }
+atom    A
+macro   put     A               { emit 1 }
+macro   put     \\n              { emit \\n }
+macro   emit    \\1              { byte \\1 }
+macro   secret  \\1              { byte \\1 }
+";
+    let ext = "::base
+export  { A, put, emit }
+macro   put     A               { secret 2 }
+macro   put     (A)             { byte 3 }
+macro   secret  \\1              { byte \\1, \\1 }
+";
+    let modules = [("base", base), ("ext", ext)];
+    assert_eq!(
+        Build::new("::ext\n        put A\n        put (A)\n        put 4\n")
+            .modules(&modules)
+            .assemble(),
+        Ok(vec![2, 2, 3, 4])
+    );
+    // no form of either matches; the forms of both are listed
+    let error = Build::new("::ext\n        put (4)\n")
+        .modules(&modules)
+        .error();
+    assert!(matches!(
+        error.kind(),
+        ParseErrorKind::NoMatchingForm { forms, .. } if forms.len() == 4
+    ));
+
+    // the same macro, exported by both, can be used unqualified;
+    // but not where one extends it
+    let build =
+        Build::new("::base\n::ext\n        emit 5\n        base.put A\n");
+    assert_eq!(build.modules(&modules).assemble(), Ok(vec![5, 1]));
+    let error = Build::new("::base\n::ext\n        put A\n")
+        .modules(&modules)
+        .error();
+    assert!(matches!(
+        error.kind(),
+        ParseErrorKind::AmbiguousMacro { name, modules }
+            if name == "put" && modules == &["base", "ext"]
+    ));
+
+    // a module cannot export what it neither defines nor imports
+    let error = Build::new("::base\nexport  { secret }\n")
+        .modules(&modules)
+        .assemble();
+    assert_eq!(
+        error,
+        Err("`secret` is exported, but not defined at 2:11".to_string())
+    );
+}
+
+#[test]
+fn modules_import_cycle() {
+    //--------------------------------------------------------------------------
+    let modules = [("a", "::b\n"), ("b", "::c\n"), ("c", "::a\n")];
+    let error = Build::new("::a\n")
+        .modules(&modules)
+        .assemble()
+        .unwrap_err();
+    assert!(
+        error.starts_with(
+            "Modules import each other in a cycle: `a` -> `b` -> `c` -> `a`"
+        ),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn modules_bundled() {
+    //--------------------------------------------------------------------------
+    // the bundled modules are found without a search path
+    let mut asm = Assembler::default();
+    let file = asm.add_source("test", "::c6502\n        lda $12\n");
+    asm.add_bundled();
+    assert!(asm.assemble_file(file).is_none());
+    assert_eq!(asm.objects().last().unwrap().data(), [0xA5, 0x12]);
+}
+
+#[test]
+fn modules_override() {
+    //--------------------------------------------------------------------------
+    // a directory's module is used instead of the bundled module
+    let mut path = SearchPath::default();
+    path.add_dir("tests/loader/lib").unwrap();
+    path.add_bundled();
+    let mut asm = Assembler::default();
+    asm.add_search_path(&path);
+    let file = asm.load("tests/loader/game.rym").unwrap();
+    assert!(asm.assemble_file(file).is_none());
+    assert_eq!(asm.objects().last().unwrap().data(), [0xFF]);
+
+    let mut asm = Assembler::default();
+    asm.add_bundled();
+    let file = asm.load("tests/loader/game.rym").unwrap();
+    assert!(asm.assemble_file(file).is_none());
+    assert_eq!(asm.objects().last().unwrap().data(), [0x78]);
+
+    // a module is read only when imported, so a directory can hold files
+    // that cannot be read as source code, e.g. "binary.rym"; errors within
+    // a module refer to its file by path
+    let mut path = SearchPath::default();
+    path.add_dir("tests/loader/lib").unwrap();
+    let mut asm = Assembler::default();
+    asm.add_search_path(&path);
+    let file = asm.add_source("test", "::broken\n");
+    let error = asm.assemble_file(file).unwrap();
+    let location = error.location().unwrap();
+    assert_eq!((location.line, location.col), (5, 9));
+    assert_eq!(
+        Path::new(asm.sources().name(location.span.file)),
+        Path::new("tests/loader/lib/broken.rym")
+    );
+    assert!(SearchPath::default()
+        .add_dir("tests/loader/game.rym")
+        .is_err());
+}
+
+#[test]
+fn source_map() {
+    //--------------------------------------------------------------------------
+    // errors refer to the file, and the span within, where they occurred
+    let mut asm = Assembler::default();
+    asm.add_module("lib", "atom    A\n        nope A\n");
+    let file = asm.add_source("game.rym", "::lib\n");
+    let error = asm.assemble_file(file).unwrap();
+    let location = error.location().unwrap();
+    assert_eq!((location.line, location.col), (2, 9));
+    assert_eq!(asm.sources().name(location.span.file), "lib.rym");
+    assert_eq!(asm.sources().snippet(location.span), "nope");
+    assert_ne!(location.span.file, file);
+}
+
+#[test]
+fn parse_errors() {
+    //--------------------------------------------------------------------------
+    // an error parsing the source code stops assembly,
+    // rather than packing the statements before it
+    let source = "::z80
+        ld      A, B
+        ld      A, C
+        }
+        ld      A, D
+";
+    let mut asm = Assembler::default();
+    asm.add_bundled();
+    let file = asm.add_source("test", source);
+    let error = asm.assemble_file(file).unwrap();
+    assert_eq!(error.to_string(), "Unexpected at 4:9");
+    assert!(asm.objects().iter().all(|o| o.name() == "z80"));
+
+    // as does one within a module imported
+    let modules = [("lib", "macro   put \\1 { byte \\1 }\n}\n")];
+    let error = Build::new("::lib\n        put 1\n")
+        .modules(&modules)
+        .assemble();
+    assert_eq!(error, Err("Unexpected at 2:1".to_string()));
+
+    // text that does not follow the grammar at all is an error, in the
+    // file it lies within, rather than a panic
+    let error = Build::new("@@@ !!\n        nop\n").error();
+    assert!(matches!(error.kind(), ParseErrorKind::Syntax(t) if t == "@@@ !!"));
+    assert_eq!(error.to_string(), "Syntax error in `@@@ !!` at 1:1");
+
+    let mut asm = Assembler::default();
+    asm.add_module("lib", "export  { put }\n        ld A, (2 + 3) * 2\n");
+    asm.add_bundled();
+    let file = asm.add_source("test", "::lib\n");
+    let error = asm.assemble_file(file).unwrap();
+    assert_eq!(error.to_string(), "Syntax error in `* 2` at 2:23");
+    let location = error.location().unwrap();
+    assert_eq!(asm.sources().name(location.span.file), "lib.rym");
+    assert_eq!(asm.sources().snippet(location.span), "* 2");
+}